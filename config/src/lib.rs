@@ -28,6 +28,94 @@ pub enum ShapingStrategy {
     Full,        // Map everything including site links and access points.
 }
 
+/// A source of latency samples for `qos_daemon`. Several may run at once; each is
+/// reported (and graphed) separately.
+///
+/// * `Pping` - passively times customer TCP connections with `pping`, watching the
+///   Internet-facing interface.
+/// * `Icmp` - actively pings every client's CPE addresses with `fping`.
+/// * `File` - reads `<ip> <rtt in ms>` lines, one sample per line, from a file or
+///   named pipe written by an external tool (for example an eBPF TCP RTT tracer).
+#[derive(Deserialize, Clone)]
+pub enum LatencySource {
+    /// Passive TCP round-trip times from `pping`.
+    Pping {
+        /// Path to the `pping` binary. Defaults to `/usr/local/bin/pping`.
+        #[serde(default = "default_pping_path")]
+        path: String,
+    },
+    /// Active ICMP probes of each client's CPE addresses.
+    Icmp {
+        /// Path to the `fping` binary. Defaults to `/usr/bin/fping`.
+        #[serde(default = "default_fping_path")]
+        path: String,
+        /// Pings sent to each address per sampling window. Defaults to 5.
+        #[serde(default = "default_icmp_count")]
+        count: u32,
+    },
+    /// Samples read from a file or named pipe.
+    File {
+        /// The file or pipe to read.
+        path: String,
+    },
+}
+
+impl LatencySource {
+    /// Short name for the source, used to tag its reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LatencySource::Pping { .. } => "pping",
+            LatencySource::Icmp { .. } => "icmp",
+            LatencySource::File { .. } => "file",
+        }
+    }
+}
+
+/// Settings for active reachability monitoring, in which `qos_daemon` pings the
+/// addresses of every client and infrastructure queue with `fping`.
+#[derive(Deserialize, Clone)]
+pub struct ReachabilityConfig {
+    /// Path to the `fping` binary. Defaults to `/usr/bin/fping`.
+    #[serde(default = "default_fping_path")]
+    pub path: String,
+
+    /// Seconds between rounds of probes. Defaults to 60.
+    #[serde(default = "default_reachability_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Pings sent to each address per round. Defaults to 3.
+    #[serde(default = "default_reachability_count")]
+    pub count: u32,
+
+    /// The most pings to send per second, across all addresses. Defaults to 500.
+    #[serde(default = "default_reachability_rate")]
+    pub max_pings_per_sec: u32,
+}
+
+fn default_reachability_interval_secs() -> u64 {
+    60
+}
+
+fn default_reachability_count() -> u32 {
+    3
+}
+
+fn default_reachability_rate() -> u32 {
+    500
+}
+
+fn default_pping_path() -> String {
+    "/usr/local/bin/pping".to_string()
+}
+
+fn default_fping_path() -> String {
+    "/usr/bin/fping".to_string()
+}
+
+fn default_icmp_count() -> u32 {
+    5
+}
+
 /// Defines the configuration to use. Saved in the file `/usr/local/etc/bracket_qos.ron`.
 /// *Note*: It is intended that a future release will change this.
 #[derive(Deserialize, Clone)]
@@ -82,6 +170,93 @@ pub struct QosConfig {
     /// load distribution, and prevents the shaper from wasting CPU time on management
     /// tasks. They *can* be run on the same box.
     pub controller_url: String,
+
+    /// The shared secret used to sign reports sent to `qos_manager`. It must match one
+    /// of the `bus_keys` in the manager's configuration, or the manager will reject
+    /// everything this shaper sends.
+    #[serde(default)]
+    pub bus_key: String,
+
+    /// Identifies this shaper to `qos_manager`, when more than one shaper reports to the
+    /// same manager. Letters, numbers, `-` and `_` only. Defaults to `default`.
+    #[serde(default = "default_shaper_id")]
+    pub shaper_id: String,
+
+    /// Directory in which reports are queued until the manager accepts them.
+    /// Defaults to `/var/spool/bracket_qos`.
+    #[serde(default = "default_spool_path")]
+    pub spool_path: String,
+
+    /// The most reports to keep in the spool. When it is full, the oldest are
+    /// discarded. Defaults to 10,000 (a few days of reports).
+    #[serde(default = "default_spool_max_reports")]
+    pub spool_max_reports: usize,
+
+    /// Address on which to serve Prometheus metrics (at `/metrics`), for
+    /// example `"0.0.0.0:9185"`. Not served if missing.
+    #[serde(default)]
+    pub metrics_listen: Option<String>,
+
+    /// Seconds between queue statistics reads. Defaults to 60; as low as 1 is
+    /// supported, for sites that need finer graphs.
+    #[serde(default = "default_stats_interval_secs")]
+    pub stats_interval_secs: u64,
+
+    /// Seconds between host (CPU and memory) checks. Defaults to 60.
+    #[serde(default = "default_host_interval_secs")]
+    pub host_interval_secs: u64,
+
+    /// Length, in seconds, of each latency sampling window. Defaults to 60.
+    #[serde(default = "default_latency_interval_secs")]
+    pub latency_interval_secs: u64,
+
+    /// Where latency samples come from (see `LatencySource`, above). Defaults to
+    /// `pping` alone.
+    #[serde(default = "default_latency_sources")]
+    pub latency_sources: Vec<LatencySource>,
+
+    /// Active reachability and packet-loss monitoring of clients. Off if missing.
+    #[serde(default)]
+    pub reachability: Option<ReachabilityConfig>,
+
+    /// Seconds between checks of UISP (and the manager's limits) for changes
+    /// that need the queues rebuilding. Defaults to 300.
+    #[serde(default = "default_update_interval_secs")]
+    pub update_interval_secs: u64,
+}
+
+fn default_shaper_id() -> String {
+    "default".to_string()
+}
+
+fn default_spool_path() -> String {
+    "/var/spool/bracket_qos".to_string()
+}
+
+fn default_spool_max_reports() -> usize {
+    10_000
+}
+
+fn default_stats_interval_secs() -> u64 {
+    60
+}
+
+fn default_host_interval_secs() -> u64 {
+    60
+}
+
+fn default_latency_interval_secs() -> u64 {
+    60
+}
+
+fn default_latency_sources() -> Vec<LatencySource> {
+    vec![LatencySource::Pping {
+        path: default_pping_path(),
+    }]
+}
+
+fn default_update_interval_secs() -> u64 {
+    300
 }
 
 /// Where the configuration file is saved
@@ -105,6 +280,15 @@ impl QosConfig {
         } else {
             cfg.nms_url = format!("{}/nms/api/v2.1", cfg.nms_url);
         }
+        // A zero interval would spin.
+        cfg.stats_interval_secs = cfg.stats_interval_secs.max(1);
+        cfg.host_interval_secs = cfg.host_interval_secs.max(1);
+        cfg.latency_interval_secs = cfg.latency_interval_secs.max(1);
+        cfg.update_interval_secs = cfg.update_interval_secs.max(1);
+        if let Some(reachability) = cfg.reachability.as_mut() {
+            reachability.interval_secs = reachability.interval_secs.max(1);
+            reachability.max_pings_per_sec = reachability.max_pings_per_sec.max(1);
+        }
         Ok(cfg)
     }
 }
@@ -126,6 +310,17 @@ impl Default for QosConfig {
             include_ip_ranges: Vec::new(),
             ignore_ip_ranges: Vec::new(),
             controller_url: String::new(),
+            bus_key: String::new(),
+            shaper_id: default_shaper_id(),
+            spool_path: default_spool_path(),
+            spool_max_reports: default_spool_max_reports(),
+            metrics_listen: None,
+            stats_interval_secs: default_stats_interval_secs(),
+            host_interval_secs: default_host_interval_secs(),
+            latency_interval_secs: default_latency_interval_secs(),
+            latency_sources: default_latency_sources(),
+            reachability: None,
+            update_interval_secs: default_update_interval_secs(),
         }
     }
 }
@@ -0,0 +1,126 @@
+//! Handles communication with `qos_manager`. Every request is signed with the
+//! `bus_key` from the configuration, so that the manager can reject traffic
+//! that didn't come from a shaper. Every request also carries the bus protocol
+//! version agreed with the manager by `hello`.
+
+mod spool;
+pub use spool::*;
+
+use anyhow::{Error, Result};
+use config::QosConfig;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use shared_rest::{
+    negotiate_protocol, sign_bus_message, Hello, HelloResponse, BUS_MIN_PROTOCOL_VERSION,
+    BUS_PROTOCOL_HEADER, BUS_PROTOCOL_VERSION, BUS_SHAPER_HEADER, BUS_SIGNATURE_HEADER,
+    BUS_TIMESTAMP_HEADER,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The bus protocol version agreed with the manager. Until `hello` succeeds,
+/// assume the manager speaks our newest version.
+static PROTOCOL: AtomicU32 = AtomicU32::new(BUS_PROTOCOL_VERSION);
+
+/// The bus protocol version agreed with the manager.
+pub fn negotiated_protocol() -> u32 {
+    PROTOCOL.load(Ordering::Relaxed)
+}
+
+/// Adds the protocol, shaper ID, timestamp and signature headers to a request.
+fn sign(request: RequestBuilder, config: &QosConfig, path: &str, body: &[u8]) -> RequestBuilder {
+    let timestamp = chrono::Utc::now().timestamp();
+    let signature = sign_bus_message(&config.bus_key, &config.shaper_id, timestamp, path, body);
+    request
+        .header(
+            BUS_PROTOCOL_HEADER,
+            PROTOCOL.load(Ordering::Relaxed).to_string(),
+        )
+        .header(BUS_SHAPER_HEADER, &config.shaper_id)
+        .header(BUS_TIMESTAMP_HEADER, timestamp.to_string())
+        .header(BUS_SIGNATURE_HEADER, signature)
+}
+
+/// Sends a signed, already-serialized JSON body to the manager. Only fails if
+/// the manager couldn't be reached; check the status for rejections.
+async fn post_body(config: &QosConfig, path: &str, body: Vec<u8>) -> Result<StatusCode> {
+    Ok(post_request(config, path, body).await?.status())
+}
+
+async fn post_request(config: &QosConfig, path: &str, body: Vec<u8>) -> Result<Response> {
+    let client = reqwest::Client::new();
+    let request = client
+        .post(format!("{}{path}", config.controller_url))
+        .header("Content-Type", "application/json");
+    Ok(sign(request, config, path, &body).body(body).send().await?)
+}
+
+/// Describes a rejection by the manager. Protocol mismatches are spelled out,
+/// since they mean that one side needs upgrading.
+fn rejection(path: &str, status: StatusCode) -> Error {
+    if status == StatusCode::UPGRADE_REQUIRED {
+        Error::msg(format!(
+            "Manager doesn't support bus protocol v{} ({path}). Upgrade qos_manager or qos_daemon so that they match.",
+            PROTOCOL.load(Ordering::Relaxed)
+        ))
+    } else {
+        Error::msg(format!("Manager rejected {path}: {status}"))
+    }
+}
+
+/// Introduces this daemon to the manager, and agrees the bus protocol version
+/// to use. Fails if the manager can't be reached, or if the two have no
+/// protocol version in common.
+pub async fn hello(config: &QosConfig) -> Result<u32> {
+    let hello = Hello {
+        daemon_version: crate::version::VERSION.to_string(),
+        protocol_version: BUS_PROTOCOL_VERSION,
+        min_protocol_version: BUS_MIN_PROTOCOL_VERSION,
+    };
+    let res = post_request(config, "/bus/hello", serde_json::to_vec(&hello)?).await?;
+    if !res.status().is_success() {
+        return Err(rejection("/bus/hello", res.status()));
+    }
+    let manager = res.json::<HelloResponse>().await?;
+    let version = negotiate_protocol(
+        (BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION),
+        (manager.min_protocol_version, manager.protocol_version),
+    )
+    .ok_or_else(|| {
+        Error::msg(format!(
+            "qos_manager {} speaks bus protocol v{}-v{}, but this daemon speaks v{}-v{}. Upgrade the older of the two.",
+            manager.manager_version,
+            manager.min_protocol_version,
+            manager.protocol_version,
+            BUS_MIN_PROTOCOL_VERSION,
+            BUS_PROTOCOL_VERSION
+        ))
+    })?;
+    PROTOCOL.store(version, Ordering::Relaxed);
+    Ok(version)
+}
+
+/// Sends a signed JSON report to the manager, at `path` (e.g. `/bus/tree`).
+pub async fn post_to_manager<T: Serialize>(
+    config: &QosConfig,
+    path: &str,
+    report: &T,
+) -> Result<()> {
+    let status = post_body(config, path, serde_json::to_vec(report)?).await?;
+    if !status.is_success() {
+        return Err(rejection(path, status));
+    }
+    Ok(())
+}
+
+/// Makes a signed GET request to the manager, at `path`, and deserializes the result.
+pub async fn get_from_manager<T: DeserializeOwned>(config: &QosConfig, path: &str) -> Result<T> {
+    let client = reqwest::Client::new();
+    let request = client
+        .get(format!("{}{path}", config.controller_url))
+        .header("Content-Type", "application/json");
+    let res = sign(request, config, path, &[]).send().await?;
+    if !res.status().is_success() {
+        return Err(rejection(path, res.status()));
+    }
+    Ok(res.json::<T>().await?)
+}
@@ -0,0 +1,192 @@
+//! Statistics reports are written to an on-disk spool, and sent to the manager
+//! in order by `run_spool`. If the manager can't be reached, reports wait in the
+//! spool (up to `spool_max_reports`) and are replayed when it comes back.
+
+use super::{hello, post_body};
+use crate::pretty::{display_error, display_success, display_warning};
+use anyhow::Result;
+use config::QosConfig;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Distinguishes reports spooled in the same millisecond.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Longest wait between retries, when the manager is unreachable.
+const MAX_BACKOFF_SECS: u64 = 300;
+
+/// A report the manager fails on (5xx) this many times is moved to the
+/// `failed` directory in the spool, so that it doesn't hold up the rest.
+const MAX_SERVER_ERRORS: u32 = 5;
+
+#[derive(Serialize, Deserialize)]
+struct SpooledReport {
+    path: String,
+    body: String,
+}
+
+/// Queues a report for the manager, at `path` (e.g. `/bus/bandwidth`).
+pub fn spool_report<T: Serialize>(config: &QosConfig, path: &str, report: &T) -> Result<()> {
+    let dir = Path::new(&config.spool_path);
+    std::fs::create_dir_all(dir)?;
+    let entry = SpooledReport {
+        path: path.to_string(),
+        body: serde_json::to_string(report)?,
+    };
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1_000_000;
+    let filename = dir.join(format!("{millis:016}-{sequence:06}.json"));
+
+    // Write then rename, so that a half-written report is never sent.
+    let temporary = filename.with_extension("tmp");
+    std::fs::write(&temporary, serde_json::to_vec(&entry)?)?;
+    std::fs::rename(&temporary, &filename)?;
+
+    let spooled = spooled_reports(dir)?;
+    if spooled.len() > config.spool_max_reports {
+        let excess = spooled.len() - config.spool_max_reports;
+        display_warning(
+            &format!("Report spool is full, discarding {excess} old report(s)"),
+            2,
+        );
+        for old in spooled.iter().take(excess) {
+            let _ = std::fs::remove_file(old);
+        }
+    }
+    Ok(())
+}
+
+/// Lists spooled reports, oldest first.
+fn spooled_reports(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut result: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map(|e| e == "json").unwrap_or(false))
+        .collect();
+    result.sort();
+    Ok(result)
+}
+
+enum SendResult {
+    Sent,
+    Retry,
+    /// The manager was reached, but failed to handle the report.
+    ServerError,
+    Discard,
+    Renegotiate,
+}
+
+async fn send_spooled(config: &QosConfig, filename: &Path) -> SendResult {
+    let entry: SpooledReport = match std::fs::read(filename)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+    {
+        Some(entry) => entry,
+        None => return SendResult::Discard,
+    };
+    match post_body(config, &entry.path, entry.body.into_bytes()).await {
+        Ok(status) if status.is_success() => SendResult::Sent,
+        Ok(StatusCode::UPGRADE_REQUIRED) => SendResult::Renegotiate,
+        // The manager understood the report and refused it, so sending it
+        // again won't help. A rejected signature (403) may just be a key
+        // that hasn't been updated yet, so keep those.
+        Ok(status) if status.is_client_error() && status.as_u16() != 403 => {
+            display_warning(&format!("Manager rejected {}: {status}", entry.path), 2);
+            SendResult::Discard
+        }
+        Ok(status) if status.is_server_error() => {
+            display_warning(&format!("Manager failed on {}: {status}", entry.path), 2);
+            SendResult::ServerError
+        }
+        Ok(_) | Err(_) => SendResult::Retry,
+    }
+}
+
+/// Moves a report that the manager keeps failing on out of the queue.
+fn move_to_failed(dir: &Path, filename: &Path) -> Result<()> {
+    let failed = dir.join("failed");
+    std::fs::create_dir_all(&failed)?;
+    if let Some(name) = filename.file_name() {
+        std::fs::rename(filename, failed.join(name))?;
+    }
+    Ok(())
+}
+
+/// Sends spooled reports to the manager, oldest first, forever. When the
+/// manager is unreachable, retries with exponential backoff.
+pub async fn run_spool(config: &QosConfig) {
+    let dir = Path::new(&config.spool_path);
+    let mut backoff = 1;
+    let mut server_errors: HashMap<PathBuf, u32> = HashMap::new();
+    loop {
+        let spooled = spooled_reports(dir).unwrap_or_default();
+        if spooled.is_empty() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        let mut retry = false;
+        for filename in spooled.iter() {
+            match send_spooled(config, filename).await {
+                SendResult::Sent | SendResult::Discard => {
+                    server_errors.remove(filename);
+                    let _ = std::fs::remove_file(filename);
+                }
+                SendResult::ServerError => {
+                    let errors = server_errors.entry(filename.clone()).or_insert(0);
+                    *errors += 1;
+                    if *errors < MAX_SERVER_ERRORS {
+                        retry = true;
+                        break;
+                    }
+                    server_errors.remove(filename);
+                    display_error(
+                        &format!(
+                            "Giving up on {} after {MAX_SERVER_ERRORS} manager errors",
+                            filename.display()
+                        ),
+                        2,
+                    );
+                    if let Err(e) = move_to_failed(dir, filename) {
+                        display_error(&e.to_string(), 2);
+                        let _ = std::fs::remove_file(filename);
+                    }
+                }
+                SendResult::Retry => {
+                    retry = true;
+                    break;
+                }
+                SendResult::Renegotiate => {
+                    // The manager was probably upgraded (or downgraded). Keep
+                    // the report, and agree a new protocol version.
+                    if let Err(e) = hello(config).await {
+                        display_error(&e.to_string(), 2);
+                    }
+                    retry = true;
+                    break;
+                }
+            }
+        }
+        if retry {
+            display_warning(
+                &format!(
+                    "Unable to reach the manager, {} report(s) spooled. Retrying in {backoff}s",
+                    spooled_reports(dir).map(|s| s.len()).unwrap_or(0)
+                ),
+                2,
+            );
+            tokio::time::sleep(Duration::from_secs(backoff)).await;
+            backoff = u64::min(backoff * 2, MAX_BACKOFF_SECS);
+        } else {
+            if backoff > 1 {
+                display_success("Spooled reports delivered", 2);
+            }
+            backoff = 1;
+        }
+    }
+}
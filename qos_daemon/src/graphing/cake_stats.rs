@@ -0,0 +1,134 @@
+use super::{counter_delta, rebuilt_since};
+use lazy_static::*;
+use parking_lot::RwLock;
+use shared_rest::{CakeLine, CakeReport, CakeTin};
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+
+/// The latest CAKE statistics for a queue, and the previous tin counters (so
+/// that reports can carry per-period counts).
+struct CakeStats {
+    handle: String,
+    time: SystemTime,
+    latest: CakeLine,
+    prior_tins: Vec<CakeTin>,
+    /// True if the counters were reset between the prior read and this one.
+    reset: bool,
+}
+
+lazy_static! {
+    static ref CAKE_DOWNLOAD: RwLock<HashMap<String, CakeStats>> = RwLock::new(HashMap::new());
+}
+
+lazy_static! {
+    static ref CAKE_UPLOAD: RwLock<HashMap<String, CakeStats>> = RwLock::new(HashMap::new());
+}
+
+fn read_u64(json: &serde_json::Value, key: &str) -> u64 {
+    json.get(key).and_then(|v| v.as_u64()).unwrap_or(0)
+}
+
+/// Reads the CAKE-specific statistics from one qdisc in `tc -j -s qdisc show`
+/// output. Qdiscs of other kinds are ignored.
+pub(crate) fn read_cake_stats(
+    q: &serde_json::Value,
+    is_download: bool,
+    map: &str,
+    time: SystemTime,
+) {
+    if q.get("kind").and_then(|k| k.as_str()) != Some("cake") {
+        return;
+    }
+    let tins = q
+        .get("tins")
+        .and_then(|t| t.as_array())
+        .map(|tins| {
+            tins.iter()
+                .map(|tin| CakeTin {
+                    sent_packets: read_u64(tin, "sent_packets"),
+                    sent_bytes: read_u64(tin, "sent_bytes"),
+                    drops: read_u64(tin, "drops"),
+                    ecn_marks: read_u64(tin, "ecn_mark"),
+                    ack_drops: read_u64(tin, "ack_drops"),
+                    backlog_bytes: read_u64(tin, "backlog_bytes"),
+                    peak_delay_us: read_u64(tin, "peak_delay_us"),
+                    avg_delay_us: read_u64(tin, "avg_delay_us"),
+                    base_delay_us: read_u64(tin, "base_delay_us"),
+                    sparse_flows: read_u64(tin, "sparse_flows"),
+                    bulk_flows: read_u64(tin, "bulk_flows"),
+                    unresponsive_flows: read_u64(tin, "unresponsive_flows"),
+                })
+                .collect::<Vec<CakeTin>>()
+        })
+        .unwrap_or_default();
+    let handle = q
+        .get("handle")
+        .and_then(|h| h.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let line = CakeLine {
+        site_id: map.to_string(),
+        backlog_bytes: read_u64(q, "backlog"),
+        memory_used: read_u64(q, "memory_used"),
+        tins,
+    };
+
+    let mut lock = if is_download {
+        CAKE_DOWNLOAD.write()
+    } else {
+        CAKE_UPLOAD.write()
+    };
+    if let Some(stats) = lock.get_mut(map) {
+        stats.reset = stats.handle != handle || rebuilt_since(stats.time);
+        stats.handle = handle;
+        stats.time = time;
+        stats.prior_tins = std::mem::replace(&mut stats.latest, line).tins;
+    } else {
+        lock.insert(
+            map.to_string(),
+            CakeStats {
+                handle,
+                time,
+                latest: line,
+                prior_tins: Vec::new(),
+                reset: false,
+            },
+        );
+    }
+}
+
+fn report_lines(stats: &HashMap<String, CakeStats>, since: SystemTime) -> Vec<CakeLine> {
+    stats
+        .values()
+        // Skip queues that weren't read this time, and first reads (which
+        // have nothing to compare against).
+        .filter(|s| s.time >= since && !s.prior_tins.is_empty())
+        .map(|s| {
+            let mut line = s.latest.clone();
+            for (tin, prior) in line.tins.iter_mut().zip(s.prior_tins.iter()) {
+                tin.sent_packets = counter_delta(tin.sent_packets, prior.sent_packets, s.reset);
+                tin.sent_bytes = counter_delta(tin.sent_bytes, prior.sent_bytes, s.reset);
+                tin.drops = counter_delta(tin.drops, prior.drops, s.reset);
+                tin.ecn_marks = counter_delta(tin.ecn_marks, prior.ecn_marks, s.reset);
+                tin.ack_drops = counter_delta(tin.ack_drops, prior.ack_drops, s.reset);
+            }
+            line
+        })
+        .collect()
+}
+
+/// Builds a report from the CAKE statistics read since `since`.
+pub fn cake_report(timestamp: String, since: SystemTime) -> CakeReport {
+    let mut report = CakeReport::new(timestamp);
+    report.download = report_lines(&CAKE_DOWNLOAD.read(), since);
+    report.upload = report_lines(&CAKE_UPLOAD.read(), since);
+    report
+}
+
+/// Forgets statistics for queues not in `keep`.
+pub(crate) fn prune_cake_stats(keep: &HashSet<String>) {
+    CAKE_DOWNLOAD.write().retain(|site, _| keep.contains(site));
+    CAKE_UPLOAD.write().retain(|site, _| keep.contains(site));
+}
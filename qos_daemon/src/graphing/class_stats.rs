@@ -0,0 +1,167 @@
+use super::{counter_delta, rebuilt_since, sample_seconds};
+use crate::shaper::TC_CMD;
+use anyhow::Result;
+use lazy_static::*;
+use parking_lot::RwLock;
+use shared_rest::{BandwidthLine, ClassCounters};
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+use tokio::process::Command;
+
+lazy_static! {
+    static ref HTB_QUEUE_TO_CLIENT_SITE: RwLock<HashMap<String, String>> =
+        RwLock::new(HashMap::new());
+}
+
+lazy_static! {
+    static ref HTB_DOWNLOAD: RwLock<HashMap<String, ClassStats>> = RwLock::new(HashMap::new());
+}
+
+lazy_static! {
+    static ref HTB_UPLOAD: RwLock<HashMap<String, ClassStats>> = RwLock::new(HashMap::new());
+}
+
+/// Maps a tower or access point's HTB class to its site, so that its class
+/// statistics can be reported.
+pub fn map_htb_queue_to_site(queue: (u32, u32), site: &str) {
+    let mut lock = HTB_QUEUE_TO_CLIENT_SITE.write();
+    lock.insert(super::tc_handle(queue), site.to_string());
+}
+
+/// The site mapped to an HTB class's handle.
+#[cfg(test)]
+pub(crate) fn htb_queue_site(handle: &str) -> Option<String> {
+    HTB_QUEUE_TO_CLIENT_SITE.read().get(handle).cloned()
+}
+
+#[derive(Clone, Copy, Default)]
+struct ClassSample {
+    time: Option<SystemTime>,
+    bytes: u64,
+    drops: u64,
+    overlimits: u64,
+    borrowed: u64,
+    lended: u64,
+}
+
+struct ClassStats {
+    current: ClassSample,
+    prior: ClassSample,
+}
+
+/// Reads a counter from a class in `tc -j -s class show` output. Depending
+/// upon the `iproute2` version, counters are either top-level or nested in
+/// `stats` / `xstats`.
+fn counter(class: &serde_json::Value, key: &str) -> u64 {
+    class
+        .get(key)
+        .or_else(|| class.get("stats").and_then(|s| s.get(key)))
+        .or_else(|| class.get("xstats").and_then(|s| s.get(key)))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+}
+
+/// Reads the HTB class counters for every mapped tower and access point on
+/// `interface`, with a single `tc` call.
+pub(crate) async fn class_stats(interface: &str, is_download: bool) -> Result<()> {
+    let out = Command::new(TC_CMD)
+        .arg("-j")
+        .arg("-s")
+        .arg("class")
+        .arg("show")
+        .arg("dev")
+        .arg(interface)
+        .output()
+        .await?;
+    let time = SystemTime::now();
+    let classes: Vec<serde_json::Value> = serde_json::from_slice(&out.stdout)?;
+
+    let map = HTB_QUEUE_TO_CLIENT_SITE.read();
+    let mut lock = if is_download {
+        HTB_DOWNLOAD.write()
+    } else {
+        HTB_UPLOAD.write()
+    };
+    for class in classes.iter() {
+        if class.get("class").and_then(|c| c.as_str()) != Some("htb") {
+            continue;
+        }
+        let site = match class
+            .get("handle")
+            .and_then(|h| h.as_str())
+            .and_then(|h| map.get(h))
+        {
+            Some(site) => site,
+            None => continue,
+        };
+        let sample = ClassSample {
+            time: Some(time),
+            bytes: counter(class, "bytes"),
+            drops: counter(class, "drops"),
+            overlimits: counter(class, "overlimits"),
+            borrowed: counter(class, "borrowed"),
+            lended: counter(class, "lended"),
+        };
+        if let Some(stats) = lock.get_mut(site) {
+            stats.prior = std::mem::replace(&mut stats.current, sample);
+        } else {
+            lock.insert(
+                site.clone(),
+                ClassStats {
+                    current: sample,
+                    prior: ClassSample::default(),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Forgets the HTB class mappings, before the queues are rebuilt.
+pub(crate) fn clear_htb_queue_mappings() {
+    HTB_QUEUE_TO_CLIENT_SITE.write().clear();
+}
+
+/// Forgets statistics for towers and access points not in `keep`.
+pub(crate) fn prune_class_stats(keep: &HashSet<String>) {
+    HTB_DOWNLOAD.write().retain(|site, _| keep.contains(site));
+    HTB_UPLOAD.write().retain(|site, _| keep.contains(site));
+}
+
+/// Bandwidth lines for every tower and access point read since `since`,
+/// that has a prior read to compare against.
+pub(crate) fn class_lines(is_download: bool, since: SystemTime) -> Vec<BandwidthLine> {
+    let lock = if is_download {
+        HTB_DOWNLOAD.read()
+    } else {
+        HTB_UPLOAD.read()
+    };
+    lock.iter()
+        .filter_map(|(site_id, stats)| {
+            let (current, prior) = (&stats.current, &stats.prior);
+            let (time, prior_time) = (current.time?, prior.time?);
+            if time < since {
+                return None;
+            }
+            let reset = current.bytes < prior.bytes || rebuilt_since(prior_time);
+            let seconds = sample_seconds(time, prior_time, reset);
+            if seconds <= 0.0 {
+                return None;
+            }
+            let delta = |current, prior| counter_delta(current, prior, reset);
+            let bytes = delta(current.bytes, prior.bytes) as f64;
+            Some(BandwidthLine {
+                site_id: site_id.clone(),
+                mbits_per_second: (bytes / seconds / 1_000_000.0) * 8.0,
+                drops: delta(current.drops, prior.drops),
+                class_stats: Some(ClassCounters {
+                    overlimits: delta(current.overlimits, prior.overlimits),
+                    borrowed: delta(current.borrowed, prior.borrowed),
+                    lended: delta(current.lended, prior.lended),
+                }),
+            })
+        })
+        .collect()
+}
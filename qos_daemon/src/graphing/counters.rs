@@ -0,0 +1,47 @@
+//! Turns `tc` counters into per-period figures. Every rebuild recreates the
+//! qdiscs and classes, resetting their counters to zero, so a counter that
+//! went backwards (or a queue rebuilt since its last read) has been counting
+//! since the rebuild rather than since the prior read.
+
+use lazy_static::*;
+use parking_lot::RwLock;
+use std::time::SystemTime;
+
+lazy_static! {
+    static ref REBUILT_AT: RwLock<Option<SystemTime>> = RwLock::new(None);
+}
+
+/// Call when the queues are about to be (re)built. Forgets the old handle
+/// mappings; the build maps the new ones.
+pub fn queues_rebuilding() {
+    *REBUILT_AT.write() = Some(SystemTime::now());
+    super::clear_queue_mappings();
+    super::clear_htb_queue_mappings();
+}
+
+/// True if the queues were rebuilt after `time`.
+pub(crate) fn rebuilt_since(time: SystemTime) -> bool {
+    matches!(*REBUILT_AT.read(), Some(rebuilt) if rebuilt > time)
+}
+
+/// A counter's change between two reads. After a reset, the whole current
+/// value is new.
+pub(crate) fn counter_delta(current: u64, prior: u64, reset: bool) -> u64 {
+    if reset || current < prior {
+        current
+    } else {
+        current - prior
+    }
+}
+
+/// Seconds covered by a sample read at `time`, with the prior read at
+/// `prior`. After a reset, the sample only covers the time since the rebuild.
+pub(crate) fn sample_seconds(time: SystemTime, prior: SystemTime, reset: bool) -> f64 {
+    let since = match *REBUILT_AT.read() {
+        Some(rebuilt) if reset && rebuilt > prior => rebuilt,
+        _ => prior,
+    };
+    time.duration_since(since)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
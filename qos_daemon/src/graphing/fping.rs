@@ -0,0 +1,46 @@
+use std::{collections::HashMap, net::Ipv4Addr, process::Stdio, str::FromStr};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// Pings each target `count` times with `fping`, waiting at least
+/// `interval_ms` between any two pings. Returns the replies for each target
+/// that `fping` reported on, with `None` for each lost ping.
+pub(crate) async fn fping(
+    path: &str,
+    count: u32,
+    interval_ms: u32,
+    targets: &[Ipv4Addr],
+) -> std::io::Result<HashMap<Ipv4Addr, Vec<Option<f32>>>> {
+    let mut child = Command::new(path)
+        .arg("-q")
+        .arg("-C")
+        .arg(count.max(1).to_string())
+        .arg("-i")
+        .arg(interval_ms.max(1).to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Targets go on stdin, so that large networks don't hit argument limits.
+    if let Some(mut stdin) = child.stdin.take() {
+        let list: String = targets.iter().map(|ip| format!("{ip}\n")).collect();
+        stdin.write_all(list.as_bytes()).await?;
+    }
+    // fping exits non-zero if any target is unreachable, so only the output matters.
+    let output = child.wait_with_output().await?;
+    Ok(String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(parse_line)
+        .collect())
+}
+
+/// Parses a line of `fping -C` output: `<ip> : <ms> <ms> - <ms>`, where `-`
+/// is a lost ping.
+fn parse_line(line: &str) -> Option<(Ipv4Addr, Vec<Option<f32>>)> {
+    let (ip, replies) = line.split_once(" : ")?;
+    let ip = Ipv4Addr::from_str(ip.trim()).ok()?;
+    let replies = replies
+        .split_whitespace()
+        .map(|rtt| rtt.parse::<f32>().ok())
+        .collect();
+    Some((ip, replies))
+}
@@ -1,16 +1,30 @@
-use crate::pretty::display_action;
+use crate::{bus::spool_report, pretty::display_action};
+use chrono::Utc;
 use config::QosConfig;
+use lazy_static::*;
+use parking_lot::RwLock;
 use shared_rest::SystemStatus;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use sysinfo::{ProcessorExt, SystemExt};
-use tokio::spawn;
+
+lazy_static! {
+    static ref LAST_HOST_STATUS: RwLock<Option<SystemStatus>> = RwLock::new(None);
+    static ref STARTED: Instant = Instant::now();
+}
+
+/// The most recent host check, if there has been one.
+pub fn last_host_status() -> Option<SystemStatus> {
+    LAST_HOST_STATUS.read().clone()
+}
 
 pub async fn gather_host_info(config: QosConfig) {
     use sysinfo::System;
+    // Host checks start with the daemon, so this is close enough to its start time.
+    lazy_static::initialize(&STARTED);
     let mut sys = System::new_all();
     sys.refresh_all();
     loop {
-        tokio::time::sleep(Duration::from_secs(60)).await;
+        tokio::time::sleep(Duration::from_secs(config.host_interval_secs)).await;
         display_action("Host Check", 2);
         sys.refresh_all();
 
@@ -19,22 +33,19 @@ pub async fn gather_host_info(config: QosConfig) {
             .iter()
             .map(|cpu| cpu.cpu_usage())
             .collect::<Vec<f32>>();
-        let report = SystemStatus::new(
+        let mut report = SystemStatus::new(
+            Utc::now().format("%+").to_string(),
             sys.total_memory(),
             sys.used_memory(),
             sys.total_swap(),
             sys.used_swap(),
             cpu_usage,
         );
-        // TODO: Send the report
-        spawn(send_report(
-            report,
-            format!("{}/bus/host", &config.controller_url),
-        ));
+        report.daemon_version = crate::version::VERSION.to_string();
+        report.uptime_secs = STARTED.elapsed().as_secs();
+        *LAST_HOST_STATUS.write() = Some(report.clone());
+        if let Err(e) = spool_report(&config, "/bus/host", &report) {
+            println!("{:?}", e);
+        }
     }
 }
-
-async fn send_report(report: SystemStatus, url: String) {
-    let client = reqwest::Client::new();
-    let _ = client.post(&url).json(&report).send().await;
-}
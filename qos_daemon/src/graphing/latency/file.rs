@@ -0,0 +1,61 @@
+use super::LatencyMap;
+use crate::pretty::{display_action, display_warning};
+use config::QosConfig;
+use std::{net::Ipv4Addr, str::FromStr, time::Duration};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Reads `<ip> <rtt in ms>` samples from a file or named pipe written by an
+/// external tool, reporting once per sampling window. Blank lines and lines
+/// starting with `#` are ignored.
+pub(crate) async fn run(config: &QosConfig, path: &str, mut latency_map: LatencyMap) {
+    let window = Duration::from_secs(config.latency_interval_secs);
+    loop {
+        // Opening a pipe waits for a writer.
+        let file = match tokio::fs::File::open(path).await {
+            Ok(file) => file,
+            Err(e) => {
+                display_warning(&format!("Unable to open {path}: {e}"), 2);
+                tokio::time::sleep(window).await;
+                continue;
+            }
+        };
+        let mut lines = BufReader::new(file).lines();
+        let mut ticker = tokio::time::interval(window);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    display_action("Latency File Result", 2);
+                    latency_map.send(config);
+                }
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        if let Some((ip, rtt)) = parse_line(&line) {
+                            latency_map.store(ip, rtt);
+                        }
+                    }
+                    // At the end of a file, or a pipe with no writer; wait for more.
+                    Ok(None) => tokio::time::sleep(Duration::from_secs(1)).await,
+                    Err(e) => {
+                        display_warning(&format!("Unable to read {path}: {e}"), 2);
+                        break;
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Ipv4Addr, f32)> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    let mut fields = line.split_whitespace();
+    let ip = Ipv4Addr::from_str(fields.next()?).ok()?;
+    let rtt = fields.next()?.parse::<f32>().ok()?;
+    if !rtt.is_finite() || rtt < 0.0 {
+        return None;
+    }
+    Some((ip, rtt))
+}
@@ -0,0 +1,37 @@
+use super::{mapped_ips, LatencyMap};
+use crate::{
+    graphing::fping,
+    pretty::{display_action, display_warning},
+};
+use config::QosConfig;
+use std::time::{Duration, Instant};
+
+/// Pings every mapped CPE address with `fping` once per sampling window,
+/// reporting after each round.
+pub(crate) async fn run(
+    config: &QosConfig,
+    path: &str,
+    count: u32,
+    mut latency_map: LatencyMap,
+) {
+    let window = Duration::from_secs(config.latency_interval_secs);
+    loop {
+        let started = Instant::now();
+        let targets = mapped_ips();
+        if !targets.is_empty() {
+            match fping(path, count, 1, &targets).await {
+                Ok(results) => {
+                    display_action("CPE Ping Result", 2);
+                    for (ip, replies) in results {
+                        for rtt in replies.into_iter().flatten() {
+                            latency_map.store(ip, rtt);
+                        }
+                    }
+                    latency_map.send(config);
+                }
+                Err(e) => display_warning(&format!("Unable to run {path}: {e}"), 2),
+            }
+        }
+        tokio::time::sleep(window.saturating_sub(started.elapsed())).await;
+    }
+}
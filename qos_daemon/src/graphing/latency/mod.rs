@@ -0,0 +1,166 @@
+use crate::{bus::spool_report, tree_builder::is_ip_relevant_no_igore};
+use config::{LatencySource, QosConfig};
+use lazy_static::*;
+use parking_lot::RwLock;
+use shared_rest::{LatencyReport, MinMaxAvg};
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv4Addr,
+    str::FromStr,
+};
+mod file;
+mod icmp;
+mod pping;
+
+lazy_static! {
+    static ref IP_TO_CLIENT_SITE: RwLock<HashMap<Ipv4Addr, String>> = RwLock::new(HashMap::new());
+}
+
+lazy_static! {
+    static ref UNMAPPED_IP: RwLock<HashSet<Ipv4Addr>> = RwLock::new(HashSet::new());
+}
+
+lazy_static! {
+    static ref LAST_SITE_LATENCY: RwLock<HashMap<(&'static str, String), f32>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Average latency (ms) per latency source and site, from the most recent
+/// report of each source.
+pub fn site_latencies() -> Vec<(&'static str, String, f32)> {
+    LAST_SITE_LATENCY
+        .read()
+        .iter()
+        .map(|((source, site), latency)| (*source, site.clone(), *latency))
+        .collect()
+}
+
+/// Every client address currently mapped to a site.
+fn mapped_ips() -> Vec<Ipv4Addr> {
+    IP_TO_CLIENT_SITE.read().keys().cloned().collect()
+}
+
+struct LatencyResult {
+    latency: f32,
+}
+
+struct LatencyMap {
+    source: &'static str,
+    latencies: HashMap<String, Vec<LatencyResult>>,
+}
+
+impl LatencyMap {
+    fn new(source: &LatencySource) -> Self {
+        Self {
+            source: source.name(),
+            latencies: HashMap::new(),
+        }
+    }
+
+    fn store_site(&mut self, site: &str, latency: f32) {
+        if let Some(l) = self.latencies.get_mut(site) {
+            l.push(LatencyResult { latency });
+        } else {
+            self.latencies
+                .insert(site.to_string(), vec![LatencyResult { latency }]);
+        }
+    }
+
+    /// Stores a sample for a single address, such as a probed CPE.
+    fn store(&mut self, ip: Ipv4Addr, latency: f32) {
+        let site = IP_TO_CLIENT_SITE.read().get(&ip).cloned();
+        if let Some(cs) = site {
+            self.store_site(&cs, latency);
+        } else if is_ip_relevant_no_igore(ip) {
+            UNMAPPED_IP.write().insert(ip);
+        }
+    }
+
+    /// Stores a sample for a flow between two addresses, attributing it to
+    /// whichever end is a client.
+    fn store_latency(&mut self, ip_1: Ipv4Addr, ip_2: Ipv4Addr, latency: f32) {
+        let (is_ip_1_local, is_ip_2_local) = {
+            let lock = IP_TO_CLIENT_SITE.read();
+            (lock.contains_key(&ip_1), lock.contains_key(&ip_2))
+        };
+
+        // Traffic between two clients doesn't tell us about either's Internet latency.
+        if is_ip_1_local && is_ip_2_local {
+            return;
+        }
+        if is_ip_1_local {
+            self.store(ip_1, latency);
+        } else if is_ip_2_local {
+            self.store(ip_2, latency);
+        } else {
+            if is_ip_relevant_no_igore(ip_1) {
+                UNMAPPED_IP.write().insert(ip_1);
+            }
+            if is_ip_relevant_no_igore(ip_2) {
+                UNMAPPED_IP.write().insert(ip_2);
+            }
+        }
+    }
+
+    fn to_latency_report(&mut self) -> LatencyReport {
+        let mut result = LatencyReport::new();
+        result.timestamp = chrono::Utc::now().format("%+").to_string();
+        result.source = self.source.to_string();
+        for (k, v) in self.latencies.iter() {
+            let samples: Vec<f32> = v.iter().map(|l| l.latency).collect();
+            if let Some(latency) = MinMaxAvg::from_samples(&samples) {
+                result.report(&k, latency);
+            }
+        }
+        {
+            let mut lock = LAST_SITE_LATENCY.write();
+            lock.retain(|(source, _), _| *source != self.source);
+            for item in result.items.iter() {
+                lock.insert((self.source, item.site.clone()), item.latency.average);
+            }
+        }
+        let mut lock = UNMAPPED_IP.write();
+        result.add_unmapped(&lock);
+        lock.clear();
+        self.latencies.clear();
+        result
+    }
+
+    /// Queues the report for the manager and starts a new sampling window.
+    fn send(&mut self, config: &QosConfig) {
+        let report = self.to_latency_report();
+        if let Err(e) = spool_report(config, "/bus/latency", &report) {
+            println!("{:?}", e);
+        }
+    }
+}
+
+pub fn map_ip_to_site(ip: &str, site: &str) {
+    //println!("Mapping {ip} to {site}");
+    if let Ok(ip) = Ipv4Addr::from_str(ip) {
+        let mut lock = IP_TO_CLIENT_SITE.write();
+        lock.insert(ip, site.to_string());
+    }
+}
+
+/// Runs every configured latency source concurrently, forever.
+pub async fn gather_latency(config: &QosConfig) {
+    let mut sources = Vec::new();
+    for source in config.latency_sources.iter() {
+        let config = config.clone();
+        let source = source.clone();
+        sources.push(tokio::spawn(async move {
+            let latency_map = LatencyMap::new(&source);
+            match source {
+                LatencySource::Pping { path } => pping::run(&config, &path, latency_map).await,
+                LatencySource::Icmp { path, count } => {
+                    icmp::run(&config, &path, count, latency_map).await
+                }
+                LatencySource::File { path } => file::run(&config, &path, latency_map).await,
+            }
+        }));
+    }
+    for source in sources {
+        let _ = source.await;
+    }
+}
@@ -0,0 +1,56 @@
+use super::LatencyMap;
+use crate::pretty::{display_action, display_warning};
+use config::QosConfig;
+use std::{net::Ipv4Addr, str::FromStr};
+use tokio::process::Command;
+
+/// Runs `pping` for one sampling window at a time, reporting after each.
+pub(crate) async fn run(config: &QosConfig, path: &str, mut latency_map: LatencyMap) {
+    loop {
+        let pping = Command::new(path)
+            .arg("-i")
+            .arg(&config.to_internet)
+            .arg("-s")
+            .arg(config.latency_interval_secs.to_string())
+            .arg("-m")
+            .output()
+            .await;
+        match pping {
+            Ok(pping) => {
+                display_action("Latency Check Result", 2);
+                let raw = String::from_utf8_lossy(&pping.stdout);
+                for (ip_1, ip_2, rtt) in raw.lines().filter_map(parse_line) {
+                    latency_map.store_latency(ip_1, ip_2, rtt);
+                }
+                latency_map.send(config);
+            }
+            Err(e) => {
+                display_warning(&format!("Unable to run {path}: {e}"), 2);
+                // Don't spin if pping is missing.
+                tokio::time::sleep(std::time::Duration::from_secs(config.latency_interval_secs))
+                    .await;
+            }
+        }
+    }
+}
+
+/// Parses a line of `pping -m` output:
+/// `<timestamp> <rtt> <min rtt> <bytes> <bytes> <bytes> <src ip>:<port>+<dst ip>:<port>`,
+/// with times in seconds. Returns the two addresses and the RTT in ms.
+fn parse_line(line: &str) -> Option<(Ipv4Addr, Ipv4Addr, f32)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 3 {
+        return None;
+    }
+    let rtt = fields[1].parse::<f32>().ok()?;
+    if !rtt.is_finite() || rtt < 0.0 {
+        return None;
+    }
+    let (src, dst) = fields[fields.len() - 1].split_once('+')?;
+    Some((endpoint_ip(src)?, endpoint_ip(dst)?, rtt * 1_000.0))
+}
+
+fn endpoint_ip(endpoint: &str) -> Option<Ipv4Addr> {
+    let (ip, _port) = endpoint.rsplit_once(':')?;
+    Ipv4Addr::from_str(ip).ok()
+}
@@ -1,6 +1,16 @@
 mod queue_stats;
 pub use queue_stats::*;
+mod cake_stats;
+pub use cake_stats::*;
+mod class_stats;
+pub use class_stats::*;
+mod counters;
+pub use counters::*;
 mod latency;
 pub use latency::*;
 mod host;
 pub use host::*;
+mod fping;
+pub(crate) use fping::*;
+mod reachability;
+pub use reachability::*;
@@ -1,4 +1,12 @@
-use crate::{pretty::display_action, shaper::TC_CMD};
+use super::{
+    cake_report, class_lines, class_stats, counter_delta, prune_cake_stats, prune_class_stats,
+    read_cake_stats, rebuilt_since, sample_seconds,
+};
+use crate::{
+    bus::{negotiated_protocol, spool_report},
+    pretty::display_action,
+    shaper::TC_CMD,
+};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use config::QosConfig;
@@ -6,28 +14,31 @@ use lazy_static::*;
 use parking_lot::RwLock;
 use shared_rest::{BandwidthLine, BandwidthReport};
 use std::{
-    collections::HashMap,
-    time::{Duration, Instant, SystemTime},
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
 };
-use tokio::{join, process::Command, spawn};
+use tokio::{join, process::Command};
 
 lazy_static! {
     static ref QUEUE_TO_CLIENT_SITE: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
 }
 
-lazy_static! {
-    static ref HTB_QUEUE_TO_CLIENT_SITE: RwLock<HashMap<String, String>> =
-        RwLock::new(HashMap::new());
+/// Formats a (major, minor) handle as the tree builder passes it to `tc`.
+/// `tc` reads the digits as hexadecimal and prints them back unchanged, so
+/// the text matches what `tc -s class show` reports.
+pub(crate) fn tc_handle(queue: (u32, u32)) -> String {
+    format!("{}:{}", queue.0, queue.1)
 }
 
 pub fn map_queue_to_site(queue: (u32, u32), site: &str) {
     let mut lock = QUEUE_TO_CLIENT_SITE.write();
-    lock.insert(format!("{}:{}", queue.0, queue.1), site.to_string());
+    lock.insert(tc_handle(queue), site.to_string());
 }
 
-pub fn map_htb_queue_to_site(queue: (u32, u32), site: &str) {
-    let mut lock = HTB_QUEUE_TO_CLIENT_SITE.write();
-    lock.insert(format!("{}:{}", queue.0, queue.1), site.to_string());
+/// The site mapped to a client queue's handle.
+#[cfg(test)]
+pub(crate) fn queue_site(handle: &str) -> Option<String> {
+    QUEUE_TO_CLIENT_SITE.read().get(handle).cloned()
 }
 
 lazy_static! {
@@ -38,12 +49,40 @@ lazy_static! {
     static ref UPLOAD: RwLock<HashMap<String, InterfaceStats>> = RwLock::new(HashMap::new());
 }
 
+/// Byte and drop counters for one queue, as last read from `tc`.
+pub struct QueueCounters {
+    pub queue: String,
+    pub bytes: u64,
+    pub drops: u64,
+}
+
+/// The latest counters for every mapped queue, in one direction.
+pub fn queue_counters(is_download: bool) -> Vec<QueueCounters> {
+    let lock = if is_download {
+        DOWNLOAD.read()
+    } else {
+        UPLOAD.read()
+    };
+    lock.iter()
+        .map(|(queue, stats)| QueueCounters {
+            queue: queue.clone(),
+            bytes: stats.bytes,
+            drops: stats.drops,
+        })
+        .collect()
+}
+
 struct InterfaceStats {
-    last_query: SystemTime,
+    /// The qdisc's own handle. It changes when the qdisc is recreated.
+    handle: String,
+    time: SystemTime,
     drops: u64,
     bytes: u64,
+    prior_time: Option<SystemTime>,
     prior_drops: u64,
     prior_bytes: u64,
+    /// True if the counters were reset between the prior read and this one.
+    reset: bool,
 }
 
 fn read_bytes_and_drops(
@@ -56,6 +95,11 @@ fn read_bytes_and_drops(
         if let Some(bytes_sent) = q.get("bytes") {
             let drops = drops.as_u64().unwrap_or(0);
             let bytes = bytes_sent.as_u64().unwrap_or(0);
+            let handle = q
+                .get("handle")
+                .and_then(|h| h.as_str())
+                .unwrap_or_default()
+                .to_string();
 
             let mut lock = if is_download {
                 DOWNLOAD.write()
@@ -63,32 +107,34 @@ fn read_bytes_and_drops(
                 UPLOAD.write()
             };
             if let Some(cs) = lock.get_mut(map) {
-                cs.last_query = new_time.clone();
+                cs.reset = cs.handle != handle || bytes < cs.bytes || rebuilt_since(cs.time);
+                cs.prior_time = Some(cs.time);
                 cs.prior_drops = cs.drops;
                 cs.prior_bytes = cs.bytes;
+                cs.handle = handle;
+                cs.time = *new_time;
                 cs.drops = drops;
                 cs.bytes = bytes;
             } else {
                 lock.insert(
                     map.to_string(),
                     InterfaceStats {
-                        last_query: new_time.clone(),
+                        handle,
+                        time: *new_time,
                         drops,
                         bytes,
+                        prior_time: None,
                         prior_bytes: 0,
                         prior_drops: 0,
+                        reset: false,
                     },
                 );
             }
-
-            //if bytes_sent > 0 {
-            //    println!("Mapped {} to {map} => drop:{}, packet:{}. bytes:{}", parent, drops, packets, bytes_sent);
-            //}
         }
     }
 }
 
-async fn queue_stats(interface: &str, is_download: bool, new_time: SystemTime) -> Result<()> {
+async fn queue_stats(interface: &str, is_download: bool) -> Result<()> {
     let out = Command::new(TC_CMD)
         .arg("-j")
         .arg("-s")
@@ -98,6 +144,8 @@ async fn queue_stats(interface: &str, is_download: bool, new_time: SystemTime) -
         .arg(interface)
         .output()
         .await?;
+    // Rates are calculated from when the counters were actually read.
+    let new_time = SystemTime::now();
 
     let raw_queue_stats = std::str::from_utf8(&out.stdout)?;
     let json: Vec<serde_json::Value> = serde_json::from_str(&format!("{raw_queue_stats}"))?;
@@ -107,6 +155,7 @@ async fn queue_stats(interface: &str, is_download: bool, new_time: SystemTime) -
             if let Some(parent) = parent.as_str() {
                 if let Some(map) = QUEUE_TO_CLIENT_SITE.read().get(parent) {
                     read_bytes_and_drops(q, is_download, &new_time, &map);
+                    read_cake_stats(q, is_download, map, new_time);
                 }
             }
         } else {
@@ -121,204 +170,168 @@ async fn queue_stats(interface: &str, is_download: bool, new_time: SystemTime) -
         }
     }
 
-    //println!("{:#?}", json);
     Ok(())
 }
 
-// Not using this anymore, because querying by class gave wildly innacurrate results and
-// hit the CPU far too hard. Preserving out of interest.
-/*#[deprecated]
-fn class_result(is_download: bool, new_time: &SystemTime, queue: &str, bytes: u64, drops: u64) {
-    if let Some(map) = HTB_QUEUE_TO_CLIENT_SITE.read().get(queue) {
-        let mut lock = if is_download {
-            DOWNLOAD.write()
-        } else {
-            UPLOAD.write()
-        };
-        if let Some(cs) = lock.get_mut(map) {
-            cs.last_query = new_time.clone();
-            cs.prior_drops = cs.drops;
-            cs.prior_bytes = cs.bytes;
-            cs.drops = drops;
-            cs.bytes = bytes;
-        } else {
-            lock.insert(
-                map.to_string(),
-                InterfaceStats {
-                    last_query: new_time.clone(),
-                    drops,
-                    bytes,
-                    prior_bytes: 0,
-                    prior_drops: 0,
-                },
-            );
-        }
-    }
-}*/
+/// Bandwidth lines for queues read since `since`, that have a prior read to
+/// compare against.
+fn rate_lines(stats: &HashMap<String, InterfaceStats>, since: SystemTime) -> Vec<BandwidthLine> {
+    stats
+        .iter()
+        .filter(|(_, stats)| stats.time >= since)
+        .filter_map(|(site_id, stats)| {
+            let seconds = sample_seconds(stats.time, stats.prior_time?, stats.reset);
+            if seconds <= 0.0 {
+                return None;
+            }
+            let bytes = counter_delta(stats.bytes, stats.prior_bytes, stats.reset) as f64;
+            Some(BandwidthLine {
+                site_id: site_id.clone(),
+                mbits_per_second: (bytes / seconds / 1_000_000.0) * 8.0,
+                drops: counter_delta(stats.drops, stats.prior_drops, stats.reset),
+                class_stats: None,
+            })
+        })
+        .collect()
+}
 
-/*async fn class_stats(interface: &str, is_download: bool, new_time: SystemTime) -> Result<()> {
-    //println!("Gathering for {interface}");
-    let out = Command::new(TC_CMD)
-        .arg("-s")
-        .arg("class")
-        .arg("show")
-        .arg("dev")
-        .arg(interface)
-        .output()
-        .await?;
+/// Forgets the qdisc handle mappings, before the queues are rebuilt.
+pub(crate) fn clear_queue_mappings() {
+    QUEUE_TO_CLIENT_SITE.write().clear();
+}
 
-    let raw_queue_stats = std::str::from_utf8(&out.stdout)?;
-    let lines: Vec<&str> = raw_queue_stats.split('\n').collect();
-    let mut current_queue = String::new();
-    for line in lines.iter() {
-        // Did we start a new one?
-        if line.starts_with("class htb") {
-            if let Some(idx) = line.find("parent") {
-                current_queue = line[10..idx].trim().to_string();
-            }
-        }
-        // If we have a queue, do we have some data?
-        if !current_queue.is_empty() && line.starts_with(" Sent") {
-            //println!("{line}");
-            let mut bytes = 0;
-            let mut dropped = 0;
-            if let Some(idx) = line.find("bytes") {
-                let bytes_str = &line[6..idx];
-                if let Ok(b) = bytes_str.trim().parse::<u64>() {
-                    bytes = b;
-                }
-            }
-            if let Some(dropped_idx) = line.find("dropped") {
-                if let Some(comma_idx) = line.find(',') {
-                    let dropped_str = &line[dropped_idx..comma_idx];
-                    if let Ok(d) = dropped_str.trim().parse::<u64>() {
-                        dropped = d;
-                    }
-                }
-            }
-            class_result(is_download, &new_time, &current_queue, bytes, dropped);
-        }
+/// Forgets statistics for queues that are no longer in the tree.
+fn prune_stats() {
+    let keep: HashSet<String> = crate::tree_builder::QUEUE_SUMMARY
+        .read()
+        .iter()
+        .map(|entry| entry.id.clone())
+        .chain(std::iter::once("root".to_string()))
+        .collect();
+    if keep.len() <= 1 {
+        // No tree yet.
+        return;
     }
-
-    Ok(())
-}*/
+    DOWNLOAD.write().retain(|site, _| keep.contains(site));
+    UPLOAD.write().retain(|site, _| keep.contains(site));
+    prune_cake_stats(&keep);
+    prune_class_stats(&keep);
+}
 
 pub async fn gather_interface_stats(config: &QosConfig) {
-    // Gather queue data every minute
-    let mut last_check: Option<std::time::Instant> = None;
+    // Gather queue data every `stats_interval_secs`. At sub-minute intervals,
+    // don't announce every poll.
+    let interval = Duration::from_secs(config.stats_interval_secs);
     loop {
-        tokio::time::sleep(Duration::from_secs(60)).await;
-        display_action("Polling Queue Counts", 2);
+        tokio::time::sleep(interval).await;
+        if config.stats_interval_secs >= 60 {
+            display_action("Polling Queue Counts", 2);
+        }
         let time = SystemTime::now();
-        let down_stats = queue_stats(&config.to_isp, true, time.clone());
-        let up_stats = queue_stats(&config.to_internet, false, time.clone());
-        //let down_class_stats = class_stats(&config.to_isp, true, time.clone());
-        //let up_class_stats = class_stats(&config.to_internet, false, time.clone());
-        //let _ = join!(down_stats, up_stats, down_class_stats, up_class_stats);
-        let _ = join!(down_stats, up_stats);
+        let down_stats = queue_stats(&config.to_isp, true);
+        let up_stats = queue_stats(&config.to_internet, false);
+        let down_class_stats = class_stats(&config.to_isp, true);
+        let up_class_stats = class_stats(&config.to_internet, false);
+        let _ = join!(down_stats, up_stats, down_class_stats, up_class_stats);
+        prune_stats();
 
         let time_formatter: DateTime<Utc> = time.into();
         let mut report = BandwidthReport::new(time_formatter.format("%+").to_string());
-        for (site_id, stats) in DOWNLOAD.read().iter() {
-            let bytes = if stats.bytes > stats.prior_bytes {
-                stats.bytes - stats.prior_bytes
-            } else {
-                0
-            } as f64;
-            let bytes_per_second = bytes / 60.0;
-            let mbits_per_second = (bytes_per_second / 1_000_000.0) * 8.0;
-
-            let drops = if stats.drops > stats.prior_drops {
-                stats.drops - stats.prior_drops
-            } else {
-                0
-            };
-
-            report.download.push(BandwidthLine {
-                site_id: site_id.clone(),
-                mbits_per_second,
-                drops,
-            });
+        report.download = rate_lines(&DOWNLOAD.read(), time);
+        report.upload = rate_lines(&UPLOAD.read(), time);
+        add_parent_bandwidth(&mut report, time);
+        if report.download.is_empty() && report.upload.is_empty() {
+            // Nothing to compare against yet.
+            continue;
         }
-        for (site_id, stats) in UPLOAD.read().iter() {
-            let bytes = if stats.bytes > stats.prior_bytes {
-                stats.bytes - stats.prior_bytes
-            } else {
-                0
-            } as f64;
-            let seconds_elapsed = if let Some(last_check) = last_check {
-                last_check.elapsed().as_secs_f64()
-            } else {
-                60.0
-            };
-            let bytes_per_second = bytes / seconds_elapsed;
-            let mbits_per_second = (bytes_per_second / 1_000_000.0) * 8.0;
-
-            let drops = if stats.drops > stats.prior_drops {
-                stats.drops - stats.prior_drops
-            } else {
-                0
-            };
-
-            report.upload.push(BandwidthLine {
-                site_id: site_id.clone(),
-                mbits_per_second,
-                drops,
-            });
+        if let Err(e) = spool_report(config, "/bus/bandwidth", &report) {
+            println!("{:?}", e);
         }
-        if last_check.is_some() {
-            // TODO: Walk trees upwards now we have all of the child numbers.
-            add_parent_bandwidth(&mut report);
-            spawn(send_report(
-                report,
-                format!("{}/bus/bandwidth", &config.controller_url),
-            ));
+        // Older managers don't accept CAKE reports.
+        if negotiated_protocol() >= 2 {
+            let cake = cake_report(report.timestamp.clone(), time);
+            if let Err(e) = spool_report(config, "/bus/cake", &cake) {
+                println!("{:?}", e);
+            }
         }
-        last_check = Some(Instant::now());
     }
 }
 
-fn add_parent_bandwidth(report: &mut BandwidthReport) {
+/// Adds tower and access point lines to the report. Measured HTB class
+/// statistics are used where available; any other parent gets the sum of
+/// the client queues beneath it.
+fn add_parent_bandwidth(report: &mut BandwidthReport, since: SystemTime) {
     let tree = crate::tree_builder::QUEUE_SUMMARY.read();
-    let mut add_dl = Vec::new();
-    let mut add_up = Vec::new();
-
-    for dl_host in report.download.iter() {
-        walk_queue_tree(&tree, dl_host, &mut add_dl);
-    }
-    for ul_host in report.upload.iter() {
-        walk_queue_tree(&tree, ul_host, &mut add_up);
-    }
-
-    report.download.extend_from_slice(&add_dl);
-    report.upload.extend_from_slice(&add_up);
+    let index: HashMap<&str, usize> = tree
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.id.as_str(), i))
+        .collect();
+    add_parents(
+        &tree,
+        &index,
+        &mut report.download,
+        class_lines(true, since),
+    );
+    add_parents(&tree, &index, &mut report.upload, class_lines(false, since));
 }
 
-fn walk_queue_tree(
+fn add_parents(
     tree: &[shared_rest::QueueTreeEntry],
-    line: &BandwidthLine,
-    target: &mut Vec<BandwidthLine>,
+    index: &HashMap<&str, usize>,
+    lines: &mut Vec<BandwidthLine>,
+    measured: Vec<BandwidthLine>,
 ) {
-    if let Some(tree_entry) = tree.iter().find(|qs| qs.id == line.site_id) {
-        let mut current = tree_entry.parent;
-        while current.is_some() {
-            let current_queue = &tree[current.unwrap()];
-            if let Some(bw) = target.iter_mut().find(|b| b.site_id == current_queue.id) {
-                bw.mbits_per_second += line.mbits_per_second;
-                bw.drops += line.drops;
-            } else {
-                target.push(BandwidthLine {
-                    site_id: current_queue.id.to_string(),
-                    mbits_per_second: line.mbits_per_second,
-                    drops: line.drops,
-                });
+    let reported: HashSet<String> = lines
+        .iter()
+        .chain(measured.iter())
+        .map(|line| line.site_id.clone())
+        .collect();
+    let mut summed: HashMap<String, BandwidthLine> = HashMap::new();
+    for line in lines.iter() {
+        let mut current = index
+            .get(line.site_id.as_str())
+            .and_then(|i| tree[*i].parent);
+        while let Some(parent) = current {
+            let parent_entry = &tree[parent];
+            if !reported.contains(&parent_entry.id) {
+                let sum = summed
+                    .entry(parent_entry.id.clone())
+                    .or_insert_with(|| BandwidthLine {
+                        site_id: parent_entry.id.clone(),
+                        mbits_per_second: 0.0,
+                        drops: 0,
+                        class_stats: None,
+                    });
+                sum.mbits_per_second += line.mbits_per_second;
+                sum.drops += line.drops;
             }
-            current = current_queue.parent;
+            current = parent_entry.parent;
         }
     }
+    lines.extend(measured);
+    lines.extend(summed.into_values());
 }
 
-async fn send_report(report: BandwidthReport, url: String) {
-    let client = reqwest::Client::new();
-    let _ = client.post(&url).json(&report).send().await;
+#[cfg(test)]
+mod test {
+    use super::tc_handle;
+
+    /// What `tc` prints for a handle given on its command line: it reads the
+    /// digits as hexadecimal, and prints them in hexadecimal.
+    fn tc_round_trip(handle: &str) -> String {
+        let (major, minor) = handle.split_once(':').unwrap();
+        let major = u32::from_str_radix(major, 16).unwrap();
+        let minor = u32::from_str_radix(minor, 16).unwrap();
+        format!("{major:x}:{minor:x}")
+    }
+
+    #[test]
+    fn handle_matches_tc_output() {
+        for (cpu_id, class_id) in [(1, 3), (1, 10), (12, 255), (16, 4096)] {
+            // As the tree builder passes it to `tc class add`.
+            let created = format!("{cpu_id}:{class_id}");
+            assert_eq!(tc_handle((cpu_id, class_id)), tc_round_trip(&created));
+        }
+    }
 }
@@ -0,0 +1,97 @@
+use crate::{
+    bus::{negotiated_protocol, spool_report},
+    graphing::fping,
+    pretty::{display_action, display_warning},
+    tree_builder::QUEUE_SUMMARY,
+};
+use chrono::Utc;
+use config::QosConfig;
+use shared_rest::{ReachabilityItem, ReachabilityReport};
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Periodically pings the addresses of every client and infrastructure queue,
+/// and reports which answered. Does nothing unless `reachability` is configured.
+pub async fn gather_reachability(config: &QosConfig) {
+    let settings = match &config.reachability {
+        Some(settings) => settings.clone(),
+        None => return,
+    };
+    let interval = Duration::from_secs(settings.interval_secs);
+    let spacing_ms = u32::max(1, 1000 / settings.max_pings_per_sec);
+    loop {
+        let started = Instant::now();
+        let targets = probe_targets();
+        if !targets.is_empty() {
+            let ips: Vec<Ipv4Addr> = targets.keys().cloned().collect();
+            match fping(&settings.path, settings.count, spacing_ms, &ips).await {
+                Ok(results) => {
+                    display_action("Reachability Check", 2);
+                    let report = ReachabilityReport {
+                        timestamp: Utc::now().format("%+").to_string(),
+                        items: summarize(&targets, &results),
+                    };
+                    // Older managers don't accept reachability reports.
+                    if negotiated_protocol() >= 3 {
+                        if let Err(e) = spool_report(config, "/bus/reachability", &report) {
+                            println!("{:?}", e);
+                        }
+                    }
+                }
+                Err(e) => display_warning(&format!("Unable to run {}: {e}", settings.path), 2),
+            }
+        }
+        tokio::time::sleep(interval.saturating_sub(started.elapsed())).await;
+    }
+}
+
+/// Maps each address of a client or infrastructure queue to its site.
+fn probe_targets() -> HashMap<Ipv4Addr, String> {
+    let mut targets = HashMap::new();
+    for entry in QUEUE_SUMMARY
+        .read()
+        .iter()
+        .filter(|e| e.level_type.is_client_queue())
+    {
+        for ip in entry.ip_addresses.iter() {
+            // Single hosts only; there's no sensible way to ping a subnet.
+            let ip = ip.strip_suffix("/32").unwrap_or(ip);
+            if let Ok(ip) = Ipv4Addr::from_str(ip) {
+                targets.insert(ip, entry.id.clone());
+            }
+        }
+    }
+    targets
+}
+
+/// Combines the per-address results into a line per site.
+fn summarize(
+    targets: &HashMap<Ipv4Addr, String>,
+    results: &HashMap<Ipv4Addr, Vec<Option<f32>>>,
+) -> Vec<ReachabilityItem> {
+    let mut sites: HashMap<&str, (u32, Vec<f32>)> = HashMap::new();
+    for (ip, site) in targets.iter() {
+        if let Some(replies) = results.get(ip) {
+            let entry = sites.entry(site).or_default();
+            entry.0 += replies.len() as u32;
+            entry.1.extend(replies.iter().flatten());
+        }
+    }
+    sites
+        .into_iter()
+        .map(|(site, (sent, rtts))| ReachabilityItem {
+            site: site.to_string(),
+            sent,
+            received: rtts.len() as u32,
+            rtt_ms: if rtts.is_empty() {
+                None
+            } else {
+                Some(rtts.iter().sum::<f32>() / rtts.len() as f32)
+            },
+        })
+        .collect()
+}
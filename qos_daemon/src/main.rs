@@ -3,7 +3,9 @@
 //! UISP is periodically polled, and if the configuration has changed the tree is rebuilt.
 
 use anyhow::Result;
+use shared_rest::{QueueTreeEntry, RebuildTrigger};
 use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 use tree_builder::QueueTree;
 mod pretty;
 mod shaper;
@@ -13,6 +15,9 @@ use pretty::*;
 use shaper::{get_limit_hash, update_limits};
 use tokio::join;
 mod graphing;
+mod bus;
+mod metrics;
+mod rebuild;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -37,6 +42,14 @@ async fn main() -> Result<()> {
     // Request these from the manager process via a REST request.
     // We're ignoring any errors, so the system will start correctly
     // even if the manager isn't running. Don't worry, we'll keep trying.
+    // Introduce ourselves to the manager, and agree on a protocol version.
+    // Like fetching limits, this may fail if the manager isn't running.
+    display_action("Contacting Manager", 1);
+    match bus::hello(&config).await {
+        Ok(version) => display_success(&format!("Using bus protocol v{version}"), 2),
+        Err(e) => display_error(&e.to_string(), 2),
+    }
+
     display_action("Fetching Limits from Controller", 1);
     let _ = update_limits(&config).await; // Ignoring error
 
@@ -51,40 +64,39 @@ async fn main() -> Result<()> {
         QueueTree::from_last_known_good()?
     };
 
-    // Perform the basic XDP/XPS setup.
-    display_action("XPS Interface Setup", 1);
-    shaper::setup_xdp(&config).await?;
-
-    // Clear all existing QoS config.
-    display_action("Clearing QoS Config", 1);
-    shaper::clear_queue_settings(&config).await?;
-
-    // Sets up master multiqueue modes and master interface queues.
-    // Copied from LibreQOS.
-    display_action("Setting Interface Queues", 1);
-    shaper::set_master_multiqueues(&config).await?;
-    let queue_count = shaper::count_queues(&config).await?;
-    shaper::set_master_interface_queues(&config, &queue_count).await?;
-
-    // Build the actual queues
-    display_action("Building Initial Queues", 1);
+    // Set up the interfaces and build the actual queues.
     let plan_hash = queue_plan.make_hash(); // Hash the queue list for change detection
-    shaper::build_client_queues(&config, queue_plan).await?;
+    let tree = rebuild::rebuild_queues(&config, queue_plan, vec![RebuildTrigger::Startup], &[])
+        .await?;
 
     // Create a Future for each long-running task:
     // * Checking UISP for updates.
     // * Polling interface statistics
     // * Polling latency gathering
     // * Host information
+    // * Probing client reachability
+    // * Sending spooled reports to the manager
+    // * Serving Prometheus metrics
     //
     // Then join! on them to run them concurrently. They are designed to run
     // forever...
     display_action("Polling for Changes & Graph Updates", 1);
-    let updater = check_for_updates(plan_hash, &config);
+    let updater = check_for_updates(plan_hash, tree, &config);
     let interface_poller = graphing::gather_interface_stats(&config);
     let latency = graphing::gather_latency(&config);
     let host_info = graphing::gather_host_info(config.clone());
-    let _ = join!(updater, interface_poller, latency, host_info);
+    let reachability = graphing::gather_reachability(&config);
+    let spool = bus::run_spool(&config);
+    let metrics_server = metrics::run_metrics_server(&config);
+    let _ = join!(
+        updater,
+        interface_poller,
+        latency,
+        host_info,
+        reachability,
+        spool,
+        metrics_server
+    );
 
     // So we never actually get here unless things have gone wrong.
     Ok(())
@@ -118,13 +130,26 @@ async fn build_plan(config: &config::QosConfig) -> Result<tree_builder::QueueTre
 
 /// Periodically re-downloads the queue tree from UISP (without applying it). Hash it,
 /// and compare the hash to the previous version. If it has changed, then we tear down
-/// the queues and re-apply the new scheme - notifying the host.
-async fn check_for_updates(previous_hash: String, config: &config::QosConfig) -> Result<()> {
+/// the queues and re-apply the new scheme - notifying the host. A `SIGHUP` checks at
+/// once, and rebuilds even if nothing has changed.
+async fn check_for_updates(
+    previous_hash: String,
+    previous_tree: Vec<QueueTreeEntry>,
+    config: &config::QosConfig,
+) -> Result<()> {
     let mut last_hash = previous_hash;
     let mut last_limit = get_limit_hash();
+    let mut last_tree = previous_tree;
+    let mut hangup = signal(SignalKind::hangup())?;
     loop {
-        // Wait for 5 minutes
-        tokio::time::sleep(Duration::from_secs(300)).await;
+        // Wait for `update_interval_secs` (5 minutes by default), or a SIGHUP
+        let forced = tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.update_interval_secs)) => false,
+            _ = hangup.recv() => {
+                display_action("Rebuild Requested", 1);
+                true
+            }
+        };
 
         // Try to build a new plan
         let queue_plan = build_plan(config).await;
@@ -138,24 +163,23 @@ async fn check_for_updates(previous_hash: String, config: &config::QosConfig) ->
             // TODO: Update site config and also rebuild if it changed
             let plan_hash = queue_plan.make_hash();
             let limit_hash = get_limit_hash();
-            if plan_hash == last_hash && last_limit == limit_hash {
+            let mut triggers = Vec::new();
+            if plan_hash != last_hash {
+                triggers.push(RebuildTrigger::TopologyChanged);
+            }
+            if limit_hash != last_limit {
+                triggers.push(RebuildTrigger::LimitsChanged);
+            }
+            if forced {
+                triggers.push(RebuildTrigger::Forced);
+            }
+            if triggers.is_empty() {
                 display_action("No Changes Detected", 1);
             } else {
                 last_limit = limit_hash;
-                let queue_count = shaper::count_queues(&config).await?;
                 last_hash = plan_hash;
-                display_action("XPS Interface Setup", 1);
-                shaper::setup_xdp(&config).await?;
-
-                display_action("Clearing QoS Config", 1);
-                shaper::clear_queue_settings(&config).await?;
-
-                display_action("Setting Interface Queues", 1);
-                shaper::set_master_multiqueues(&config).await?;
-                shaper::set_master_interface_queues(&config, &queue_count).await?;
-
-                display_action("Building Initial Queues", 1);
-                shaper::build_client_queues(&config, queue_plan).await?;
+                last_tree =
+                    rebuild::rebuild_queues(config, queue_plan, triggers, &last_tree).await?;
             }
         }
     }
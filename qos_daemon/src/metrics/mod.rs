@@ -0,0 +1,183 @@
+//! Serves Prometheus metrics at `/metrics`, if `metrics_listen` is set in
+//! the configuration.
+
+use crate::{
+    graphing::{last_host_status, queue_counters, site_latencies},
+    pretty::{display_error, display_success},
+};
+use config::QosConfig;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use shared_rest::{MetricType, PrometheusText};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    process::ExitStatus,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+static REBUILDS: AtomicU64 = AtomicU64::new(0);
+static LAST_REBUILD_MILLIS: AtomicU64 = AtomicU64::new(0);
+static TC_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Records a completed queue build (initial or rebuild).
+pub fn record_rebuild(duration: Duration) {
+    REBUILDS.fetch_add(1, Ordering::Relaxed);
+    LAST_REBUILD_MILLIS.store(duration.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Counts a `tc` command that exited unsuccessfully.
+pub fn record_tc_status(status: &ExitStatus) {
+    if !status.success() {
+        TC_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn render() -> String {
+    let mut page = PrometheusText::new();
+
+    page.family(
+        "bracket_qos_queue_bytes_total",
+        "Bytes sent by each queue.",
+        MetricType::Counter,
+    );
+    for (direction, is_download) in [("download", true), ("upload", false)] {
+        for q in queue_counters(is_download) {
+            page.sample(
+                "bracket_qos_queue_bytes_total",
+                &[("queue", &q.queue), ("direction", direction)],
+                q.bytes as f64,
+            );
+        }
+    }
+    page.family(
+        "bracket_qos_queue_drops_total",
+        "Packets dropped by each queue.",
+        MetricType::Counter,
+    );
+    for (direction, is_download) in [("download", true), ("upload", false)] {
+        for q in queue_counters(is_download) {
+            page.sample(
+                "bracket_qos_queue_drops_total",
+                &[("queue", &q.queue), ("direction", direction)],
+                q.drops as f64,
+            );
+        }
+    }
+
+    page.family(
+        "bracket_qos_site_latency_milliseconds",
+        "Average round-trip time per site, from each latency source's last report.",
+        MetricType::Gauge,
+    );
+    for (source, site, latency) in site_latencies() {
+        page.sample(
+            "bracket_qos_site_latency_milliseconds",
+            &[("site", &site), ("source", source)],
+            latency as f64,
+        );
+    }
+
+    if let Some(host) = last_host_status() {
+        page.family(
+            "bracket_qos_cpu_usage_percent",
+            "CPU use, per CPU.",
+            MetricType::Gauge,
+        );
+        for (cpu, usage) in host.cpu_usage.iter().enumerate() {
+            page.sample(
+                "bracket_qos_cpu_usage_percent",
+                &[("cpu", &cpu.to_string())],
+                *usage as f64,
+            );
+        }
+        // sysinfo reports memory in kilobytes.
+        page.single(
+            "bracket_qos_memory_total_bytes",
+            "Installed memory.",
+            MetricType::Gauge,
+            host.total_memory as f64 * 1024.0,
+        )
+        .single(
+            "bracket_qos_memory_used_bytes",
+            "Memory in use.",
+            MetricType::Gauge,
+            host.used_memory as f64 * 1024.0,
+        )
+        .single(
+            "bracket_qos_swap_total_bytes",
+            "Installed swap.",
+            MetricType::Gauge,
+            host.total_swap as f64 * 1024.0,
+        )
+        .single(
+            "bracket_qos_swap_used_bytes",
+            "Swap in use.",
+            MetricType::Gauge,
+            host.used_swap as f64 * 1024.0,
+        );
+    }
+
+    page.single(
+        "bracket_qos_rebuilds_total",
+        "Queue builds, including the initial build.",
+        MetricType::Counter,
+        REBUILDS.load(Ordering::Relaxed) as f64,
+    )
+    .single(
+        "bracket_qos_last_rebuild_duration_seconds",
+        "How long the most recent queue build took.",
+        MetricType::Gauge,
+        LAST_REBUILD_MILLIS.load(Ordering::Relaxed) as f64 / 1000.0,
+    )
+    .single(
+        "bracket_qos_tc_failures_total",
+        "tc commands that exited with an error.",
+        MetricType::Counter,
+        TC_FAILURES.load(Ordering::Relaxed) as f64,
+    );
+
+    page.finish()
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = if request.uri().path() == "/metrics" {
+        Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(render()))
+    } else {
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+    };
+    Ok(response.unwrap_or_default())
+}
+
+/// Serves `/metrics` forever. Returns immediately if `metrics_listen` isn't
+/// set, or isn't a valid address.
+pub async fn run_metrics_server(config: &QosConfig) {
+    let listen = match &config.metrics_listen {
+        Some(listen) => listen,
+        None => return,
+    };
+    let addr: SocketAddr = match listen.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            display_error(&format!("Invalid metrics_listen '{listen}': {e}"), 2);
+            return;
+        }
+    };
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    match Server::try_bind(&addr) {
+        Ok(server) => {
+            display_success(&format!("Serving metrics on {addr}"), 2);
+            if let Err(e) = server.serve(make_service).await {
+                display_error(&format!("Metrics server failed: {e}"), 2);
+            }
+        }
+        Err(e) => display_error(&format!("Unable to serve metrics on {addr}: {e}"), 2),
+    }
+}
@@ -0,0 +1,76 @@
+//! Tears down and rebuilds the shaping queues, recording each rebuild as an
+//! event for the manager.
+
+use crate::{
+    bus::{negotiated_protocol, spool_report},
+    metrics,
+    pretty::*,
+    shaper,
+    tree_builder::QueueTree,
+};
+use anyhow::Result;
+use chrono::Utc;
+use config::QosConfig;
+use shared_rest::{count_tree_changes, QueueTreeEntry, RebuildEvent, RebuildTrigger};
+use std::time::Instant;
+
+/// Applies a queue plan, replacing any existing queues, and reports the
+/// rebuild to the manager. `previous` is the tree that was in place before
+/// (empty at startup). Returns the new tree, in manager format.
+pub async fn rebuild_queues(
+    config: &QosConfig,
+    queue_plan: QueueTree,
+    triggers: Vec<RebuildTrigger>,
+    previous: &[QueueTreeEntry],
+) -> Result<Vec<QueueTreeEntry>> {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let tree = queue_plan.to_monitor_tree(config);
+    let result = apply_plan(config, queue_plan).await;
+    if result.is_ok() {
+        metrics::record_rebuild(started.elapsed());
+    }
+
+    let (nodes_added, nodes_removed, nodes_changed) = count_tree_changes(previous, &tree);
+    let event = RebuildEvent {
+        started: started_at.format("%+").to_string(),
+        ended: Utc::now().format("%+").to_string(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        triggers,
+        nodes_added,
+        nodes_removed,
+        nodes_changed,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    // The spool is on disk, so a failed startup build is still reported
+    // once the daemon is running again.
+    if negotiated_protocol() >= 4 {
+        if let Err(e) = spool_report(config, "/bus/rebuild", &event) {
+            display_warning(&format!("Unable to spool rebuild event: {e:?}"), 2);
+        }
+    }
+
+    result.map(|_| tree)
+}
+
+async fn apply_plan(config: &QosConfig, queue_plan: QueueTree) -> Result<()> {
+    // Perform the basic XDP/XPS setup.
+    display_action("XPS Interface Setup", 1);
+    shaper::setup_xdp(config).await?;
+
+    // Clear all existing QoS config.
+    display_action("Clearing QoS Config", 1);
+    shaper::clear_queue_settings(config).await?;
+
+    // Sets up master multiqueue modes and master interface queues.
+    // Copied from LibreQOS.
+    display_action("Setting Interface Queues", 1);
+    shaper::set_master_multiqueues(config).await?;
+    let queue_count = shaper::count_queues(config).await?;
+    shaper::set_master_interface_queues(config, &queue_count).await?;
+
+    // Build the actual queues
+    display_action("Building Queues", 1);
+    shaper::build_client_queues(config, queue_plan).await?;
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::bus::get_from_manager;
 use anyhow::Result;
 use config::QosConfig;
 use lazy_static::*;
@@ -24,16 +25,7 @@ fn make_hash(shaper_config: &ShaperTreeConfig) -> String {
 /// 0. A hash of the limits result, used to detect changes.
 /// 1. The actual list of limits.
 pub async fn update_limits(config: &QosConfig) -> Result<()> {
-    let full_url = format!("{}/bus/site_config", config.controller_url);
-    let client = reqwest::Client::new();
-
-    let res = client
-        .get(&full_url)
-        .header("'Content-Type", "application/json")
-        .send()
-        .await?;
-
-    let new_limits = res.json::<ShaperTreeConfig>().await?;
+    let new_limits: ShaperTreeConfig = get_from_manager(config, "/bus/site_config").await?;
     let new_hash = make_hash(&new_limits);
 
     let mut lock = LIMITS.write();
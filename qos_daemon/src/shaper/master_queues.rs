@@ -1,5 +1,8 @@
 use super::{QueueCount, TC_CMD};
-use crate::pretty::{display_action, display_success};
+use crate::{
+    metrics::record_tc_status,
+    pretty::{display_action, display_success},
+};
 use anyhow::Result;
 use config::QosConfig;
 use tokio::process::Command;
@@ -16,7 +19,7 @@ pub async fn set_master_multiqueues(config: &QosConfig) -> Result<()> {
 async fn set_master_multiqueue(interface: &str) -> Result<()> {
     display_action(&format!("Set multiqueue for {}", interface), 3);
     // tc qdisc replace dev ens19 root handle 7FFF: mq
-    Command::new(TC_CMD)
+    let status = Command::new(TC_CMD)
         .arg("qdisc")
         .arg("replace")
         .arg("dev")
@@ -27,6 +30,7 @@ async fn set_master_multiqueue(interface: &str) -> Result<()> {
         .arg("mq")
         .status()
         .await?;
+    record_tc_status(&status);
 
     display_success(&format!("Set multiqueue for {}", interface), 3);
     Ok(())
@@ -63,7 +67,7 @@ async fn set_master_queues(
     for queue in 0..n_queues {
         let queue_id = queue + 1;
 
-        Command::new("/sbin/tc")
+        let status = Command::new("/sbin/tc")
             .arg("qdisc")
             .arg("add")
             .arg("dev")
@@ -77,8 +81,9 @@ async fn set_master_queues(
             .arg("2")
             .status()
             .await?;
+        record_tc_status(&status);
 
-        Command::new("/sbin/tc")
+        let status = Command::new("/sbin/tc")
             .arg("class")
             .arg("add")
             .arg("dev")
@@ -94,8 +99,9 @@ async fn set_master_queues(
             .arg(format!("{max_mbps}mbit"))
             .status()
             .await?;
+        record_tc_status(&status);
 
-        Command::new("/sbin/tc")
+        let status = Command::new("/sbin/tc")
             .arg("qdisc")
             .arg("add")
             .arg("dev")
@@ -106,8 +112,9 @@ async fn set_master_queues(
             .arg("diffserv4")
             .status()
             .await?;
+        record_tc_status(&status);
 
-        Command::new("/sbin/tc")
+        let status = Command::new("/sbin/tc")
             .arg("class")
             .arg("add")
             .arg("dev")
@@ -125,8 +132,9 @@ async fn set_master_queues(
             .arg("5")
             .status()
             .await?;
+        record_tc_status(&status);
 
-        Command::new("/sbin/tc")
+        let status = Command::new("/sbin/tc")
             .arg("qdisc")
             .arg("add")
             .arg("dev")
@@ -137,6 +145,7 @@ async fn set_master_queues(
             .arg("diffserv4")
             .status()
             .await?;
+        record_tc_status(&status);
 
         display_success(&format!("Parent queue {}:1", queue_id), 3);
     }
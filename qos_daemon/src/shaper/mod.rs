@@ -14,23 +14,34 @@ mod limits;
 pub use limits::*;
 pub mod tuning;
 
+#[cfg(not(test))]
 pub const TC_CMD: &str = "/sbin/tc";
+/// Tests build queues without touching the host's interfaces.
+#[cfg(test)]
+pub const TC_CMD: &str = "true";
 
 /// Walks the Queue Tree and builds the actual queues in the traffic shaper.
+/// Returns once every CPU's queues have been built.
 pub async fn build_client_queues(config: &QosConfig, plan: QueueTree) -> Result<()> {
+    crate::graphing::queues_rebuilding();
+
     // Generate each CPU's queue plan independently
     let mut my_plan = plan.clone();
+    let mut builders = Vec::new();
     my_plan.queues.drain(0..).for_each(|cpu_queue| {
         if let QueueType::CpuQueue { cpu_id } = cpu_queue.queue_type {
             //println!("CPU Queue {}", cpu_id);
             let my_config = config.clone();
-            spawn_blocking(move || {
+            builders.push(spawn_blocking(move || {
                 let cpu_id = cpu_id + 1;
                 let mut class_id = 5;
                 let _ = cpu_queue.walk_and_build(&my_config, cpu_id, 1, &mut class_id);
-            });
+            }));
         }
     });
+    for builder in builders {
+        builder.await?;
+    }
 
     Ok(())
 }
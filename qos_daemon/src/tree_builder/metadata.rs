@@ -0,0 +1,37 @@
+use shared_rest::NodeMetadata;
+use uisp_support::{Device, Site};
+
+/// Collects the UISP and CRM details of a site, for reporting to the manager.
+pub fn site_metadata(site: &Site, devices: &[Device]) -> NodeMetadata {
+    // Sorted, so that the tree hash doesn't change with UISP's ordering.
+    let mut uisp_device_ids: Vec<String> = devices
+        .iter()
+        .filter(|d| d.get_site_id().as_ref() == Some(&site.id))
+        .map(|d| d.get_id())
+        .collect();
+    uisp_device_ids.sort();
+
+    let (crm_client_id, crm_service_id, shaping_profile) = match &site.ucrm {
+        Some(ucrm) => (
+            ucrm.client.as_ref().map(|c| c.id.clone()),
+            ucrm.service.as_ref().map(|s| s.id.clone()),
+            ucrm.service.as_ref().map(|s| s.name.clone()),
+        ),
+        None => (None, None, None),
+    };
+
+    let gps = site
+        .description
+        .as_ref()
+        .and_then(|d| d.location.as_ref())
+        .map(|l| (l.latitude, l.longitude));
+
+    NodeMetadata {
+        uisp_device_ids,
+        crm_client_id,
+        crm_service_id,
+        shaping_profile,
+        gps,
+        ..Default::default()
+    }
+}
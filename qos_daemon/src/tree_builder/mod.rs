@@ -5,11 +5,15 @@ use shared_rest::{DuplicateIp, QueueTreeEntry};
 use tokio::spawn;
 use uisp_support::{DataLink, Device, Site};
 mod ip_matchers;
+mod metadata;
 mod queue_tree;
 use config::{QosConfig, ShapingStrategy};
 pub use queue_tree::*;
 mod strategy;
-use crate::pretty::display_warning;
+use crate::{
+    bus::{negotiated_protocol, post_to_manager},
+    pretty::display_warning,
+};
 pub use ip_matchers::{is_ip_relevant_no_igore, load_ip_matching};
 use lazy_static::*;
 use parking_lot::RwLock;
@@ -52,11 +56,7 @@ pub async fn build_logical_tree(
     
     // If there are any duplicates, submit the list to the manager
     if !dupes.is_empty() {
-        send_dupes(
-            dupes,
-            format!("{}/bus/duplicate_ip", &config.controller_url),
-        )
-        .await;
+        send_dupes(dupes, config).await;
     }
 
     // Create the queue tree summary in the REST format required
@@ -64,19 +64,15 @@ pub async fn build_logical_tree(
     // to the manager.
     let tree_summary = tree.to_monitor_tree(config);
     *QUEUE_SUMMARY.write() = tree_summary.clone();
-    spawn(send_tree_report(
-        tree_summary,
-        format!("{}/bus/tree", &config.controller_url),
-    ));
+    spawn(send_tree_report(tree_summary, config.clone()));
 
     Ok(tree)
 }
 
 // Send the duplicate IP list via REST to the manager.
-async fn send_dupes(dupes: Vec<String>, url: String) {
+async fn send_dupes(dupes: Vec<String>, config: &QosConfig) {
     let report = DuplicateIp { dupes };
-    let client = reqwest::Client::new();
-    let res = client.post(&url).json(&report).send().await;
+    let res = post_to_manager(config, "/bus/duplicate_ip", &report).await;
     if res.is_err() {
         println!("{:?}", res);
     }
@@ -177,11 +173,27 @@ fn walk_tree_maximums(queue: &mut Queue, speed_limit: (u32, u32), config: &QosCo
     }
 }
 
+/// The tree with the `level_type`s that managers before bus protocol v5
+/// understand.
+fn legacy_tree(report: &[QueueTreeEntry]) -> Vec<serde_json::Value> {
+    report
+        .iter()
+        .filter_map(|entry| {
+            let mut value = serde_json::to_value(entry).ok()?;
+            value["level_type"] = entry.level_type.legacy_name().into();
+            Some(value)
+        })
+        .collect()
+}
+
 /// Send the queue tree (in manager friendly format) to the manager
 /// program.
-async fn send_tree_report(report: Vec<QueueTreeEntry>, url: String) {
-    let client = reqwest::Client::new();
-    let res = client.post(&url).json(&report).send().await;
+async fn send_tree_report(report: Vec<QueueTreeEntry>, config: QosConfig) {
+    let res = if negotiated_protocol() >= 5 {
+        post_to_manager(&config, "/bus/tree", &report).await
+    } else {
+        post_to_manager(&config, "/bus/tree", &legacy_tree(&report)).await
+    };
     if res.is_err() {
         println!("{:?}", res);
     }
@@ -1,5 +1,6 @@
 use crate::{
     graphing::map_ip_to_site,
+    metrics::record_tc_status,
     shaper::{count_queues, QueueCount, TC_CMD},
 };
 use anyhow::Result;
@@ -9,10 +10,10 @@ use ron::{
     to_string,
 };
 use serde::{Deserialize, Serialize};
-use shared_rest::QueueTreeEntry;
+use shared_rest::{NodeKind, NodeMetadata, QueueTreeEntry};
 use std::{
     collections::{hash_map::DefaultHasher, HashMap, HashSet},
-    hash::Hash,
+    hash::{Hash, Hasher},
     process::{Command, Stdio},
 };
 
@@ -66,7 +67,8 @@ impl QueueTree {
     pub fn make_hash(&self) -> String {
         let ron = to_string(&self.queues).unwrap();
         let mut hasher = DefaultHasher::new();
-        format!("{:?}", ron.hash(&mut hasher))
+        ron.hash(&mut hasher);
+        format!("{:x}", hasher.finish())
     }
 
     /// Converts a QueueTree to a `QueueTreeEntry` vector, in the format
@@ -76,16 +78,19 @@ impl QueueTree {
         result.push(QueueTreeEntry {
             name: "Root".to_string(),
             id: "root".to_string(),
-            level_type: "root".to_string(),
+            level_type: NodeKind::Root,
             parent: None,
             down_mbps: config.internet_download_mbps,
             up_mbps: config.internet_upload_mbps,
             ip_addresses: HashSet::new(),
+            metadata: NodeMetadata::default(),
         });
 
         let n_queues = u32::min(self.queue_count.to_internet, self.queue_count.to_isp);
         for c in self.queues.iter().take(n_queues as usize) {
-            c.to_tree(0, &mut result);
+            // Matches the numbering used by `build_client_queues`
+            // and `walk_and_build`.
+            c.to_tree(0, &mut result, 0, &mut 5);
         }
 
         result
@@ -120,6 +125,15 @@ pub struct Queue {
     pub name: String, // Not actually used, but makes for better debug decoration
     pub queue_type: QueueType,
     pub children: Vec<Queue>,
+
+    /// Overrides the node kind reported to the manager, which is otherwise
+    /// implied by `queue_type`.
+    #[serde(default)]
+    pub kind: Option<NodeKind>,
+
+    /// Details reported to the manager; not used for shaping.
+    #[serde(default)]
+    pub metadata: NodeMetadata,
 }
 
 impl Queue {
@@ -128,6 +142,8 @@ impl Queue {
             name: format!("CPU {cpu_id} Queue"),
             queue_type: QueueType::CpuQueue { cpu_id },
             children: Vec::new(),
+            kind: None,
+            metadata: NodeMetadata::default(),
         }
     }
 
@@ -147,6 +163,8 @@ impl Queue {
                 ip_addresses: ip_addresses.iter().cloned().collect(),
             },
             children: Vec::new(),
+            kind: None,
+            metadata: NodeMetadata::default(),
         }
     }
 
@@ -159,6 +177,8 @@ impl Queue {
                 up_mbps,
             },
             children: Vec::new(),
+            kind: None,
+            metadata: NodeMetadata::default(),
         }
     }
 
@@ -171,9 +191,23 @@ impl Queue {
                 up_mbps,
             },
             children: Vec::new(),
+            kind: None,
+            metadata: NodeMetadata::default(),
         }
     }
 
+    /// Reports this queue to the manager as a different kind of node (e.g.
+    /// `Infrastructure`, for a site's equipment shaped as a client site).
+    pub fn with_kind(mut self, kind: NodeKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: NodeMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
     pub fn walk_and_build(
         &self,
         config: &QosConfig,
@@ -299,64 +333,82 @@ impl Queue {
         Ok(())
     }
 
-    fn to_tree(&self, parent: usize, tree: &mut Vec<QueueTreeEntry>) {
+    /// Adds this queue (and its children) to a monitor tree. `cpu_id` and
+    /// `class_id` follow the same numbering as `walk_and_build`, so that each
+    /// node can report its `tc` class handle.
+    fn to_tree(
+        &self,
+        parent: usize,
+        tree: &mut Vec<QueueTreeEntry>,
+        cpu_id: u32,
+        class_id: &mut u32,
+    ) {
         let mut new_parent = parent;
-        match &self.queue_type {
-            QueueType::CpuQueue { .. } => {}
+        let mut cpu_id = cpu_id;
+        let node = match &self.queue_type {
+            QueueType::CpuQueue { cpu_id: id } => {
+                // `walk_and_build` uses the queue's own (1-based) ID as the
+                // HTB major number.
+                cpu_id = *id;
+                None
+            }
             QueueType::TowerSite {
                 site_id,
                 down_mbps,
                 up_mbps,
-            } => {
-                new_parent = tree.len();
-                tree.push(QueueTreeEntry {
-                    name: self.name.clone(),
-                    id: site_id.clone(),
-                    level_type: "tower".to_string(),
-                    parent: Some(parent),
-                    down_mbps: *down_mbps,
-                    up_mbps: *up_mbps,
-                    ip_addresses: HashSet::new(),
-                });
-            }
+            } => Some((
+                site_id,
+                NodeKind::Tower,
+                *down_mbps,
+                *up_mbps,
+                HashSet::new(),
+            )),
             QueueType::AccessPointSite {
                 site_id,
                 down_mbps,
                 up_mbps,
-            } => {
-                new_parent = tree.len();
-                tree.push(QueueTreeEntry {
-                    name: self.name.clone(),
-                    id: site_id.clone(),
-                    level_type: "ap".to_string(),
-                    parent: Some(parent),
-                    down_mbps: *down_mbps,
-                    up_mbps: *up_mbps,
-                    ip_addresses: HashSet::new(),
-                });
-            }
+            } => Some((
+                site_id,
+                NodeKind::AccessPoint,
+                *down_mbps,
+                *up_mbps,
+                HashSet::new(),
+            )),
             QueueType::ClientSite {
                 site_id,
                 down_mbps,
                 up_mbps,
                 ip_addresses,
-                ..
-            } => {
-                new_parent = tree.len();
-                tree.push(QueueTreeEntry {
-                    name: self.name.clone(),
-                    id: site_id.clone(),
-                    level_type: "client".to_string(),
-                    parent: Some(parent),
-                    down_mbps: *down_mbps,
-                    up_mbps: *up_mbps,
-                    ip_addresses: ip_addresses.clone(),
-                });
-            }
+            } => Some((
+                site_id,
+                NodeKind::Client,
+                *down_mbps,
+                *up_mbps,
+                ip_addresses.clone(),
+            )),
+        };
+
+        if let Some((site_id, kind, down_mbps, up_mbps, ip_addresses)) = node {
+            let mut metadata = self.metadata.clone();
+            metadata.cpu = Some(cpu_id - 1);
+            metadata.class_handle = Some(format!("{cpu_id}:{class_id}"));
+            *class_id += 1;
+
+            new_parent = tree.len();
+            tree.push(QueueTreeEntry {
+                name: self.name.clone(),
+                id: site_id.clone(),
+                level_type: self.kind.unwrap_or(kind),
+                parent: Some(parent),
+                down_mbps,
+                up_mbps,
+                ip_addresses,
+                metadata,
+            });
         }
 
         for c in self.children.iter() {
-            c.to_tree(new_parent, tree);
+            c.to_tree(new_parent, tree, cpu_id, class_id);
         }
     }
 }
@@ -369,7 +421,7 @@ fn add_client_site_htb(
     mbps: u32,
 ) -> Result<()> {
     //println!("tc class add dev {interface} parent {cpu_id}:{minor_parent} classid {class_id}...");
-    Command::new(TC_CMD)
+    let status = Command::new(TC_CMD)
         .arg("class")
         .arg("add")
         .arg("dev")
@@ -386,11 +438,12 @@ fn add_client_site_htb(
         .arg("prio")
         .arg("3")
         .status()?;
+    record_tc_status(&status);
     Ok(())
 }
 
 fn add_client_cake(interface: &str, cpu_id: u32, class_id: u32) -> Result<()> {
-    Command::new(TC_CMD)
+    let status = Command::new(TC_CMD)
         .arg("qdisc")
         .arg("add")
         .arg("dev")
@@ -400,6 +453,7 @@ fn add_client_cake(interface: &str, cpu_id: u32, class_id: u32) -> Result<()> {
         .arg("cake")
         .arg("diffserv4")
         .status()?;
+    record_tc_status(&status);
     Ok(())
 }
 
@@ -428,7 +482,7 @@ fn add_tower_htb(
     //println!("tc class add dev {interface} parent {cpu_id}:{minor_parent} classid {class_id} htb rate {mbps}mbit ceil {mbps}mbit prio 3");
     //shell('tc class add dev ' + interfaceA + ' parent ' + parentClassID + ' classid ' + str(minor) + ' htb rate '+ str(round(elemDownloadMin)) + 'mbit ceil '+ str(round(elemDownloadMax)) + 'mbit prio 3')
     //shell('tc class add dev ' + interfaceB + ' parent ' + parentClassID + ' classid ' + str(minor) + ' htb rate '+ str(round(elemUploadMin)) + 'mbit ceil '+ str(round(elemUploadMax)) + 'mbit prio 3')
-    Command::new(TC_CMD)
+    let status = Command::new(TC_CMD)
         .arg("class")
         .arg("add")
         .arg("dev")
@@ -445,6 +499,70 @@ fn add_tower_htb(
         .arg("prio")
         .arg("3")
         .status()?;
+    record_tc_status(&status);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Queue, QueueTree};
+    use crate::{
+        graphing::{htb_queue_site, queue_site},
+        shaper::QueueCount,
+    };
+    use config::QosConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn class_handles_match_built_classes() {
+        let mut cpu_queues = Vec::new();
+        for cpu in 1..=2 {
+            let mut tower = Queue::new_tower_site("Tower", 100, 100, &format!("tower-{cpu}"));
+            let mut ap = Queue::new_access_point_site("AP", 50, 50, &format!("ap-{cpu}"));
+            for client in 0..2 {
+                ap.children.push(Queue::new_client_site(
+                    "Client",
+                    10,
+                    10,
+                    &[],
+                    &format!("client-{cpu}-{client}"),
+                ));
+            }
+            tower.children.push(ap);
+            let mut cpu_queue = Queue::new_cpu_queue(cpu);
+            cpu_queue.children.push(tower);
+            cpu_queues.push(cpu_queue);
+        }
+        let tree = QueueTree {
+            queue_count: QueueCount {
+                to_isp: 2,
+                to_internet: 2,
+            },
+            ip_to_site_map: HashMap::new(),
+            queues: cpu_queues,
+        };
+
+        // As `build_client_queues` does.
+        let config = QosConfig::default();
+        for (i, cpu_queue) in tree.queues.iter().enumerate() {
+            cpu_queue
+                .walk_and_build(&config, i as u32 + 1, 1, &mut 5)
+                .unwrap();
+        }
+
+        let entries = tree.to_monitor_tree(&config);
+        assert_eq!(entries.len(), 9);
+        for entry in entries.iter().skip(1) {
+            let handle = entry.metadata.class_handle.as_ref().unwrap();
+            let built = if entry.id.starts_with("client") {
+                queue_site(handle)
+            } else {
+                htb_queue_site(handle)
+            };
+            assert_eq!(built.as_ref(), Some(&entry.id), "{handle}");
+            let cpu = entry.metadata.cpu.unwrap();
+            assert!(handle.starts_with(&format!("{}:", cpu + 1)));
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::{
+    bus::post_to_manager,
     shaper::{get_access_point_limits, get_site_limits},
-    tree_builder::{ip_matchers::ip_addresses_in_site, Queue, QueueTree},
+    tree_builder::{ip_matchers::ip_addresses_in_site, metadata::site_metadata, Queue, QueueTree},
 };
 use anyhow::Result;
 use config::QosConfig;
-use shared_rest::ApLimit;
+use shared_rest::{ApLimit, NodeKind, NodeMetadata};
 use uisp_support::{DataLink, Device, Site};
 
 #[derive(Debug, Clone)]
@@ -16,8 +17,13 @@ struct VSite {
     parent: Option<String>,
     children: Vec<VSite>,
     infrastructure_ips: Vec<String>,
-    clients: HashMap<(String, String), Vec<VClient>>,
+    /// Clients, by access point name and device ID. Clients with no known
+    /// access point have no ID.
+    clients: HashMap<(String, Option<String>), Vec<VClient>>,
     speed_limit: (u32, u32),
+    metadata: NodeMetadata,
+    /// The fake site holding clients that couldn't be placed in the hierarchy.
+    unmapped: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +34,7 @@ struct VClient {
     ip_addresses: Vec<String>,
     speed_limit: (u32, u32),
     access_point: (Option<String>, Option<String>),
+    metadata: NodeMetadata,
 }
 
 pub async fn full_tree_hierarchy(
@@ -77,6 +84,7 @@ pub async fn full_tree_hierarchy(
                 ip_addresses: ip_addresses_in_site(client, devices).unwrap_or(Vec::new()),
                 speed_limit: client.qos(config.default_download_mbps, config.default_upload_mbps),
                 access_point: find_access_point(data_links, devices, &client.id, &parent),
+                metadata: site_metadata(client, devices),
             }
         })
         .collect();
@@ -137,6 +145,8 @@ pub async fn full_tree_hierarchy(
                 infrastructure_ips: ip_addresses_in_site(site, devices).unwrap_or(Vec::new()),
                 clients: map_site_clients(&site.id, &clients, &mut included_clients),
                 speed_limit: (down_mbps, up_mbps),
+                metadata: site_metadata(site, devices),
+                unmapped: false,
             }
         })
         .collect();
@@ -192,14 +202,10 @@ pub async fn full_tree_hierarchy(
             "Warning: {not_included} clients weren't mapped. These will be added to a fake site."
         );
         //println!("{:#?}", not_included_list);
-        send_unmapped(
-            not_included_list,
-            format!("{}/bus/unmapped_clients", &config.controller_url),
-        )
-        .await;
+        send_unmapped(not_included_list, config).await;
         let mut access_points = HashMap::new();
         access_points.insert(
-            ("No AP".to_string(), "666.2".to_string()),
+            ("No AP".to_string(), None),
             clients
                 .iter()
                 .filter(|t| !included_clients.contains(&t.id))
@@ -214,6 +220,8 @@ pub async fn full_tree_hierarchy(
             infrastructure_ips: Vec::new(),
             clients: access_points,
             speed_limit: (config.internet_download_mbps, config.internet_upload_mbps),
+            metadata: NodeMetadata::default(),
+            unmapped: true,
         };
         root_sites.push(fake_site);
     }
@@ -230,29 +238,16 @@ pub async fn full_tree_hierarchy(
                 root.speed_limit.1,
                 &root.infrastructure_ips.clone(),
                 &format!("{}.1", root.id),
-            );
+            )
+            .with_kind(NodeKind::Infrastructure)
+            .with_metadata(root.metadata.clone());
             tree.queues[top_level_queue].children.push(infrastructure);
             top_level_queue += 1;
             top_level_queue %= max_queue;
         }
 
         for ((ap_name, ap_id), clients) in root.clients.iter() {
-            let (down_mbps, up_mbps) = if let Some(ap) = ap_limits.iter().find(|a| a.id == *ap_id) {
-                (ap.download, ap.upload)
-            } else {
-                root.speed_limit
-            };
-            let mut ap = Queue::new_access_point_site(&ap_name, down_mbps, up_mbps, &ap_id);
-            for c in clients.iter() {
-                let cs = Queue::new_client_site(
-                    &c.name,
-                    c.speed_limit.0,
-                    c.speed_limit.1,
-                    &c.ip_addresses,
-                    &c.id,
-                );
-                ap.children.push(cs);
-            }
+            let ap = build_ap_queue(root, ap_name, ap_id, clients, &ap_limits);
             tree.queues[top_level_queue].children.push(ap);
             top_level_queue += 1;
             top_level_queue %= max_queue;
@@ -264,7 +259,8 @@ pub async fn full_tree_hierarchy(
                 child.speed_limit.0,
                 child.speed_limit.1,
                 &child.id,
-            );
+            )
+            .with_metadata(child.metadata.clone());
             build_site_queue(child, &mut link, &ap_limits);
 
             // Balance accross CPUs
@@ -288,27 +284,14 @@ fn build_site_queue(root: &VSite, link: &mut Queue, ap_limits: &[ApLimit]) {
             root.speed_limit.1,
             &root.infrastructure_ips.clone(),
             &format!("{}.1", root.id),
-        );
+        )
+        .with_kind(NodeKind::Infrastructure)
+        .with_metadata(root.metadata.clone());
         link.children.push(infrastructure);
     }
     for ((ap_name, ap_id), clients) in root.clients.iter() {
-        let (down_mbps, up_mbps) = if let Some(ap) = ap_limits.iter().find(|a| a.id == *ap_id) {
-            (ap.download, ap.upload)
-        } else {
-            root.speed_limit
-        };
-        let mut ap = Queue::new_access_point_site(&ap_name, down_mbps, up_mbps, &ap_id);
-        for c in clients.iter() {
-            let cs = Queue::new_client_site(
-                &c.name,
-                c.speed_limit.0,
-                c.speed_limit.1,
-                &c.ip_addresses,
-                &c.id,
-            );
-            ap.children.push(cs);
-        }
-        link.children.push(ap);
+        link.children
+            .push(build_ap_queue(root, ap_name, ap_id, clients, ap_limits));
     }
     for child in root.children.iter() {
         let mut clink = Queue::new_tower_site(
@@ -316,12 +299,52 @@ fn build_site_queue(root: &VSite, link: &mut Queue, ap_limits: &[ApLimit]) {
             child.speed_limit.0,
             child.speed_limit.1,
             &child.id,
-        );
+        )
+        .with_metadata(child.metadata.clone());
         build_site_queue(child, &mut clink, ap_limits);
         link.children.push(clink);
     }
 }
 
+/// Builds an access point queue, and the queues of the clients behind it.
+fn build_ap_queue(
+    site: &VSite,
+    ap_name: &str,
+    ap_id: &Option<String>,
+    clients: &[VClient],
+    ap_limits: &[ApLimit],
+) -> Queue {
+    let (id, kind) = match ap_id {
+        Some(id) => (id.clone(), NodeKind::AccessPoint),
+        None if site.unmapped => (format!("{}.2", site.id), NodeKind::Unmapped),
+        None => (format!("{}.2", site.id), NodeKind::NoAp),
+    };
+    let (down_mbps, up_mbps) = if let Some(ap) = ap_limits.iter().find(|a| a.id == id) {
+        (ap.download, ap.upload)
+    } else {
+        site.speed_limit
+    };
+    let metadata = NodeMetadata {
+        uisp_device_ids: ap_id.iter().cloned().collect(),
+        ..Default::default()
+    };
+    let mut ap = Queue::new_access_point_site(ap_name, down_mbps, up_mbps, &id)
+        .with_kind(kind)
+        .with_metadata(metadata);
+    for c in clients.iter() {
+        let cs = Queue::new_client_site(
+            &c.name,
+            c.speed_limit.0,
+            c.speed_limit.1,
+            &c.ip_addresses,
+            &c.id,
+        )
+        .with_metadata(c.metadata.clone());
+        ap.children.push(cs);
+    }
+    ap
+}
+
 fn map_child_sites(site: &mut VSite, sites: &[VSite], included_sites: &mut HashSet<String>) {
     sites
         .iter()
@@ -345,8 +368,8 @@ fn map_site_clients(
     site_id: &str,
     clients: &[VClient],
     included_clients: &mut HashSet<String>,
-) -> HashMap<(String, String), Vec<VClient>> {
-    let mut aps: HashMap<(String, String), Vec<VClient>> = HashMap::new();
+) -> HashMap<(String, Option<String>), Vec<VClient>> {
+    let mut aps: HashMap<(String, Option<String>), Vec<VClient>> = HashMap::new();
 
     let result: Vec<VClient> = clients
         .iter()
@@ -366,14 +389,14 @@ fn map_site_clients(
 
     for c in result.iter() {
         let ap_name = c.access_point.0.clone().unwrap_or("No AP".to_string());
-        let ap_id = c.access_point.1.clone().unwrap_or(format!("{}.2", site_id));
+        let ap_id = c.access_point.1.clone();
         if let Some(ap) = aps.get_mut(&(ap_name, ap_id)) {
             ap.push(c.clone());
         } else {
             aps.insert(
                 (
                     c.access_point.0.clone().unwrap_or("No AP".to_string()),
-                    c.access_point.1.clone().unwrap_or(format!("{}.2", site_id)),
+                    c.access_point.1.clone(),
                 ),
                 vec![c.clone()],
             );
@@ -440,10 +463,9 @@ fn find_access_point(
     (ap_name, ap_id)
 }
 
-async fn send_unmapped(clients: Vec<String>, url: String) {
+async fn send_unmapped(clients: Vec<String>, config: &QosConfig) {
     let report = shared_rest::Unmapped { clients };
-    let client = reqwest::Client::new();
-    let res = client.post(&url).json(&report).send().await;
+    let res = post_to_manager(config, "/bus/unmapped_clients", &report).await;
     if res.is_err() {
         println!("{:?}", res);
     }
@@ -1,9 +1,10 @@
 use crate::{
     pretty::display_success,
-    tree_builder::{ip_matchers::ip_addresses_in_site, Queue, QueueTree},
+    tree_builder::{ip_matchers::ip_addresses_in_site, metadata::site_metadata, Queue, QueueTree},
 };
 use anyhow::Result;
 use config::QosConfig;
+use shared_rest::NodeKind;
 use uisp_support::{Device, Site};
 
 pub async fn single_layer_strategy(
@@ -21,25 +22,33 @@ pub async fn single_layer_strategy(
             if let Some(name) = client_site.name() {
                 if let Ok(ip_addresses) = ip_addresses_in_site(client_site, devices) {
                     if !ip_addresses.is_empty() {
-                        let qos = if client_site.is_client_site() {
-                            client_site
-                                .qos(config.default_download_mbps, config.default_upload_mbps)
+                        let (qos, kind) = if client_site.is_client_site() {
+                            (
+                                client_site
+                                    .qos(config.default_download_mbps, config.default_upload_mbps),
+                                NodeKind::Client,
+                            )
                         } else {
-                            (config.internet_download_mbps, config.internet_upload_mbps)
+                            (
+                                (config.internet_download_mbps, config.internet_upload_mbps),
+                                NodeKind::Infrastructure,
+                            )
                         };
                         for ip in ip_addresses.iter() {
                             tree.ip_to_site_map
                                 .insert(ip.clone(), client_site.id.clone());
                         }
-                        tree.queues[top_level_queue]
-                            .children
-                            .push(Queue::new_client_site(
+                        tree.queues[top_level_queue].children.push(
+                            Queue::new_client_site(
                                 &name,
                                 qos.0,
                                 qos.1,
                                 &ip_addresses,
                                 &client_site.id,
-                            ));
+                            )
+                            .with_kind(kind)
+                            .with_metadata(site_metadata(client_site, devices)),
+                        );
 
                         top_level_queue += 1;
                         top_level_queue %=
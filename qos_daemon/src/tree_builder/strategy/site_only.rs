@@ -1,9 +1,10 @@
 use crate::{
     shaper::get_site_limits,
-    tree_builder::{ip_matchers::ip_addresses_in_site, Queue, QueueTree},
+    tree_builder::{ip_matchers::ip_addresses_in_site, metadata::site_metadata, Queue, QueueTree},
 };
 use anyhow::Result;
 use config::QosConfig;
+use shared_rest::NodeKind;
 use uisp_support::{Device, Site};
 
 pub async fn site_only_strategy(
@@ -26,7 +27,9 @@ pub async fn site_only_strategy(
                     (config.internet_download_mbps, config.internet_upload_mbps)
                 };
 
-            let mut tower_queue = Queue::new_tower_site(&name, down_mbps, up_mbps, &site.id);
+            let metadata = site_metadata(site, devices);
+            let mut tower_queue = Queue::new_tower_site(&name, down_mbps, up_mbps, &site.id)
+                .with_metadata(metadata.clone());
 
             // Insert infrastructure elements
             if let Ok(ip_addresses) = ip_addresses_in_site(site, devices) {
@@ -40,7 +43,9 @@ pub async fn site_only_strategy(
                     config.internet_upload_mbps,
                     &ip_addresses,
                     &format!("{}.0", site.id),
-                );
+                )
+                .with_kind(NodeKind::Infrastructure)
+                .with_metadata(metadata);
                 tower_queue.children.push(infrastructure);
             }
 
@@ -64,13 +69,16 @@ pub async fn site_only_strategy(
                                     tree.ip_to_site_map
                                         .insert(ip.clone(), client_site.id.clone());
                                 }
-                                tower_queue.children.push(Queue::new_client_site(
-                                    &name,
-                                    qos.0,
-                                    qos.1,
-                                    &ip_addresses,
-                                    &client_site.id,
-                                ));
+                                tower_queue.children.push(
+                                    Queue::new_client_site(
+                                        &name,
+                                        qos.0,
+                                        qos.1,
+                                        &ip_addresses,
+                                        &client_site.id,
+                                    )
+                                    .with_metadata(site_metadata(client_site, devices)),
+                                );
                             }
                         }
                     }
@@ -0,0 +1,264 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::serde::{Deserialize, Serialize};
+use ron::ser::{to_string_pretty, PrettyConfig};
+
+const ALERTS_FILENAME: &str = "alerts.ron";
+
+/// Alert rules and where to send alerts, from `alerts.ron`. Alerting is off
+/// if the file is missing.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertConfig {
+    /// Seconds between rule checks. Defaults to 300.
+    #[serde(default = "default_check_secs")]
+    pub check_secs: u64,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub targets: Vec<AlertTarget>,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceWindow>,
+}
+
+fn default_check_secs() -> u64 {
+    300
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            check_secs: default_check_secs(),
+            rules: Vec::new(),
+            targets: Vec::new(),
+            maintenance: Vec::new(),
+        }
+    }
+}
+
+/// A threshold on one metric. An alert is raised for a node (or IP address,
+/// or shaper) once the metric reaches `trigger` for `for_checks` checks in a
+/// row, and cleared once it falls below `clear`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertRule {
+    /// Names the rule in notifications. Must be unique.
+    pub name: String,
+    pub metric: AlertMetric,
+    pub trigger: f64,
+    /// Defaults to `trigger`. Set it lower to stop an alert flapping.
+    #[serde(default)]
+    pub clear: Option<f64>,
+    #[serde(default = "default_for_checks")]
+    pub for_checks: u32,
+    /// How far back (seconds) to look at the metric. Defaults to 900.
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u32,
+    #[serde(default)]
+    pub overrides: Vec<AlertOverride>,
+}
+
+fn default_for_checks() -> u32 {
+    1
+}
+
+fn default_window_secs() -> u32 {
+    900
+}
+
+impl AlertRule {
+    /// The trigger and clear thresholds for a subject, or `None` if the rule
+    /// is disabled for it.
+    pub fn thresholds(&self, id: &str) -> Option<(f64, f64)> {
+        let mut trigger = self.trigger;
+        let mut clear = self.clear.unwrap_or(self.trigger);
+        if let Some(o) = self.overrides.iter().find(|o| o.id == id) {
+            if o.disabled {
+                return None;
+            }
+            trigger = o.trigger.unwrap_or(trigger);
+            clear = o.clear.or(o.trigger).unwrap_or(clear);
+        }
+        Some((trigger, clear.min(trigger)))
+    }
+}
+
+/// What a rule measures. Node metrics apply to every node of the given level.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(crate = "rocket::serde")]
+pub enum AlertMetric {
+    /// Peak use, as a percentage of the node's download or upload limit
+    /// (whichever is higher).
+    Congestion(AlertLevel),
+    /// Median latency (ms) of the node and the clients beneath it.
+    MedianLatency(AlertLevel),
+    /// Highest latency (ms) of the node and the clients beneath it.
+    PeakLatency(AlertLevel),
+    /// Packets dropped per second, download and upload together.
+    DropRate(AlertLevel),
+    /// 1 for each IP address found more than once in UISP.
+    DuplicateIp,
+    /// 1 for each client that couldn't be placed in the tree.
+    UnmappedClient,
+    /// Seconds since each shaper was last heard from.
+    ShaperSilent,
+    /// Seconds since each shaper's bandwidth, latency and host feeds were
+    /// last received.
+    StaleFeed,
+}
+
+/// The nodes a node metric applies to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(crate = "rocket::serde")]
+pub enum AlertLevel {
+    Site,
+    AccessPoint,
+    Client,
+}
+
+/// Changes a rule's thresholds for one node, IP address or shaper.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertOverride {
+    pub id: String,
+    #[serde(default)]
+    pub trigger: Option<f64>,
+    #[serde(default)]
+    pub clear: Option<f64>,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// A period in which alerts are neither raised nor cleared. It covers the
+/// listed nodes and everything beneath them, or everything if `ids` is empty.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct MaintenanceWindow {
+    /// RFC 3339, for example `2022-06-01T02:00:00-05:00`.
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub reason: String,
+}
+
+impl MaintenanceWindow {
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        match (
+            DateTime::parse_from_rfc3339(&self.start),
+            DateTime::parse_from_rfc3339(&self.end),
+        ) {
+            (Ok(start), Ok(end)) => now >= start && now < end,
+            _ => false,
+        }
+    }
+}
+
+/// Somewhere to send alerts.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub enum AlertTarget {
+    /// POSTs each alert as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: Vec<(String, String)>,
+    },
+    /// Emails each alert.
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default)]
+        security: SmtpSecurity,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    /// Publishes each alert as JSON.
+    Mqtt {
+        host: String,
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        topic: String,
+        #[serde(default = "default_mqtt_client_id")]
+        client_id: String,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "bracket_qos".to_string()
+}
+
+/// How to secure an SMTP connection.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(crate = "rocket::serde")]
+pub enum SmtpSecurity {
+    /// Plain text, such as a local relay.
+    #[default]
+    None,
+    /// Upgrade with STARTTLS (usually port 587).
+    StartTls,
+    /// TLS from the start (usually port 465).
+    Tls,
+}
+
+lazy_static! {
+    static ref ALERT_CONFIG: RwLock<AlertConfig> = RwLock::new(AlertConfig::default());
+}
+
+/// Loads `alerts.ron`. It is read again before every check, so that rules,
+/// targets and maintenance windows can be changed without a restart.
+pub fn load_alerts() -> Result<()> {
+    let data = std::fs::read_to_string(ALERTS_FILENAME)?;
+    let mut cfg: AlertConfig = ron::from_str(&data)?;
+    // A zero interval would spin.
+    cfg.check_secs = cfg.check_secs.max(1);
+    *ALERT_CONFIG.write() = cfg;
+    Ok(())
+}
+
+/// Reads `alerts.ron` without loading it, or `None` if there isn't one.
+pub fn read_alerts_file() -> Result<Option<AlertConfig>> {
+    if !std::path::Path::new(ALERTS_FILENAME).exists() {
+        return Ok(None);
+    }
+    let data = std::fs::read_to_string(ALERTS_FILENAME)?;
+    Ok(Some(ron::from_str(&data)?))
+}
+
+/// Writes a replacement for `alerts.ron` beside it, returning its path, so
+/// that a restore can install it once everything else has succeeded.
+pub fn stage_alerts(cfg: &AlertConfig) -> Result<String> {
+    let staged = format!("{ALERTS_FILENAME}.restore");
+    std::fs::write(&staged, to_string_pretty(cfg, PrettyConfig::new())?)?;
+    Ok(staged)
+}
+
+/// Moves a file written by `stage_alerts` over `alerts.ron`, and loads it.
+pub fn install_alerts(staged: &str) -> Result<()> {
+    std::fs::rename(staged, ALERTS_FILENAME)?;
+    load_alerts()
+}
+
+pub fn alert_configuration() -> AlertConfig {
+    ALERT_CONFIG.read().clone()
+}
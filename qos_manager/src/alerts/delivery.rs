@@ -0,0 +1,153 @@
+use super::AlertTarget;
+use super::SmtpSecurity;
+use anyhow::{Error, Result};
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use rocket::serde::Serialize;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::time::Duration;
+
+/// Gives up on a target that hasn't accepted an alert in this long.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// An alert being raised or cleared, as sent to every target.
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertNotification {
+    pub rule: String,
+    /// `raised`, `cleared` or `test`.
+    pub state: String,
+    /// Node ID, IP address or shaper ID.
+    pub id: String,
+    pub name: String,
+    pub value: f64,
+    pub trigger: f64,
+    /// RFC 3339.
+    pub time: String,
+}
+
+impl AlertNotification {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} for {} (value {:.1}, threshold {:.1})",
+            self.rule, self.state, self.name, self.value, self.trigger
+        )
+    }
+}
+
+/// Sends a notification to one target.
+pub async fn deliver(target: &AlertTarget, notification: &AlertNotification) -> Result<()> {
+    let delivery = async {
+        match target {
+            AlertTarget::Webhook { url, headers } => webhook(url, headers, notification).await,
+            AlertTarget::Smtp {
+                host,
+                port,
+                security,
+                username,
+                password,
+                from,
+                to,
+            } => {
+                let mut builder = match security {
+                    SmtpSecurity::None => {
+                        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+                    }
+                    SmtpSecurity::StartTls => {
+                        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+                    }
+                    SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+                }
+                .port(*port);
+                if let (Some(username), Some(password)) = (username, password) {
+                    builder =
+                        builder.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+                let mut message = Message::builder()
+                    .from(from.parse()?)
+                    .subject(format!("[BracketQOS] {}", notification.summary()))
+                    .header(ContentType::TEXT_PLAIN);
+                for recipient in to.iter() {
+                    message = message.to(recipient.parse()?);
+                }
+                let body = format!(
+                    "{}\n\nRule: {}\nState: {}\nID: {}\nValue: {}\nThreshold: {}\nTime: {}\n",
+                    notification.summary(),
+                    notification.rule,
+                    notification.state,
+                    notification.id,
+                    notification.value,
+                    notification.trigger,
+                    notification.time
+                );
+                builder.build().send(message.body(body)?).await?;
+                Ok(())
+            }
+            AlertTarget::Mqtt {
+                host,
+                port,
+                topic,
+                client_id,
+                username,
+                password,
+            } => {
+                mqtt(
+                    host,
+                    *port,
+                    topic,
+                    client_id,
+                    username.as_ref().zip(password.as_ref()),
+                    notification,
+                )
+                .await
+            }
+        }
+    };
+    rocket::tokio::time::timeout(DELIVERY_TIMEOUT, delivery)
+        .await
+        .map_err(|_| Error::msg("Timed out"))?
+}
+
+async fn webhook(
+    url: &str,
+    headers: &[(String, String)],
+    notification: &AlertNotification,
+) -> Result<()> {
+    let mut request = reqwest::Client::new().post(url).json(notification);
+    for (name, value) in headers.iter() {
+        request = request.header(name, value);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+async fn mqtt(
+    host: &str,
+    port: u16,
+    topic: &str,
+    client_id: &str,
+    credentials: Option<(&String, &String)>,
+    notification: &AlertNotification,
+) -> Result<()> {
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(Duration::from_secs(10));
+    if let Some((username, password)) = credentials {
+        options.set_credentials(username, password);
+    }
+    let (client, mut events) = AsyncClient::new(options, 10);
+    let payload = rocket::serde::json::to_string(notification)?;
+    client
+        .publish(topic, QoS::AtLeastOnce, false, payload)
+        .await?;
+    // Nothing is sent until the event loop is polled; stop once the broker
+    // has acknowledged the message.
+    loop {
+        if let Event::Incoming(Packet::PubAck(_)) = events.poll().await? {
+            break;
+        }
+    }
+    let _ = client.disconnect().await;
+    Ok(())
+}
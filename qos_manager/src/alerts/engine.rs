@@ -0,0 +1,191 @@
+use super::{alert_configuration, deliver, load_alerts, observe, AlertConfig, AlertNotification};
+use crate::bus::get_tree_ancestors;
+use chrono::Local;
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+/// The state of one rule for one node, IP address or shaper.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertState {
+    pub rule: String,
+    pub id: String,
+    pub name: String,
+    pub value: f64,
+    pub trigger: f64,
+    /// Consecutive checks at or over the trigger.
+    #[serde(skip)]
+    breaches: u32,
+    pub active: bool,
+    /// When the alert was raised (RFC 3339).
+    pub since: String,
+}
+
+lazy_static! {
+    static ref ALERTS: RwLock<HashMap<(String, String), AlertState>> = RwLock::new(HashMap::new());
+}
+
+/// Alerts that are currently raised, oldest first.
+pub fn active_alerts() -> Vec<AlertState> {
+    let mut active: Vec<AlertState> = ALERTS
+        .read()
+        .values()
+        .filter(|a| a.active)
+        .cloned()
+        .collect();
+    active.sort_by(|a, b| a.since.cmp(&b.since));
+    active
+}
+
+/// Checks every rule every `check_secs`, forever, sending notifications as
+/// alerts are raised and cleared.
+pub async fn alert_runner() {
+    loop {
+        if let Err(e) = load_alerts() {
+            // A missing file just means alerting isn't set up.
+            if std::path::Path::new("alerts.ron").exists() {
+                println!("Unable to load alerts.ron: {e:?}");
+            }
+        }
+        let config = alert_configuration();
+        let notifications = check_rules(&config).await;
+        send_all(&config, &notifications).await;
+        rocket::tokio::time::sleep(Duration::from_secs(config.check_secs)).await;
+    }
+}
+
+/// Sends each notification to every target, logging failures.
+pub async fn send_all(config: &AlertConfig, notifications: &[AlertNotification]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for notification in notifications.iter() {
+        println!("Alert: {}", notification.summary());
+        for (i, target) in config.targets.iter().enumerate() {
+            if let Err(e) = deliver(target, notification).await {
+                let error = format!("Alert target {} failed: {e:?}", i + 1);
+                println!("{error}");
+                errors.push(error);
+            }
+        }
+    }
+    errors
+}
+
+/// Is the subject covered by a maintenance window that is in effect?
+fn in_maintenance(config: &AlertConfig, id: &str) -> bool {
+    let now = Local::now();
+    let windows: Vec<_> = config
+        .maintenance
+        .iter()
+        .filter(|w| w.is_active(now))
+        .collect();
+    if windows.is_empty() {
+        return false;
+    }
+    if windows.iter().any(|w| w.ids.is_empty()) {
+        return true;
+    }
+    let mut covered: HashSet<String> = HashSet::from([id.to_string()]);
+    if let Some(ancestors) = get_tree_ancestors(id) {
+        covered.extend(ancestors.into_iter().map(|a| a.id));
+    }
+    windows
+        .iter()
+        .any(|w| w.ids.iter().any(|i| covered.contains(i)))
+}
+
+async fn check_rules(config: &AlertConfig) -> Vec<AlertNotification> {
+    let mut notifications = Vec::new();
+    let now = Local::now().to_rfc3339();
+    let rule_names: HashSet<&str> = config.rules.iter().map(|r| r.name.as_str()).collect();
+
+    for rule in config.rules.iter() {
+        let observations = observe(rule).await;
+        let mut lock = ALERTS.write();
+        let mut seen = HashSet::new();
+        for obs in observations.into_iter() {
+            seen.insert(obs.id.clone());
+            if in_maintenance(config, &obs.id) {
+                continue;
+            }
+            let (trigger, clear) = match rule.thresholds(&obs.id) {
+                Some(thresholds) => thresholds,
+                None => {
+                    lock.remove(&(rule.name.clone(), obs.id.clone()));
+                    continue;
+                }
+            };
+            let state = lock
+                .entry((rule.name.clone(), obs.id.clone()))
+                .or_insert_with(|| AlertState {
+                    rule: rule.name.clone(),
+                    id: obs.id.clone(),
+                    name: obs.name.clone(),
+                    value: 0.0,
+                    trigger,
+                    breaches: 0,
+                    active: false,
+                    since: String::new(),
+                });
+            state.value = obs.value;
+            state.trigger = trigger;
+            if !state.active {
+                if obs.value >= trigger {
+                    state.breaches += 1;
+                    if state.breaches >= rule.for_checks.max(1) {
+                        state.active = true;
+                        state.since = now.clone();
+                        notifications.push(notify(state, "raised", &now));
+                    }
+                } else {
+                    state.breaches = 0;
+                }
+            } else if obs.value < clear {
+                state.active = false;
+                state.breaches = 0;
+                notifications.push(notify(state, "cleared", &now));
+            }
+        }
+
+        // Anything no longer reported (a resolved duplicate IP, a removed
+        // node) is cleared.
+        let gone: Vec<(String, String)> = lock
+            .keys()
+            .filter(|(r, id)| *r == rule.name && !seen.contains(id))
+            .cloned()
+            .collect();
+        for key in gone {
+            if in_maintenance(config, &key.1) {
+                continue;
+            }
+            if let Some(mut state) = lock.remove(&key) {
+                if state.active {
+                    state.value = 0.0;
+                    notifications.push(notify(&state, "cleared", &now));
+                }
+            }
+        }
+    }
+
+    // Forget the state of rules that have been removed.
+    ALERTS
+        .write()
+        .retain(|(rule, _), _| rule_names.contains(rule.as_str()));
+    notifications
+}
+
+fn notify(state: &AlertState, change: &str, now: &str) -> AlertNotification {
+    AlertNotification {
+        rule: state.rule.clone(),
+        state: change.to_string(),
+        id: state.id.clone(),
+        name: state.name.clone(),
+        value: state.value,
+        trigger: state.trigger,
+        time: now.to_string(),
+    }
+}
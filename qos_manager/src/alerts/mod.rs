@@ -0,0 +1,8 @@
+mod config;
+pub use config::*;
+mod observe;
+pub use observe::*;
+mod delivery;
+pub use delivery::*;
+mod engine;
+pub use engine::*;
@@ -0,0 +1,169 @@
+use super::{AlertLevel, AlertMetric, AlertRule};
+use crate::bus::{
+    duplicate_ip_list, get_queue_tree, shaper_feeds_last_seen, shaper_list, shapers_last_seen,
+    unmapped_client_list, SCHEDULED_FEEDS,
+};
+use crate::config::configuration;
+use crate::influx::{AggregateFunction, FluxDuration, FluxRange, InfluxQuery, QueryFilter};
+use crate::queries::{combine_shapers, InternetBandwidth};
+use crate::reports::{latency_over, peak_bandwidth_over};
+use chrono::{DateTime, Local};
+use lazy_static::*;
+use rocket::futures::future::join_all;
+use shared_rest::{NodeKind, QueueTreeEntry};
+
+lazy_static! {
+    static ref FIRST_STALE_CHECK: DateTime<Local> = Local::now();
+}
+
+/// One reading of a rule's metric, for a node, IP address or shaper.
+pub struct Observation {
+    /// Node ID, IP address or shaper ID.
+    pub id: String,
+    pub name: String,
+    pub value: f64,
+}
+
+impl Observation {
+    fn new(id: &str, name: &str, value: f64) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            value,
+        }
+    }
+}
+
+/// Reads the current value of a rule's metric for everything it applies to.
+pub async fn observe(rule: &AlertRule) -> Vec<Observation> {
+    let window = FluxDuration::seconds(rule.window_secs.clamp(60, 86_400));
+    match rule.metric {
+        AlertMetric::Congestion(level) => {
+            let nodes = nodes_at(level);
+            let peaks = join_all(
+                nodes
+                    .iter()
+                    .map(|n| peak_bandwidth_over(n.id.clone(), window.clone())),
+            )
+            .await;
+            nodes
+                .iter()
+                .zip(peaks)
+                .map(|(n, (down, up))| {
+                    let down_pct = percent(down as f64, n.down_mbps);
+                    let up_pct = percent(up as f64, n.up_mbps);
+                    Observation::new(&n.id, &n.name, f64::max(down_pct, up_pct))
+                })
+                .collect()
+        }
+        AlertMetric::MedianLatency(level) | AlertMetric::PeakLatency(level) => {
+            let function = match rule.metric {
+                AlertMetric::PeakLatency(_) => AggregateFunction::Max,
+                _ => AggregateFunction::Median,
+            };
+            let nodes = nodes_at(level);
+            let latencies = join_all(
+                nodes
+                    .iter()
+                    .map(|n| latency_over(&n.id, window.clone(), function.clone())),
+            )
+            .await;
+            nodes
+                .iter()
+                .zip(latencies)
+                .map(|(n, latency)| Observation::new(&n.id, &n.name, latency))
+                .collect()
+        }
+        AlertMetric::DropRate(level) => {
+            let nodes = nodes_at(level);
+            let rates = join_all(nodes.iter().map(|n| drop_rate(&n.id, window.clone()))).await;
+            nodes
+                .iter()
+                .zip(rates)
+                .map(|(n, rate)| Observation::new(&n.id, &n.name, rate))
+                .collect()
+        }
+        AlertMetric::DuplicateIp => duplicate_ip_list()
+            .iter()
+            .map(|ip| Observation::new(ip, ip, 1.0))
+            .collect(),
+        AlertMetric::UnmappedClient => unmapped_client_list()
+            .iter()
+            .map(|client| Observation::new(client, client, 1.0))
+            .collect(),
+        AlertMetric::ShaperSilent => {
+            let now = Local::now();
+            shapers_last_seen()
+                .iter()
+                .map(|(shaper, seen)| {
+                    let silent = (now - *seen).num_seconds().max(0) as f64;
+                    Observation::new(shaper, shaper, silent)
+                })
+                .collect()
+        }
+        AlertMetric::StaleFeed => {
+            // Feeds never received since startup count from the first check,
+            // so a fresh manager doesn't alert at once.
+            let now = Local::now();
+            let never_seen = (now - *FIRST_STALE_CHECK).num_seconds().max(0) as f64;
+            let feeds = shaper_feeds_last_seen();
+            let mut observations = Vec::new();
+            for shaper in shaper_list() {
+                for feed in SCHEDULED_FEEDS {
+                    let id = format!("{shaper}/{feed}");
+                    let age = feeds
+                        .get(&shaper)
+                        .and_then(|f| f.get(feed))
+                        .map(|seen| (now - *seen).num_seconds().max(0) as f64)
+                        .unwrap_or(never_seen);
+                    observations.push(Observation::new(&id, &id, age));
+                }
+            }
+            observations
+        }
+    }
+}
+
+fn nodes_at(level: AlertLevel) -> Vec<QueueTreeEntry> {
+    get_queue_tree()
+        .into_iter()
+        .filter(|n| match level {
+            AlertLevel::Site => n.level_type == NodeKind::Tower,
+            AlertLevel::AccessPoint => n.level_type == NodeKind::AccessPoint,
+            AlertLevel::Client => n.level_type.is_client_queue(),
+        })
+        .collect()
+}
+
+fn percent(mbps: f64, limit: u32) -> f64 {
+    if limit == 0 {
+        0.0
+    } else {
+        mbps * 100.0 / limit as f64
+    }
+}
+
+/// Average packets dropped per second over the window. Each report holds the
+/// drops since the previous one, which is assumed to be
+/// `stats_resolution_secs` earlier.
+async fn drop_rate(id: &str, window: FluxDuration) -> f64 {
+    let result = InfluxQuery::new()
+        .with_range(&FluxRange::last(window))
+        .with_aggregate(&FluxDuration::days(2), AggregateFunction::Mean)
+        .with_measurement("queues")
+        .with_filter(QueryFilter::Either {
+            field: "_field".to_string(),
+            value_1: "down_drops".to_string(),
+            value_2: "up_drops".to_string(),
+        })
+        .with_filter(QueryFilter::tag("site", id))
+        .run::<InternetBandwidth>()
+        .await;
+    match result {
+        Ok(points) => combine_shapers(&points.0)
+            .pop()
+            .map(|drops| (drops.down + drops.up) / configuration().stats_resolution_secs as f64)
+            .unwrap_or(0.0),
+        Err(_) => 0.0,
+    }
+}
@@ -0,0 +1,82 @@
+use super::{find_user, Role, User};
+use rocket::{
+    http::{Cookie, CookieJar, SameSite, Status},
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+/// Name of the (encrypted) session cookie.
+const SESSION_COOKIE: &str = "bqos_session";
+
+/// Sessions expire after 12 hours, forcing a new login.
+const SESSION_SECONDS: i64 = 12 * 60 * 60;
+
+/// Sets the session cookie after a successful login. The cookie is private,
+/// so Rocket encrypts and authenticates it with the configured `secret_key`.
+pub fn start_session(cookies: &CookieJar<'_>, user: &User) {
+    let now = chrono::Utc::now().timestamp();
+    let mut cookie = Cookie::new(SESSION_COOKIE, format!("{}:{now}", user.username));
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Strict);
+    cookies.add_private(cookie);
+}
+
+pub fn end_session(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::from(SESSION_COOKIE));
+}
+
+/// Finds the logged-in user for a request. The role is always read from the
+/// current user list, so demoting or deleting a user takes effect at once.
+fn session_user(request: &Request<'_>) -> Option<User> {
+    let cookie = request.cookies().get_private(SESSION_COOKIE)?;
+    let (username, issued) = cookie.value().rsplit_once(':')?;
+    let issued = issued.parse::<i64>().ok()?;
+    if chrono::Utc::now().timestamp() - issued > SESSION_SECONDS {
+        return None;
+    }
+    find_user(username)
+}
+
+fn authorize(request: &Request<'_>, role: Role) -> Outcome<User, ()> {
+    match session_user(request) {
+        None => Outcome::Error((Status::Unauthorized, ())),
+        Some(user) if user.role < role => Outcome::Error((Status::Forbidden, ())),
+        Some(user) => Outcome::Success(user),
+    }
+}
+
+/// Request guard: any logged-in user (support staff and up).
+pub struct ReadOnlyUser(pub User);
+
+/// Request guard: NOC operators and administrators.
+pub struct OperatorUser(pub User);
+
+/// Request guard: administrators only.
+pub struct AdminUser(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadOnlyUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Role::ReadOnly).map(ReadOnlyUser)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OperatorUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Role::Operator).map(OperatorUser)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Role::Admin).map(AdminUser)
+    }
+}
@@ -0,0 +1,6 @@
+mod users;
+pub use users::*;
+mod guards;
+pub use guards::*;
+mod routes;
+pub use routes::*;
@@ -0,0 +1,124 @@
+use super::{
+    check_password, delete_user, end_session, set_user, start_session, user_list, AdminUser,
+    ReadOnlyUser, Role,
+};
+use crate::store::audit;
+use rocket::{
+    fs::NamedFile,
+    http::{CookieJar, Status},
+    response::Redirect,
+    serde::{json::Json, Deserialize, Serialize},
+};
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SetUserRequest {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+/// A user as reported to the web UI (never includes the password hash).
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserInfo {
+    pub username: String,
+    pub role: Role,
+}
+
+/// Opens the login page
+#[get("/login")]
+pub async fn login_page<'a>() -> Option<NamedFile> {
+    NamedFile::open("static/login.html").await.ok()
+}
+
+/// Opens the user administration page
+#[get("/users")]
+pub async fn users_page<'a>(_user: AdminUser) -> Option<NamedFile> {
+    NamedFile::open("static/users.html").await.ok()
+}
+
+#[post("/auth/login", data = "<login>")]
+pub async fn login(login: Json<LoginRequest>, cookies: &CookieJar<'_>) -> Status {
+    if let Some(user) = check_password(&login.username, &login.password) {
+        start_session(cookies, &user);
+        Status::Ok
+    } else {
+        println!("Failed login attempt for {}", login.username);
+        Status::Unauthorized
+    }
+}
+
+#[get("/auth/logout")]
+pub async fn logout(cookies: &CookieJar<'_>) -> Redirect {
+    end_session(cookies);
+    Redirect::to("/login")
+}
+
+#[get("/auth/whoami")]
+pub async fn whoami(user: ReadOnlyUser) -> Json<UserInfo> {
+    Json(UserInfo {
+        username: user.0.username,
+        role: user.0.role,
+    })
+}
+
+#[get("/auth/users")]
+pub async fn list_users(_user: AdminUser) -> Json<Vec<UserInfo>> {
+    Json(
+        user_list()
+            .drain(0..)
+            .map(|u| UserInfo {
+                username: u.username,
+                role: u.role,
+            })
+            .collect(),
+    )
+}
+
+#[post("/auth/users", data = "<request>")]
+pub async fn add_user(user: AdminUser, request: Json<SetUserRequest>) -> Status {
+    match set_user(&request.username, &request.password, request.role) {
+        Ok(_) => {
+            println!("{} updated user {}", user.0.username, request.username);
+            audit(
+                &user.0.username,
+                "set_user",
+                &format!("{} ({:?})", request.username, request.role),
+            );
+            Status::Ok
+        }
+        Err(e) => {
+            println!("{:?}", e);
+            Status::BadRequest
+        }
+    }
+}
+
+#[delete("/auth/users/<username>")]
+pub async fn remove_user(user: AdminUser, username: String) -> Status {
+    match delete_user(&username) {
+        Ok(_) => {
+            println!("{} removed user {username}", user.0.username);
+            audit(&user.0.username, "remove_user", &username);
+            Status::Ok
+        }
+        Err(e) => {
+            println!("{:?}", e);
+            Status::BadRequest
+        }
+    }
+}
+
+/// Anyone who isn't logged in is sent to the login page.
+#[catch(401)]
+pub fn unauthorized() -> Redirect {
+    Redirect::to("/login")
+}
@@ -0,0 +1,196 @@
+use anyhow::{Error, Result};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use lazy_static::*;
+use parking_lot::{RwLock, RwLockWriteGuard};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use rocket::serde::{Deserialize, Serialize};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use std::{path::Path, time::Duration};
+
+/// Access levels for the manager. Each level includes everything
+/// permitted by the levels below it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(crate = "rocket::serde")]
+pub enum Role {
+    /// Support staff: can view pages, graphs and reports.
+    ReadOnly,
+    /// NOC operators: can also change site and AP limits.
+    Operator,
+    /// Administrators: can also manage user accounts.
+    Admin,
+}
+
+/// A manager user account. Passwords are only ever stored as Argon2 hashes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct User {
+    pub username: String,
+    pub password_hash: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct UserList {
+    pub users: Vec<User>,
+}
+
+lazy_static! {
+    static ref USERS: RwLock<UserList> = RwLock::new(UserList { users: Vec::new() });
+}
+
+const USERS_FILENAME: &str = "users.ron";
+
+/// Loads `users.ron` from the working directory. If there are no users at all,
+/// an `admin` account is created with a random password, which is printed to
+/// the console so that the first login is possible.
+pub fn load_users() -> Result<()> {
+    let mut users = if Path::new(USERS_FILENAME).exists() {
+        let data = std::fs::read_to_string(USERS_FILENAME)?;
+        ron::from_str(&data)?
+    } else {
+        UserList { users: Vec::new() }
+    };
+
+    let created_admin = users.users.is_empty();
+    if created_admin {
+        let password: String = OsRng
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        users.users.push(User {
+            username: "admin".to_string(),
+            password_hash: hash_password(&password)?,
+            role: Role::Admin,
+        });
+        println!("No users found. Created user 'admin' with password '{password}'");
+        println!("Please log in and change it.");
+    }
+
+    *lock_users()? = users;
+    // Only a newly created admin needs writing; a plain load is already on disk.
+    if created_admin {
+        save_users()?;
+    }
+    Ok(())
+}
+
+fn save_users() -> Result<()> {
+    let data = USERS.read().clone();
+    let users_ron = to_string_pretty(&data, PrettyConfig::new())?;
+    std::fs::write(USERS_FILENAME, users_ron)?;
+    Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| Error::msg(e.to_string()))?;
+    Ok(hash.to_string())
+}
+
+/// Checks a username/password pair, returning the matching user if the
+/// password is correct.
+pub fn check_password(username: &str, password: &str) -> Option<User> {
+    let user = find_user(username)?;
+    let hash = PasswordHash::new(&user.password_hash).ok()?;
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+    {
+        Some(user)
+    } else {
+        None
+    }
+}
+
+pub fn find_user(username: &str) -> Option<User> {
+    USERS
+        .read()
+        .users
+        .iter()
+        .find(|u| u.username == username)
+        .cloned()
+}
+
+pub fn user_list() -> Vec<User> {
+    USERS.read().users.clone()
+}
+
+/// Adds a user, or replaces the password and role of an existing one.
+/// Refuses to demote the last administrator.
+pub fn set_user(username: &str, password: &str, role: Role) -> Result<()> {
+    if username.is_empty() || password.is_empty() {
+        return Err(Error::msg("Username and password are required"));
+    }
+    let password_hash = hash_password(password)?;
+    let mut lock = lock_users()?;
+    if role != Role::Admin && remaining_admins(&lock, username) == 0 {
+        return Err(Error::msg("Cannot demote the last administrator"));
+    }
+    if let Some(user) = lock.users.iter_mut().find(|u| u.username == username) {
+        user.password_hash = password_hash;
+        user.role = role;
+    } else {
+        lock.users.push(User {
+            username: username.to_string(),
+            password_hash,
+            role,
+        });
+    }
+    drop(lock);
+    save_users()
+}
+
+/// Writes a replacement for every user account beside `users.ron`, returning
+/// its path, so that a restore can install it once everything else has
+/// succeeded.
+pub fn stage_users(users: &[User]) -> Result<String> {
+    if !users.iter().any(|u| u.role == Role::Admin) {
+        return Err(Error::msg("At least one administrator is required"));
+    }
+    let users = UserList {
+        users: users.to_vec(),
+    };
+    let staged = format!("{USERS_FILENAME}.restore");
+    std::fs::write(&staged, to_string_pretty(&users, PrettyConfig::new())?)?;
+    Ok(staged)
+}
+
+/// Moves a file written by `stage_users` over `users.ron`, and loads it.
+pub fn install_users(staged: &str) -> Result<()> {
+    std::fs::rename(staged, USERS_FILENAME)?;
+    load_users()
+}
+
+/// Removes a user. Refuses to remove the last administrator, which would
+/// lock everyone out of user management.
+pub fn delete_user(username: &str) -> Result<()> {
+    let mut lock = lock_users()?;
+    if remaining_admins(&lock, username) == 0 {
+        return Err(Error::msg("Cannot remove the last administrator"));
+    }
+    lock.users.retain(|u| u.username != username);
+    drop(lock);
+    save_users()
+}
+
+fn lock_users() -> Result<RwLockWriteGuard<'static, UserList>> {
+    USERS
+        .try_write_for(Duration::from_secs(2))
+        .ok_or_else(|| Error::msg("The user list is busy; please try again"))
+}
+
+/// The administrators other than `username`.
+fn remaining_admins(users: &UserList, username: &str) -> usize {
+    users
+        .users
+        .iter()
+        .filter(|u| u.role == Role::Admin && u.username != username)
+        .count()
+}
@@ -0,0 +1,188 @@
+use crate::alerts::{install_alerts, read_alerts_file, stage_alerts, AlertConfig};
+use crate::auth::{install_users, stage_users, user_list, Role, User};
+use crate::bus::{load_config, load_tree};
+use crate::reports::{ApReport, SiteReport};
+use crate::store::{all_nightly_reports, audit, load_limits, load_trees, restore_state};
+use anyhow::{Error, Result};
+use argon2::password_hash::PasswordHash;
+use chrono::{DateTime, Local};
+use rocket::serde::{Deserialize, Serialize};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use shared_rest::{QueueTreeEntry, ShaperTreeConfig};
+use std::collections::{BTreeMap, HashSet};
+
+/// The archive format this build writes. Archives from newer managers are
+/// refused; older ones are read with defaults for anything they lack.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Everything needed to rebuild a manager: its speed limits, each shaper's
+/// last tree, the nightly report history, alert rules (with their
+/// overrides, targets and maintenance windows) and user accounts.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct BackupArchive {
+    pub archive_version: u32,
+    /// When the archive was made (RFC 3339).
+    pub created: String,
+    pub manager_version: String,
+    #[serde(default = "ShaperTreeConfig::new")]
+    pub limits: ShaperTreeConfig,
+    #[serde(default)]
+    pub trees: BTreeMap<String, Vec<QueueTreeEntry>>,
+    #[serde(default)]
+    pub nightly: Vec<(String, ApReport, SiteReport)>,
+    /// `None` if the manager had no `alerts.ron`.
+    #[serde(default)]
+    pub alerts: Option<AlertConfig>,
+    #[serde(default)]
+    pub users: Vec<User>,
+}
+
+/// Just enough of an archive to check its version before reading the rest.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ArchiveHeader {
+    archive_version: u32,
+}
+
+impl BackupArchive {
+    /// Gathers the manager's current state.
+    pub fn export() -> Result<Self> {
+        Ok(Self {
+            archive_version: ARCHIVE_VERSION,
+            created: Local::now().to_rfc3339(),
+            manager_version: env!("CARGO_PKG_VERSION").to_string(),
+            limits: load_limits()?,
+            trees: load_trees()?,
+            nightly: all_nightly_reports()?,
+            alerts: read_alerts_file()?,
+            users: user_list(),
+        })
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(to_string_pretty(self, PrettyConfig::new())?)
+    }
+
+    /// Reads an archive, refusing ones written by a newer manager.
+    pub fn parse(data: &str) -> Result<Self> {
+        let header: ArchiveHeader =
+            ron::from_str(data).map_err(|e| Error::msg(format!("Not a manager backup: {e}")))?;
+        if header.archive_version > ARCHIVE_VERSION {
+            return Err(Error::msg(format!(
+                "The backup is archive v{}, but this manager only reads up to v{ARCHIVE_VERSION}. Upgrade qos_manager.",
+                header.archive_version
+            )));
+        }
+        Ok(ron::from_str(data)?)
+    }
+
+    /// Anything that would leave the manager broken if restored. An empty
+    /// list means the archive can be restored.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !self.users.iter().any(|u| u.role == Role::Admin) {
+            problems.push("There are no administrators in the backup".to_string());
+        }
+        let mut usernames = HashSet::new();
+        for user in self.users.iter() {
+            if user.username.is_empty() {
+                problems.push("A user has no username".to_string());
+            } else if !usernames.insert(user.username.as_str()) {
+                problems.push(format!("User {} is listed twice", user.username));
+            }
+            if PasswordHash::new(&user.password_hash).is_err() {
+                problems.push(format!(
+                    "User {} has an invalid password hash",
+                    user.username
+                ));
+            }
+        }
+
+        let mut ids = HashSet::new();
+        for site in self.limits.sites.iter() {
+            if !ids.insert(site.id.as_str()) {
+                problems.push(format!("Site {} has more than one limit", site.id));
+            }
+        }
+        let mut ids = HashSet::new();
+        for ap in self.limits.access_points.iter() {
+            if !ids.insert(ap.id.as_str()) {
+                problems.push(format!("Access point {} has more than one limit", ap.id));
+            }
+        }
+
+        for (shaper_id, tree) in self.trees.iter() {
+            if shaper_id.is_empty() {
+                problems.push("A tree has no shaper ID".to_string());
+            }
+            for (i, node) in tree.iter().enumerate() {
+                if let Some(parent) = node.parent {
+                    if parent >= tree.len() || parent == i {
+                        problems.push(format!(
+                            "Node {} in shaper {shaper_id}'s tree has an invalid parent",
+                            node.id
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (date, _, _) in self.nightly.iter() {
+            if DateTime::parse_from_rfc3339(date).is_err() {
+                problems.push(format!("Nightly report date {date} is invalid"));
+            }
+        }
+
+        if let Some(alerts) = &self.alerts {
+            let mut names = HashSet::new();
+            for rule in alerts.rules.iter() {
+                if !names.insert(rule.name.as_str()) {
+                    problems.push(format!("Alert rule {} is listed twice", rule.name));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Replaces the manager's state with the archive's, and reloads it. An
+    /// archive without alert rules leaves `alerts.ron` alone.
+    ///
+    /// The users and alert rules are written out first, and only moved into
+    /// place once the database has been restored, so that a failure part way
+    /// through leaves the manager as it was.
+    pub fn restore(&self, username: &str) -> Result<()> {
+        let problems = self.problems();
+        if !problems.is_empty() {
+            return Err(Error::msg(problems.join("; ")));
+        }
+        let users = stage_users(&self.users)?;
+        let alerts = match self.alerts.as_ref().map(stage_alerts).transpose() {
+            Ok(alerts) => alerts,
+            Err(e) => {
+                let _ = std::fs::remove_file(&users);
+                return Err(e);
+            }
+        };
+        if let Err(e) = restore_state(&self.limits, &self.trees, &self.nightly) {
+            for staged in std::iter::once(&users).chain(alerts.iter()) {
+                let _ = std::fs::remove_file(staged);
+            }
+            return Err(e);
+        }
+        install_users(&users)?;
+        if let Some(alerts) = alerts {
+            install_alerts(&alerts)?;
+        }
+        load_config()?;
+        load_tree()?;
+        audit(
+            username,
+            "restore",
+            &format!("Backup made {} by v{}", self.created, self.manager_version),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use super::{BackupArchive, RestorePreview};
+use anyhow::{Error, Result};
+
+const USAGE: &str = "Usage:
+    qos_manager backup <file>             Write a backup archive
+    qos_manager restore <file>            Show what restoring <file> would change
+    qos_manager restore <file> --apply    Restore <file>";
+
+/// Runs `backup` or `restore` if they were given on the command line,
+/// returning the exit code. Returns `None` for anything else, so that the
+/// manager starts as usual.
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    let result = match args.get(1).map(|a| a.as_str()) {
+        Some("backup") => backup(args),
+        Some("restore") => restore(args),
+        _ => return None,
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("{e}");
+            1
+        }
+    })
+}
+
+fn backup(args: &[String]) -> Result<()> {
+    let path = args.get(2).ok_or_else(|| Error::msg(USAGE))?;
+    let archive = BackupArchive::export()?;
+    std::fs::write(path, archive.to_ron()?)?;
+    println!(
+        "Wrote {path}: {} site and {} access point limits, {} shaper trees, {} nightly reports, {} users{}",
+        archive.limits.sites.len(),
+        archive.limits.access_points.len(),
+        archive.trees.len(),
+        archive.nightly.len(),
+        archive.users.len(),
+        if archive.alerts.is_some() { " and alert rules" } else { "" }
+    );
+    Ok(())
+}
+
+fn restore(args: &[String]) -> Result<()> {
+    let path = args.get(2).ok_or_else(|| Error::msg(USAGE))?;
+    let apply = match args.get(3).map(|a| a.as_str()) {
+        None => false,
+        Some("--apply") => true,
+        Some(_) => return Err(Error::msg(USAGE)),
+    };
+    let archive = BackupArchive::parse(&std::fs::read_to_string(path)?)?;
+    let preview = RestorePreview::new(&archive)?;
+    println!("{preview}");
+    if !preview.problems.is_empty() {
+        return Err(Error::msg("Nothing was restored."));
+    }
+    if !apply {
+        println!("Nothing has been changed. Run again with --apply to restore {path}.");
+        return Ok(());
+    }
+    archive.restore("cli")?;
+    println!("Restored {path}.");
+    Ok(())
+}
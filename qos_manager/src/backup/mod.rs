@@ -0,0 +1,11 @@
+//! Backup and restore of the manager's configuration and history, as a
+//! single versioned RON archive. See `BackupArchive`.
+
+mod archive;
+pub use archive::*;
+mod preview;
+pub use preview::*;
+mod routes;
+pub use routes::*;
+mod cli;
+pub use cli::*;
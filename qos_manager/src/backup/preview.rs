@@ -0,0 +1,242 @@
+use super::BackupArchive;
+use crate::alerts::{read_alerts_file, AlertConfig};
+use crate::auth::{user_list, Role, User};
+use crate::reports::{ApReport, SiteReport};
+use crate::store::{all_nightly_reports, load_limits, load_trees};
+use anyhow::Result;
+use rocket::serde::Serialize;
+use ron::ser::to_string;
+use shared_rest::{count_tree_changes, QueueTreeEntry, ShaperTreeConfig};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What restoring an archive would change in one part of the manager.
+#[derive(Serialize, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct SectionPreview {
+    pub section: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: usize,
+    /// Set if the section won't be touched.
+    pub note: Option<String>,
+}
+
+/// What restoring an archive would change, and anything stopping it.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct RestorePreview {
+    pub archive_version: u32,
+    pub created: String,
+    pub manager_version: String,
+    pub sections: Vec<SectionPreview>,
+    /// The archive can only be restored if this is empty.
+    pub problems: Vec<String>,
+}
+
+impl RestorePreview {
+    /// Compares an archive with the manager's current state.
+    pub fn new(archive: &BackupArchive) -> Result<Self> {
+        Ok(Self {
+            archive_version: archive.archive_version,
+            created: archive.created.clone(),
+            manager_version: archive.manager_version.clone(),
+            sections: vec![
+                limits_preview(&load_limits()?, &archive.limits),
+                trees_preview(&load_trees()?, &archive.trees),
+                nightly_preview(&all_nightly_reports()?, &archive.nightly),
+                alerts_preview(read_alerts_file()?, archive.alerts.as_ref()),
+                users_preview(&user_list(), &archive.users),
+            ],
+            problems: archive.problems(),
+        })
+    }
+}
+
+impl fmt::Display for RestorePreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Backup made {} by qos_manager v{} (archive v{})",
+            self.created, self.manager_version, self.archive_version
+        )?;
+        for section in self.sections.iter() {
+            write!(f, "\n{}: ", section.section)?;
+            if let Some(note) = &section.note {
+                writeln!(f, "{note}")?;
+                continue;
+            }
+            writeln!(
+                f,
+                "{} added, {} removed, {} changed, {} unchanged",
+                section.added.len(),
+                section.removed.len(),
+                section.changed.len(),
+                section.unchanged
+            )?;
+            for item in section.added.iter() {
+                writeln!(f, "  + {item}")?;
+            }
+            for item in section.removed.iter() {
+                writeln!(f, "  - {item}")?;
+            }
+            for item in section.changed.iter() {
+                writeln!(f, "  ~ {item}")?;
+            }
+        }
+        if !self.problems.is_empty() {
+            writeln!(f, "\nThe backup can't be restored:")?;
+            for problem in self.problems.iter() {
+                writeln!(f, "  ! {problem}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares two keyed collections. `describe` names a changed item, given
+/// its current and restored values.
+fn compare<V: PartialEq>(
+    section: &str,
+    current: &BTreeMap<String, V>,
+    restored: &BTreeMap<String, V>,
+    describe: impl Fn(&str, &V, &V) -> String,
+) -> SectionPreview {
+    let mut preview = SectionPreview {
+        section: section.to_string(),
+        ..Default::default()
+    };
+    for (key, value) in restored.iter() {
+        match current.get(key) {
+            None => preview.added.push(key.clone()),
+            Some(old) if old != value => preview.changed.push(describe(key, old, value)),
+            Some(_) => preview.unchanged += 1,
+        }
+    }
+    for key in current.keys().filter(|k| !restored.contains_key(*k)) {
+        preview.removed.push(key.clone());
+    }
+    preview
+}
+
+fn limits_preview(current: &ShaperTreeConfig, restored: &ShaperTreeConfig) -> SectionPreview {
+    let by_id = |cfg: &ShaperTreeConfig| -> BTreeMap<String, (u32, u32)> {
+        cfg.sites
+            .iter()
+            .map(|s| (format!("site {}", s.id), (s.download, s.upload)))
+            .chain(
+                cfg.access_points
+                    .iter()
+                    .map(|a| (format!("access point {}", a.id), (a.download, a.upload))),
+            )
+            .collect()
+    };
+    compare(
+        "Speed limits",
+        &by_id(current),
+        &by_id(restored),
+        |id, old, new| format!("{id}: {}/{} to {}/{} Mbps", old.0, old.1, new.0, new.1),
+    )
+}
+
+fn trees_preview(
+    current: &BTreeMap<String, Vec<QueueTreeEntry>>,
+    restored: &BTreeMap<String, Vec<QueueTreeEntry>>,
+) -> SectionPreview {
+    // Trees are compared by their serialized form, since entries can't be
+    // compared directly.
+    let serialized = |trees: &BTreeMap<String, Vec<QueueTreeEntry>>| {
+        trees
+            .iter()
+            .map(|(id, tree)| (id.clone(), to_string(tree).unwrap_or_default()))
+            .collect::<BTreeMap<String, String>>()
+    };
+    compare(
+        "Shaper trees",
+        &serialized(current),
+        &serialized(restored),
+        |id, _, _| {
+            let (added, removed, changed) = count_tree_changes(&current[id], &restored[id]);
+            format!("{id}: {added} nodes added, {removed} removed, {changed} changed")
+        },
+    )
+}
+
+fn nightly_preview(
+    current: &[(String, ApReport, SiteReport)],
+    restored: &[(String, ApReport, SiteReport)],
+) -> SectionPreview {
+    let dates = |reports: &[(String, ApReport, SiteReport)]| {
+        reports
+            .iter()
+            .map(|(date, ap, site)| (date.clone(), to_string(&(ap, site)).unwrap_or_default()))
+            .collect::<BTreeMap<String, String>>()
+    };
+    compare(
+        "Nightly reports",
+        &dates(current),
+        &dates(restored),
+        |date, _, _| date.to_string(),
+    )
+}
+
+fn alerts_preview(current: Option<AlertConfig>, restored: Option<&AlertConfig>) -> SectionPreview {
+    let restored = match restored {
+        Some(restored) => restored,
+        None => {
+            return SectionPreview {
+                section: "Alerts".to_string(),
+                note: Some("not in the backup; alerts.ron will be left as it is".to_string()),
+                ..Default::default()
+            }
+        }
+    };
+    let items = |cfg: &AlertConfig| -> BTreeMap<String, String> {
+        let mut items: BTreeMap<String, String> = cfg
+            .rules
+            .iter()
+            .map(|r| (format!("rule {}", r.name), to_string(r).unwrap_or_default()))
+            .collect();
+        items.insert("check interval".to_string(), cfg.check_secs.to_string());
+        items.insert(
+            "targets".to_string(),
+            to_string(&cfg.targets).unwrap_or_default(),
+        );
+        items.insert(
+            "maintenance windows".to_string(),
+            to_string(&cfg.maintenance).unwrap_or_default(),
+        );
+        items
+    };
+    compare(
+        "Alerts",
+        &current.as_ref().map(items).unwrap_or_default(),
+        &items(restored),
+        |item, _, _| item.to_string(),
+    )
+}
+
+fn users_preview(current: &[User], restored: &[User]) -> SectionPreview {
+    let by_name = |users: &[User]| -> BTreeMap<String, (Role, String)> {
+        users
+            .iter()
+            .map(|u| (u.username.clone(), (u.role, u.password_hash.clone())))
+            .collect()
+    };
+    compare(
+        "Users",
+        &by_name(current),
+        &by_name(restored),
+        |username, old, new| {
+            let mut changes = Vec::new();
+            if old.0 != new.0 {
+                changes.push(format!("role {:?} to {:?}", old.0, new.0));
+            }
+            if old.1 != new.1 {
+                changes.push("password".to_string());
+            }
+            format!("{username}: {}", changes.join(", "))
+        },
+    )
+}
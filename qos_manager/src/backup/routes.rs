@@ -0,0 +1,110 @@
+use super::{BackupArchive, RestorePreview};
+use crate::auth::AdminUser;
+use rocket::{
+    data::{Data, ToByteUnit},
+    http::{Header, Status},
+    serde::json::Json,
+};
+
+/// A backup archive, sent as a file download.
+#[derive(Responder)]
+#[response(content_type = "text/plain")]
+pub struct BackupFile {
+    archive: String,
+    disposition: Header<'static>,
+}
+
+/// Downloads a backup of the manager.
+#[get("/admin/backup")]
+pub async fn backup_download(user: AdminUser) -> Result<BackupFile, (Status, String)> {
+    let archive = BackupArchive::export()
+        .and_then(|a| a.to_ron())
+        .map_err(|e| (Status::InternalServerError, e.to_string()))?;
+    println!("{} downloaded a backup", user.0.username);
+    let filename = format!(
+        "qos_manager_backup_{}.ron",
+        chrono::Local::now().format("%Y%m%d_%H%M%S")
+    );
+    Ok(BackupFile {
+        archive,
+        disposition: Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"{filename}\""),
+        ),
+    })
+}
+
+/// The largest archive accepted for restore.
+const MAX_ARCHIVE_MIB: usize = 256;
+
+async fn read_archive(data: Data<'_>) -> Result<BackupArchive, (Status, String)> {
+    let body = match data.open(MAX_ARCHIVE_MIB.mebibytes()).into_string().await {
+        Ok(body) if body.is_complete() => body.into_inner(),
+        Ok(_) => {
+            return Err((
+                Status::PayloadTooLarge,
+                "The backup is too large".to_string(),
+            ))
+        }
+        Err(e) => return Err((Status::BadRequest, e.to_string())),
+    };
+    BackupArchive::parse(&body).map_err(|e| (Status::BadRequest, e.to_string()))
+}
+
+/// Shows what restoring the posted archive would change, without changing
+/// anything.
+#[post("/admin/restore/preview", data = "<data>")]
+pub async fn restore_preview(
+    _user: AdminUser,
+    data: Data<'_>,
+) -> Result<Json<RestorePreview>, (Status, String)> {
+    let archive = read_archive(data).await?;
+    RestorePreview::new(&archive)
+        .map(Json)
+        .map_err(|e| (Status::InternalServerError, e.to_string()))
+}
+
+/// Restores the posted archive, replacing the manager's limits, trees,
+/// nightly history, alert rules and users. Returns what was changed. An
+/// archive with problems is refused, and nothing is changed.
+#[post("/admin/restore", data = "<data>")]
+pub async fn restore_archive(
+    user: AdminUser,
+    data: Data<'_>,
+) -> Result<Json<RestorePreview>, (Status, Json<RestorePreview>)> {
+    let archive = read_archive(data)
+        .await
+        .map_err(|(status, e)| (status, Json(error_preview(e))))?;
+    let preview = RestorePreview::new(&archive).map_err(|e| {
+        (
+            Status::InternalServerError,
+            Json(error_preview(e.to_string())),
+        )
+    })?;
+    if !preview.problems.is_empty() {
+        return Err((Status::UnprocessableEntity, Json(preview)));
+    }
+    if let Err(e) = archive.restore(&user.0.username) {
+        println!("Restore failed: {e:?}");
+        return Err((
+            Status::InternalServerError,
+            Json(error_preview(e.to_string())),
+        ));
+    }
+    println!(
+        "{} restored a backup made {}",
+        user.0.username, archive.created
+    );
+    Ok(Json(preview))
+}
+
+/// A preview that just carries an error.
+fn error_preview(error: String) -> RestorePreview {
+    RestorePreview {
+        archive_version: 0,
+        created: String::new(),
+        manager_version: String::new(),
+        sections: Vec::new(),
+        problems: vec![error],
+    }
+}
@@ -0,0 +1,290 @@
+use super::shaper_seen;
+use crate::{
+    configuration,
+    prometheus::{record_bus_message, record_bus_rejection},
+};
+use rocket::{
+    data::{self, Data, FromData, Limits},
+    http::Status,
+    request::{self, FromRequest},
+    serde::de::DeserializeOwned,
+    Request,
+};
+use shared_rest::{
+    verify_bus_message, BUS_MAX_CLOCK_SKEW, BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_HEADER,
+    BUS_PROTOCOL_VERSION, BUS_SHAPER_HEADER, BUS_SIGNATURE_HEADER, BUS_TIMESTAMP_HEADER,
+};
+use std::ops::Deref;
+
+/// Shaper IDs end up in Influx tags and file names, so keep them simple.
+fn is_valid_shaper_id(shaper_id: &str) -> bool {
+    !shaper_id.is_empty()
+        && shaper_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Checks that a bus request was signed with one of the configured `bus_keys`,
+/// recently enough to not be a replay. Returns the sending shaper's ID.
+fn signed_by(request: &Request<'_>, body: &[u8]) -> Option<String> {
+    let headers = request.headers();
+    let shaper_id = headers.get_one(BUS_SHAPER_HEADER)?;
+    let timestamp = headers.get_one(BUS_TIMESTAMP_HEADER)?.parse::<i64>().ok()?;
+    let signature = headers.get_one(BUS_SIGNATURE_HEADER)?;
+    if is_signed(
+        &configuration().bus_keys,
+        shaper_id,
+        timestamp,
+        request.uri().path().as_str(),
+        body,
+        signature,
+        chrono::Utc::now().timestamp(),
+    ) {
+        Some(shaper_id.to_string())
+    } else {
+        None
+    }
+}
+
+/// The checks behind `signed_by`, given the time (Unix seconds) to measure
+/// the message's age from.
+fn is_signed(
+    keys: &[String],
+    shaper_id: &str,
+    timestamp: i64,
+    path: &str,
+    body: &[u8],
+    signature: &str,
+    now: i64,
+) -> bool {
+    is_valid_shaper_id(shaper_id)
+        && (now - timestamp).abs() <= BUS_MAX_CLOCK_SKEW
+        && verify_bus_message(keys, shaper_id, timestamp, path, body, signature)
+}
+
+/// Checks that a bus message was written with a protocol version this manager
+/// understands. `/bus/hello` is exempt, since that is where the version is agreed.
+fn check_protocol(request: &Request<'_>, shaper_id: &str) -> Result<u32, Status> {
+    let path = request.uri().path();
+    let version = request
+        .headers()
+        .get_one(BUS_PROTOCOL_HEADER)
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(0);
+    if accepts_protocol(path.as_str(), version) {
+        Ok(version)
+    } else {
+        println!(
+            "Shaper {shaper_id} sent {path} using bus protocol v{version}, but this manager speaks v{BUS_MIN_PROTOCOL_VERSION}-v{BUS_PROTOCOL_VERSION}. Upgrade qos_manager or qos_daemon so that they match."
+        );
+        Err(Status::UpgradeRequired)
+    }
+}
+
+/// Whether a message to `path`, sent with protocol `version`, can be read.
+fn accepts_protocol(path: &str, version: u32) -> bool {
+    path == "/bus/hello" || (BUS_MIN_PROTOCOL_VERSION..=BUS_PROTOCOL_VERSION).contains(&version)
+}
+
+fn reject(request: &Request<'_>) -> Status {
+    record_bus_rejection(request.uri().path().as_str());
+    println!(
+        "Rejected unsigned bus message for {} from {:?}",
+        request.uri().path(),
+        request.client_ip()
+    );
+    // Not 401: the catcher would redirect the daemon to the login page.
+    Status::Forbidden
+}
+
+/// Data guard: a JSON bus message from a shaper, with a valid signature.
+/// Use in place of `Json<T>` on `/bus` routes.
+pub struct BusJson<T> {
+    /// The `shaper_id` of the daemon that sent the message.
+    pub shaper_id: String,
+    /// The bus protocol version the message was sent with.
+    pub protocol_version: u32,
+    pub message: T,
+}
+
+impl<T> Deref for BusJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.message
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for BusJson<T> {
+    type Error = ();
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = request.limits().get("json").unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_bytes().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => return data::Outcome::Error((Status::PayloadTooLarge, ())),
+            Err(_) => return data::Outcome::Error((Status::BadRequest, ())),
+        };
+        let shaper_id = match signed_by(request, &body) {
+            Some(shaper_id) => shaper_id,
+            None => return data::Outcome::Error((reject(request), ())),
+        };
+        let version = match check_protocol(request, &shaper_id) {
+            Ok(version) => version,
+            Err(status) => {
+                record_bus_rejection(request.uri().path().as_str());
+                return data::Outcome::Error((status, ()));
+            }
+        };
+        match rocket::serde::json::from_slice(&body) {
+            Ok(message) => {
+                record_bus_message(request.uri().path().as_str(), body.len());
+                shaper_seen(&shaper_id, request.uri().path().as_str());
+                data::Outcome::Success(BusJson {
+                    shaper_id,
+                    protocol_version: version,
+                    message,
+                })
+            }
+            Err(e) => {
+                record_bus_rejection(request.uri().path().as_str());
+                println!(
+                    "Unable to decode {} from shaper {shaper_id} (bus protocol v{version}): {:?}",
+                    request.uri().path(),
+                    e
+                );
+                data::Outcome::Error((Status::UnprocessableEntity, ()))
+            }
+        }
+    }
+}
+
+/// Request guard: a body-less bus request (e.g. a GET) from a shaper, with a
+/// valid signature.
+pub struct BusAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BusAuth {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        match signed_by(request, &[]) {
+            Some(shaper_id) => match check_protocol(request, &shaper_id) {
+                Ok(_) => {
+                    shaper_seen(&shaper_id, request.uri().path().as_str());
+                    request::Outcome::Success(BusAuth)
+                }
+                Err(status) => {
+                    record_bus_rejection(request.uri().path().as_str());
+                    request::Outcome::Error((status, ()))
+                }
+            },
+            None => request::Outcome::Error((reject(request), ())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{accepts_protocol, is_signed};
+    use shared_rest::{
+        sign_bus_message, BUS_MAX_CLOCK_SKEW, BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION,
+    };
+
+    const NOW: i64 = 1_700_000_000;
+    const PATH: &str = "/bus/tree";
+    const BODY: &[u8] = b"{\"entries\":[]}";
+
+    fn keys() -> Vec<String> {
+        vec!["old-key".to_string(), "shared-secret".to_string()]
+    }
+
+    fn sign(shaper_id: &str, timestamp: i64, body: &[u8]) -> String {
+        sign_bus_message("shared-secret", shaper_id, timestamp, PATH, body)
+    }
+
+    #[test]
+    fn signed_message_verifies() {
+        let signature = sign("shaper-1", NOW, BODY);
+        assert!(is_signed(
+            &keys(),
+            "shaper-1",
+            NOW,
+            PATH,
+            BODY,
+            &signature,
+            NOW
+        ));
+    }
+
+    #[test]
+    fn tampered_body_is_rejected() {
+        let signature = sign("shaper-1", NOW, BODY);
+        let tampered = b"{\"entries\":[1]}";
+        assert!(!is_signed(
+            &keys(),
+            "shaper-1",
+            NOW,
+            PATH,
+            tampered,
+            &signature,
+            NOW
+        ));
+    }
+
+    #[test]
+    fn other_shaper_is_rejected() {
+        let signature = sign("shaper-1", NOW, BODY);
+        assert!(!is_signed(
+            &keys(),
+            "shaper-2",
+            NOW,
+            PATH,
+            BODY,
+            &signature,
+            NOW
+        ));
+    }
+
+    #[test]
+    fn stale_timestamp_is_rejected() {
+        let sent = NOW - BUS_MAX_CLOCK_SKEW - 1;
+        let signature = sign("shaper-1", sent, BODY);
+        assert!(!is_signed(
+            &keys(),
+            "shaper-1",
+            sent,
+            PATH,
+            BODY,
+            &signature,
+            NOW
+        ));
+
+        // Just inside the window, in either direction.
+        for sent in [NOW - BUS_MAX_CLOCK_SKEW, NOW + BUS_MAX_CLOCK_SKEW] {
+            let signature = sign("shaper-1", sent, BODY);
+            assert!(is_signed(
+                &keys(),
+                "shaper-1",
+                sent,
+                PATH,
+                BODY,
+                &signature,
+                NOW
+            ));
+        }
+    }
+
+    #[test]
+    fn protocol_versions_outside_our_range_are_refused() {
+        for version in BUS_MIN_PROTOCOL_VERSION..=BUS_PROTOCOL_VERSION {
+            assert!(accepts_protocol("/bus/tree", version));
+        }
+        // Older than the minimum, including a daemon that sent no version.
+        assert!(!accepts_protocol("/bus/tree", BUS_MIN_PROTOCOL_VERSION - 1));
+        assert!(!accepts_protocol("/bus/tree", BUS_PROTOCOL_VERSION + 1));
+        // The version is agreed at `/bus/hello`, so anything may be sent there.
+        assert!(accepts_protocol("/bus/hello", 0));
+    }
+}
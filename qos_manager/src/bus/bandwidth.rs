@@ -1,54 +1,77 @@
-use rocket::{futures::stream, serde::json::Json};
+use super::{report_time, BusJson};
 
 #[post("/bus/bandwidth", data = "<bandwidth>")]
-pub async fn bandwidth_report(bandwidth: Json<shared_rest::BandwidthReport>) {
+pub async fn bandwidth_report(bandwidth: BusJson<shared_rest::BandwidthReport>) {
     /*bandwidth
     .download
     .iter()
     .filter(|b| b.mbits_per_second > 0.0)
     .for_each(|b| println!("{:#?}", b));*/
 
-    use influxdb2::models::DataPoint;
-    use influxdb2::Client;
+    use crate::metrics::{write_points, MetricPoint};
 
+    let time = report_time(&bandwidth.timestamp);
     let mut tmp = Vec::new();
     for line in bandwidth.download.iter() {
+        if line.mbits_per_second > 0.0 {
+            crate::outages::record_activity(&line.site_id, time);
+        }
         tmp.push(
-            DataPoint::builder("queues")
+            MetricPoint::new("queues")
                 .tag("site", &line.site_id)
+                .tag("shaper", &bandwidth.shaper_id)
                 .field("down_mbps", line.mbits_per_second)
-                .build()
-                .unwrap(),
+                .timestamp(time),
         );
         tmp.push(
-            DataPoint::builder("queues")
+            MetricPoint::new("queues")
                 .tag("site", &line.site_id)
+                .tag("shaper", &bandwidth.shaper_id)
                 .field("down_drops", line.drops as f64)
-                .build()
-                .unwrap(),
+                .timestamp(time),
         );
+        if let Some(class) = &line.class_stats {
+            tmp.push(
+                MetricPoint::new("queues")
+                    .tag("site", &line.site_id)
+                    .tag("shaper", &bandwidth.shaper_id)
+                    .field("down_overlimits", class.overlimits as f64)
+                    .field("down_borrowed", class.borrowed as f64)
+                    .field("down_lended", class.lended as f64)
+                    .timestamp(time),
+            );
+        }
     }
     for line in bandwidth.upload.iter() {
+        if line.mbits_per_second > 0.0 {
+            crate::outages::record_activity(&line.site_id, time);
+        }
         tmp.push(
-            DataPoint::builder("queues")
+            MetricPoint::new("queues")
                 .tag("site", &line.site_id)
+                .tag("shaper", &bandwidth.shaper_id)
                 .field("up_mbps", line.mbits_per_second)
-                .build()
-                .unwrap(),
+                .timestamp(time),
         );
         tmp.push(
-            DataPoint::builder("queues")
+            MetricPoint::new("queues")
                 .tag("site", &line.site_id)
+                .tag("shaper", &bandwidth.shaper_id)
                 .field("up_drops", line.drops as f64)
-                .build()
-                .unwrap(),
+                .timestamp(time),
         );
+        if let Some(class) = &line.class_stats {
+            tmp.push(
+                MetricPoint::new("queues")
+                    .tag("site", &line.site_id)
+                    .tag("shaper", &bandwidth.shaper_id)
+                    .field("up_overlimits", class.overlimits as f64)
+                    .field("up_borrowed", class.borrowed as f64)
+                    .field("up_lended", class.lended as f64)
+                    .timestamp(time),
+            );
+        }
     }
 
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result = client.write("bracketqos", stream::iter(tmp)).await;
-    if result.is_err() {
-        println!("{:?}", result);
-    }
+    write_points(tmp);
 }
@@ -0,0 +1,58 @@
+use super::{report_time, BusJson};
+use crate::metrics::{write_points, MetricPoint};
+use shared_rest::{CakeLine, CAKE_DIFFSERV4_TINS};
+
+fn cake_points(
+    lines: &[CakeLine],
+    direction: &str,
+    shaper_id: &str,
+    time: i64,
+    points: &mut Vec<MetricPoint>,
+) {
+    for line in lines.iter() {
+        points.push(
+            MetricPoint::new("cake")
+                .tag("site", &line.site_id)
+                .tag("shaper", shaper_id)
+                .tag("direction", direction)
+                .field("backlog_bytes", line.backlog_bytes as f64)
+                .field("memory_used", line.memory_used as f64)
+                .timestamp(time),
+        );
+        for (i, tin) in line.tins.iter().enumerate() {
+            let tin_name = CAKE_DIFFSERV4_TINS
+                .get(i)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| i.to_string());
+            points.push(
+                MetricPoint::new("cake_tins")
+                    .tag("site", &line.site_id)
+                    .tag("shaper", shaper_id)
+                    .tag("direction", direction)
+                    .tag("tin", &tin_name)
+                    .field("sent_packets", tin.sent_packets as f64)
+                    .field("sent_bytes", tin.sent_bytes as f64)
+                    .field("drops", tin.drops as f64)
+                    .field("ecn_marks", tin.ecn_marks as f64)
+                    .field("ack_drops", tin.ack_drops as f64)
+                    .field("backlog_bytes", tin.backlog_bytes as f64)
+                    .field("peak_delay_us", tin.peak_delay_us as f64)
+                    .field("avg_delay_us", tin.avg_delay_us as f64)
+                    .field("base_delay_us", tin.base_delay_us as f64)
+                    .field("sparse_flows", tin.sparse_flows as f64)
+                    .field("bulk_flows", tin.bulk_flows as f64)
+                    .field("unresponsive_flows", tin.unresponsive_flows as f64)
+                    .timestamp(time),
+            );
+        }
+    }
+}
+
+#[post("/bus/cake", data = "<cake>")]
+pub async fn cake_report(cake: BusJson<shared_rest::CakeReport>) {
+    let time = report_time(&cake.timestamp);
+    let mut tmp = Vec::new();
+    cake_points(&cake.download, "down", &cake.shaper_id, time, &mut tmp);
+    cake_points(&cake.upload, "up", &cake.shaper_id, time, &mut tmp);
+    write_points(tmp);
+}
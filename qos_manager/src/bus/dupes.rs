@@ -1,6 +1,7 @@
 use lazy_static::*;
 use parking_lot::RwLock;
-use rocket::serde::json::Json;
+use super::BusJson;
+use crate::store::{record_sightings, sightings, SightingKind};
 use std::time::Duration;
 
 lazy_static! {
@@ -8,12 +9,34 @@ lazy_static! {
 }
 
 #[post("/bus/duplicate_ip", data = "<dupes>")]
-pub async fn duplicate_ip(dupes: Json<shared_rest::DuplicateIp>) {
+pub async fn duplicate_ip(dupes: BusJson<shared_rest::DuplicateIp>) {
     if let Some(mut lock) = DUPLICATE_IP.try_write_for(Duration::from_secs(2)) {
         *lock = dupes.dupes.clone();
     }
+    let stored = record_sightings(
+        SightingKind::DuplicateIp,
+        &dupes.shaper_id,
+        &dupes.dupes,
+        true,
+    );
+    if let Err(e) = stored {
+        println!("Unable to store duplicate IPs: {e:?}");
+    }
 }
 
 pub fn duplicate_ip_list() -> Vec<String> {
     DUPLICATE_IP.read().clone()
 }
+
+/// Loads the duplicate IPs that shapers last reported.
+pub fn load_duplicate_ips() -> anyhow::Result<()> {
+    let mut dupes: Vec<String> = sightings(SightingKind::DuplicateIp, None)?
+        .into_iter()
+        .filter(|s| s.active)
+        .map(|s| s.value)
+        .collect();
+    dupes.sort();
+    dupes.dedup();
+    *DUPLICATE_IP.write() = dupes;
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use super::{shaper_hello_seen, BusJson};
+use rocket::serde::json::Json;
+use shared_rest::{
+    negotiate_protocol, Hello, HelloResponse, BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION,
+};
+
+/// Called by each `qos_daemon` when it starts. Replies with the protocol
+/// versions this manager supports; the daemon picks the version to use.
+#[post("/bus/hello", data = "<hello>")]
+pub async fn shaper_hello(hello: BusJson<Hello>) -> Json<HelloResponse> {
+    let negotiated = negotiate_protocol(
+        (BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION),
+        (hello.min_protocol_version, hello.protocol_version),
+    );
+    shaper_hello_seen(&hello.shaper_id, &hello.daemon_version, negotiated);
+    match negotiated {
+        Some(version) => println!(
+            "Shaper {} (qos_daemon {}) connected, using bus protocol v{version}",
+            hello.shaper_id, hello.daemon_version
+        ),
+        None => println!(
+            "Shaper {} (qos_daemon {}) speaks bus protocol v{}-v{}, but this manager speaks v{BUS_MIN_PROTOCOL_VERSION}-v{BUS_PROTOCOL_VERSION}. Upgrade the older of the two.",
+            hello.shaper_id,
+            hello.daemon_version,
+            hello.min_protocol_version,
+            hello.protocol_version
+        ),
+    }
+    Json(HelloResponse {
+        manager_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: BUS_PROTOCOL_VERSION,
+        min_protocol_version: BUS_MIN_PROTOCOL_VERSION,
+    })
+}
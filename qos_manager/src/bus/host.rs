@@ -1,44 +1,42 @@
-use rocket::{futures::stream, serde::json::Json};
+use super::{report_time, shaper_host_seen, BusJson};
 
 #[post("/bus/host", data = "<host>")]
-pub async fn host_usage(host: Json<shared_rest::SystemStatus>) {
-    use influxdb2::models::DataPoint;
-    use influxdb2::Client;
+pub async fn host_usage(host: BusJson<shared_rest::SystemStatus>) {
+    use crate::metrics::{write_points, MetricPoint};
 
     println!("{}", host.used_memory);
 
+    let time = report_time(&host.timestamp);
+    shaper_host_seen(
+        &host.shaper_id,
+        &host.daemon_version,
+        host.uptime_secs,
+        time,
+    );
     let mut tmp = vec![
-        DataPoint::builder("memory")
-            .tag("host", "server01")
+        MetricPoint::new("memory")
+            .tag("host", &host.shaper_id)
             .field(
                 "memory",
                 (host.used_memory as f64 / host.total_memory as f64) as f64,
             )
-            .build()
-            .unwrap(),
-        DataPoint::builder("swap")
-            .tag("host", "server01")
+            .timestamp(time),
+        MetricPoint::new("swap")
+            .tag("host", &host.shaper_id)
             .field(
                 "memory",
                 (host.used_swap as f64 / host.total_swap as f64) as f64,
             )
-            .build()
-            .unwrap(),
+            .timestamp(time),
     ];
     for (n, usage) in host.cpu_usage.iter().enumerate() {
         tmp.push(
-            DataPoint::builder("cpu")
-                .tag("host", "server01")
+            MetricPoint::new("cpu")
+                .tag("host", &host.shaper_id)
                 .tag("cpu", format!("{n}"))
                 .field("usage", *usage as f64)
-                .build()
-                .unwrap(),
+                .timestamp(time),
         );
     }
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result = client.write("bracketqos", stream::iter(tmp)).await;
-    if result.is_err() {
-        println!("{:?}", result);
-    }
+    write_points(tmp);
 }
@@ -1,16 +1,47 @@
+use super::{report_time, BusJson};
+use crate::influx::QueryFilter;
 use chrono::{DateTime, Local};
 use lazy_static::*;
 use parking_lot::RwLock;
-use rocket::{futures::stream, serde::json::Json};
 use std::{collections::HashMap, time::Duration};
 
+/// The latency source graphs, reports and alerts use unless asked for
+/// another, and the source of reports from daemons that predate sources.
+pub const DEFAULT_LATENCY_SOURCE: &str = "pping";
+
+/// Matches latency records from `source`. Records written before reports
+/// carried a source have no `source` tag, and came from pping.
+pub fn latency_source_filter(source: &str) -> QueryFilter {
+    if source == DEFAULT_LATENCY_SOURCE {
+        QueryFilter::tag_or_missing("source", source)
+    } else {
+        QueryFilter::tag("source", source)
+    }
+}
+
 lazy_static! {
     pub static ref UNMAPPED_IP: RwLock<HashMap<String, chrono::DateTime<Local>>> =
         RwLock::new(HashMap::new());
 }
 
+/// Loads the unknown IPs seen in the last day.
+pub fn load_unknown_ips() -> anyhow::Result<()> {
+    let since = Local::now() - chrono::Duration::hours(24);
+    let mut lock = UNMAPPED_IP.write();
+    for sighting in
+        crate::store::sightings(crate::store::SightingKind::UnknownIp, Some(since))?.into_iter()
+    {
+        if let Ok(seen) = DateTime::parse_from_rfc3339(&sighting.last_seen) {
+            let seen = seen.with_timezone(&Local);
+            let entry = lock.entry(sighting.value).or_insert(seen);
+            *entry = (*entry).max(seen);
+        }
+    }
+    Ok(())
+}
+
 #[post("/bus/latency", data = "<latency>")]
-pub async fn latency_report(latency: Json<shared_rest::LatencyReport>) {
+pub async fn latency_report(latency: BusJson<shared_rest::LatencyReport>) {
     //println!("{:#?}", latency);
 
     if let Some(mut lock) = UNMAPPED_IP.try_write_for(Duration::from_secs(2)) {
@@ -25,27 +56,48 @@ pub async fn latency_report(latency: Json<shared_rest::LatencyReport>) {
         let yesterday = local - chrono::Duration::hours(24);
         lock.retain(|_, date| *date > yesterday);
     }
+    if !latency.unmapped_ip.is_empty() {
+        let stored = crate::store::record_sightings(
+            crate::store::SightingKind::UnknownIp,
+            &latency.shaper_id,
+            &latency.unmapped_ip,
+            false,
+        );
+        if let Err(e) = stored {
+            println!("Unable to store unknown IPs: {e:?}");
+        }
+    }
 
-    use influxdb2::models::DataPoint;
-    use influxdb2::Client;
+    use crate::metrics::{write_points, MetricPoint};
 
+    let time = report_time(&latency.timestamp);
+    let source = if latency.source.is_empty() {
+        DEFAULT_LATENCY_SOURCE
+    } else {
+        latency.source.as_str()
+    };
     let mut tmp = Vec::new();
     for line in latency.items.iter() {
+        crate::outages::record_activity(&line.site, time);
         for site_id in crate::bus::get_parent_ids(&line.site) {
-            tmp.push(
-                DataPoint::builder("latency")
-                    .tag("site", &site_id)
-                    .field("latency", line.latency.average as f64)
-                    .build()
-                    .unwrap(),
-            );
+            let mut point = MetricPoint::new("latency")
+                .tag("site", &site_id)
+                .tag("shaper", &latency.shaper_id)
+                .tag("source", source)
+                .field("latency", line.latency.average as f64)
+                .timestamp(time);
+            // Older daemons only send an average.
+            if line.latency.samples > 0 {
+                point = point
+                    .field("latency_min", line.latency.min as f64)
+                    .field("latency_median", line.latency.median as f64)
+                    .field("latency_p95", line.latency.p95 as f64)
+                    .field("latency_max", line.latency.max as f64)
+                    .field("latency_samples", line.latency.samples as f64);
+            }
+            tmp.push(point);
         }
     }
 
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result = client.write("bracketqos", stream::iter(tmp)).await;
-    if result.is_err() {
-        println!("{:?}", result);
-    }
+    write_points(tmp);
 }
@@ -1,9 +1,23 @@
+mod auth;
+pub use auth::*;
+mod shapers;
+pub use shapers::*;
+mod report_time;
+pub use report_time::*;
+mod hello;
+pub use hello::*;
 mod host;
 pub use host::*;
 mod latency;
 pub use latency::*;
 mod bandwidth;
 pub use bandwidth::*;
+mod cake;
+pub use cake::*;
+mod reachability;
+pub use reachability::*;
+mod rebuild;
+pub use rebuild::*;
 mod tree;
 pub use tree::*;
 mod dupes;
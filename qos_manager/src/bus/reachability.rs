@@ -0,0 +1,86 @@
+use super::{get_parent_ids, report_time, BusJson};
+use crate::metrics::{write_points, MetricPoint};
+use chrono::{DateTime, Local, TimeZone};
+use lazy_static::*;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+/// The most recent probe results for a client queue.
+#[derive(Clone)]
+pub struct ReachabilityState {
+    pub shaper_id: String,
+    pub up: bool,
+    /// When the queue last changed between up and down.
+    pub since: DateTime<Local>,
+    pub last_report: DateTime<Local>,
+    pub loss: f32,
+    pub rtt_ms: Option<f32>,
+}
+
+lazy_static! {
+    static ref REACHABILITY: RwLock<HashMap<String, ReachabilityState>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Current reachability of each probed client queue, keyed by site ID.
+pub fn current_reachability() -> HashMap<String, ReachabilityState> {
+    REACHABILITY.read().clone()
+}
+
+#[post("/bus/reachability", data = "<report>")]
+pub async fn reachability_report(report: BusJson<shared_rest::ReachabilityReport>) {
+    let time = report_time(&report.timestamp);
+    // Spooled reports can arrive long after they were made.
+    let reported = Local.timestamp_nanos(time);
+    let now = Local::now();
+    let mut points = Vec::new();
+    {
+        let mut lock = REACHABILITY.write();
+        for item in report.items.iter() {
+            let up = item.is_up();
+            if up {
+                crate::outages::record_activity(&item.site, time);
+            }
+            let state = lock
+                .entry(item.site.clone())
+                .or_insert_with(|| ReachabilityState {
+                    shaper_id: report.shaper_id.clone(),
+                    up,
+                    since: reported,
+                    last_report: reported,
+                    loss: 0.0,
+                    rtt_ms: None,
+                });
+            // An older report, replayed late, doesn't undo a newer one.
+            if reported >= state.last_report {
+                if state.up != up {
+                    state.since = reported;
+                }
+                state.shaper_id = report.shaper_id.clone();
+                state.up = up;
+                state.last_report = reported;
+                state.loss = item.loss();
+                state.rtt_ms = item.rtt_ms;
+            }
+
+            // Written against each parent too, so that `up` averages to the
+            // share of clients reachable beneath an access point or site.
+            for site_id in get_parent_ids(&item.site) {
+                let mut point = MetricPoint::new("reachability")
+                    .tag("site", &site_id)
+                    .tag("shaper", &report.shaper_id)
+                    .field("up", if up { 1.0 } else { 0.0 })
+                    .field("loss", item.loss() as f64)
+                    .timestamp(time);
+                if let Some(rtt) = item.rtt_ms {
+                    point = point.field("rtt", rtt as f64);
+                }
+                points.push(point);
+            }
+        }
+        // Forget clients that are no longer probed.
+        let stale = now - chrono::Duration::hours(1);
+        lock.retain(|_, state| state.last_report > stale);
+    }
+    write_points(points);
+}
@@ -0,0 +1,101 @@
+use super::BusJson;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::serde::{Deserialize, Serialize};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use shared_rest::{RebuildEvent, RebuildTrigger};
+
+const REBUILDS_FILENAME: &str = "rebuilds.ron";
+
+/// Rebuilds older than this are forgotten.
+const REBUILD_RETENTION_DAYS: i64 = 90;
+
+/// A queue rebuild reported by a shaper.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct RebuildRecord {
+    pub shaper_id: String,
+    /// RFC 3339.
+    pub started: String,
+    pub ended: String,
+    pub duration_ms: u64,
+    pub triggers: Vec<RebuildTrigger>,
+    pub nodes_added: usize,
+    pub nodes_removed: usize,
+    pub nodes_changed: usize,
+    pub error: Option<String>,
+}
+
+lazy_static! {
+    static ref REBUILDS: RwLock<Vec<RebuildRecord>> = RwLock::new(Vec::new());
+}
+
+/// Every remembered rebuild, newest first.
+pub fn get_rebuilds() -> Vec<RebuildRecord> {
+    let mut rebuilds = REBUILDS.read().clone();
+    rebuilds.sort_by(|a, b| b.started.cmp(&a.started));
+    rebuilds
+}
+
+/// When a shaper's queues were last successfully rebuilt (RFC 3339).
+pub fn last_rebuild(shaper_id: &str) -> Option<String> {
+    REBUILDS
+        .read()
+        .iter()
+        .filter(|r| r.shaper_id == shaper_id && r.error.is_none())
+        .map(|r| r.ended.clone())
+        .max()
+}
+
+/// Loads `rebuilds.ron`, if there is one.
+pub fn load_rebuilds() -> Result<()> {
+    let data = std::fs::read_to_string(REBUILDS_FILENAME)?;
+    let rebuilds: Vec<RebuildRecord> = ron::from_str(&data)?;
+    *REBUILDS.write() = rebuilds;
+    Ok(())
+}
+
+fn save_rebuilds(rebuilds: &[RebuildRecord]) -> Result<()> {
+    let ron = to_string_pretty(&rebuilds, PrettyConfig::new())?;
+    std::fs::write(REBUILDS_FILENAME, ron)?;
+    Ok(())
+}
+
+#[post("/bus/rebuild", data = "<event>")]
+pub async fn rebuild_event(event: BusJson<RebuildEvent>) {
+    match &event.error {
+        Some(error) => println!(
+            "Shaper {} failed to rebuild queues: {error}",
+            event.shaper_id
+        ),
+        None => println!(
+            "Shaper {} rebuilt queues in {} ms ({:?})",
+            event.shaper_id, event.duration_ms, event.triggers
+        ),
+    }
+    let record = RebuildRecord {
+        shaper_id: event.shaper_id.clone(),
+        started: event.started.clone(),
+        ended: event.ended.clone(),
+        duration_ms: event.duration_ms,
+        triggers: event.triggers.clone(),
+        nodes_added: event.nodes_added,
+        nodes_removed: event.nodes_removed,
+        nodes_changed: event.nodes_changed,
+        error: event.error.clone(),
+    };
+
+    let forget_before = Local::now() - chrono::Duration::days(REBUILD_RETENTION_DAYS);
+    let mut lock = REBUILDS.write();
+    lock.push(record);
+    lock.retain(|r| {
+        DateTime::parse_from_rfc3339(&r.started)
+            .map(|t| t > forget_before)
+            .unwrap_or(false)
+    });
+    if let Err(e) = save_rebuilds(&lock) {
+        println!("Unable to save {REBUILDS_FILENAME}: {e:?}");
+    }
+}
@@ -0,0 +1,12 @@
+use chrono::{DateTime, Utc};
+
+/// Converts a report's RFC 3339 timestamp to the nanosecond timestamp used by
+/// Influx. Reports can arrive late (the daemon spools them while the manager is
+/// down), so points are written at the time they were gathered. Falls back to
+/// the current time if the report has no usable timestamp.
+pub fn report_time(timestamp: &str) -> i64 {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+        .timestamp_nanos()
+}
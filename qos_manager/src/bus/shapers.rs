@@ -0,0 +1,164 @@
+use chrono::{DateTime, Local, TimeZone};
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::serde::Serialize;
+use std::{collections::BTreeMap, time::Duration};
+
+/// The bus feeds that a running `qos_daemon` sends every minute or so, and so
+/// can go stale. `tree` follows the (much longer) UISP update interval.
+pub const SCHEDULED_FEEDS: [&str; 3] = ["bandwidth", "latency", "host"];
+
+/// What the manager has heard from one shaper since startup.
+#[derive(Clone, Default)]
+struct Heartbeat {
+    last_seen: Option<DateTime<Local>>,
+    /// When each bus endpoint (`tree`, `bandwidth`, etc.) last received a message.
+    feeds: BTreeMap<String, DateTime<Local>>,
+    daemon_version: Option<String>,
+    protocol_version: Option<u32>,
+    /// Worked out from the uptime in host reports.
+    daemon_started: Option<DateTime<Local>>,
+}
+
+lazy_static! {
+    /// Every shaper that has sent a signed bus message since startup.
+    static ref SHAPERS: RwLock<BTreeMap<String, Heartbeat>> = RwLock::new(BTreeMap::new());
+}
+
+/// Notes an accepted bus message from a shaper, at `path` (e.g. `/bus/tree`).
+pub fn shaper_seen(shaper_id: &str, path: &str) {
+    if let Some(mut lock) = SHAPERS.try_write_for(Duration::from_secs(2)) {
+        let now = Local::now();
+        let heartbeat = lock.entry(shaper_id.to_string()).or_default();
+        heartbeat.last_seen = Some(now);
+        let feed = path.trim_start_matches("/bus/");
+        if !feed.is_empty() {
+            heartbeat.feeds.insert(feed.to_string(), now);
+        }
+    }
+}
+
+/// Notes the daemon version a shaper introduced itself with.
+pub fn shaper_hello_seen(shaper_id: &str, daemon_version: &str, protocol_version: Option<u32>) {
+    let mut lock = SHAPERS.write();
+    let heartbeat = lock.entry(shaper_id.to_string()).or_default();
+    heartbeat.daemon_version = Some(daemon_version.to_string());
+    heartbeat.protocol_version = protocol_version;
+}
+
+/// Notes the version and uptime from a shaper's host report.
+pub fn shaper_host_seen(shaper_id: &str, daemon_version: &str, uptime_secs: u64, time: i64) {
+    let mut lock = SHAPERS.write();
+    let heartbeat = lock.entry(shaper_id.to_string()).or_default();
+    if !daemon_version.is_empty() {
+        heartbeat.daemon_version = Some(daemon_version.to_string());
+    }
+    if uptime_secs > 0 {
+        heartbeat.daemon_started =
+            Some(Local.timestamp_nanos(time) - chrono::Duration::seconds(uptime_secs as i64));
+    }
+}
+
+/// When each shaper was last heard from, since startup.
+pub fn shapers_last_seen() -> BTreeMap<String, DateTime<Local>> {
+    SHAPERS
+        .read()
+        .iter()
+        .filter_map(|(id, h)| h.last_seen.map(|seen| (id.clone(), seen)))
+        .collect()
+}
+
+/// When each shaper's feeds were last received, since startup.
+pub fn shaper_feeds_last_seen() -> BTreeMap<String, BTreeMap<String, DateTime<Local>>> {
+    SHAPERS
+        .read()
+        .iter()
+        .map(|(id, h)| (id.clone(), h.feeds.clone()))
+        .collect()
+}
+
+/// One bus feed from a shaper.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct FeedStatus {
+    pub feed: String,
+    /// RFC 3339.
+    pub last_seen: String,
+    pub age_secs: i64,
+    /// A scheduled feed that hasn't been received for `feed_stale_secs`.
+    pub stale: bool,
+}
+
+/// The health of one shaper's `qos_daemon`, as seen from the manager.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct ShaperStatus {
+    pub shaper_id: String,
+    pub daemon_version: Option<String>,
+    pub protocol_version: Option<u32>,
+    pub uptime_secs: Option<i64>,
+    /// When the queues were last successfully rebuilt (RFC 3339).
+    pub last_rebuild: Option<String>,
+    /// When anything was last received (RFC 3339).
+    pub last_seen: Option<String>,
+    pub feeds: Vec<FeedStatus>,
+    /// True if any scheduled feed is stale, or missing entirely.
+    pub stale: bool,
+}
+
+/// The status of every known shaper. Scheduled feeds that have never been
+/// received are listed as stale.
+pub fn shaper_status(stale_secs: u64) -> Vec<ShaperStatus> {
+    let now = Local::now();
+    let shaper_ids = shaper_list();
+    let shapers = SHAPERS.read();
+    shaper_ids
+        .into_iter()
+        .map(|shaper_id| {
+            let heartbeat = shapers.get(&shaper_id).cloned().unwrap_or_default();
+            let mut feeds: Vec<FeedStatus> = heartbeat
+                .feeds
+                .iter()
+                .map(|(feed, seen)| {
+                    let age_secs = (now - *seen).num_seconds().max(0);
+                    FeedStatus {
+                        feed: feed.clone(),
+                        last_seen: seen.to_rfc3339(),
+                        age_secs,
+                        stale: SCHEDULED_FEEDS.contains(&feed.as_str())
+                            && age_secs > stale_secs as i64,
+                    }
+                })
+                .collect();
+            let missing = SCHEDULED_FEEDS
+                .iter()
+                .any(|feed| !heartbeat.feeds.contains_key(*feed));
+            feeds.sort_by(|a, b| a.feed.cmp(&b.feed));
+            ShaperStatus {
+                stale: missing || feeds.iter().any(|f| f.stale),
+                daemon_version: heartbeat.daemon_version,
+                protocol_version: heartbeat.protocol_version,
+                uptime_secs: heartbeat
+                    .daemon_started
+                    .map(|started| (now - started).num_seconds().max(0)),
+                last_rebuild: super::last_rebuild(&shaper_id),
+                last_seen: heartbeat.last_seen.map(|t| t.to_rfc3339()),
+                feeds,
+                shaper_id,
+            }
+        })
+        .collect()
+}
+
+/// Lists the shapers that have reported to the manager, along with any that
+/// have a stored queue tree (so they are listed before their first report).
+pub fn shaper_list() -> Vec<String> {
+    let mut result: Vec<String> = SHAPERS.read().keys().cloned().collect();
+    for shaper_id in super::get_tree_shapers() {
+        if !result.contains(&shaper_id) {
+            result.push(shaper_id);
+        }
+    }
+    result.sort();
+    result
+}
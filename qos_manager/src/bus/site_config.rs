@@ -1,8 +1,11 @@
+use super::BusAuth;
+use crate::auth::OperatorUser;
+use crate::store::audit;
+use crate::topology::record_limit_edit;
 use anyhow::Result;
 use lazy_static::*;
 use parking_lot::RwLock;
-use rocket::serde::json::Json;
-use ron::ser::{to_string_pretty, PrettyConfig};
+use rocket::{http::Status, serde::json::Json};
 use shared_rest::{ApLimit, ShaperTreeConfig, SiteLimit};
 use std::time::Duration;
 
@@ -11,63 +14,95 @@ lazy_static! {
 }
 
 #[get("/bus/site_config")]
-pub async fn get_site_config() -> Json<ShaperTreeConfig> {
+pub async fn get_site_config(_bus: BusAuth) -> Json<ShaperTreeConfig> {
     let lock = SITE_CONFIG.read();
     Json(lock.clone())
 }
 
+/// Loads the speed limits from the state store.
 pub fn load_config() -> Result<()> {
-    let data = std::fs::read_to_string("shaper.ron")?;
-    let map_file: ShaperTreeConfig = ron::from_str(&data)?;
+    let limits = crate::store::load_limits()?;
     if let Some(mut lock) = SITE_CONFIG.try_write_for(Duration::from_secs(2)) {
-        *lock = map_file;
+        *lock = limits;
     }
     Ok(())
 }
 
 fn save_config() -> Result<()> {
     let data = SITE_CONFIG.read().clone();
-    let header_ron = to_string_pretty(&data, PrettyConfig::new())?;
-    std::fs::write("shaper.ron", header_ron)?;
-    Ok(())
+    crate::store::save_limits(&data)
 }
 
 #[get("/bus/add_site_limit/<id>/<download>/<upload>")]
-pub async fn add_site_limit(id: String, download: u32, upload: u32) -> Json<ShaperTreeConfig> {
+pub async fn add_site_limit(
+    user: OperatorUser,
+    id: String,
+    download: u32,
+    upload: u32,
+) -> Result<Json<ShaperTreeConfig>, Status> {
+    println!(
+        "{} set site {id} limit to {download}/{upload}",
+        user.0.username
+    );
+    let details = format!("{id}: {download}/{upload}");
     if let Some(mut lock) = SITE_CONFIG.try_write_for(Duration::from_secs(2)) {
         if let Some(site) = lock.sites.iter_mut().find(|s| s.id == id) {
             site.download = download;
             site.upload = upload;
         } else {
             lock.sites.push(SiteLimit {
-                id,
+                id: id.clone(),
                 download,
                 upload,
             })
         }
+    } else {
+        println!("Unable to set the limit: the speed limits are busy");
+        return Err(Status::ServiceUnavailable);
+    }
+    audit(&user.0.username, "set_site_limit", &details);
+    record_limit_edit(&id, &user.0.username, download, upload);
+    if let Err(e) = save_config() {
+        println!("Unable to store the speed limits: {e:?}");
     }
-    let _ = save_config();
 
     let lock = SITE_CONFIG.read();
-    Json(lock.clone())
+    Ok(Json(lock.clone()))
 }
 
 #[get("/bus/add_ap_limit/<id>/<download>/<upload>")]
-pub async fn add_ap_limit(id: String, download: u32, upload: u32) -> Json<ShaperTreeConfig> {
+pub async fn add_ap_limit(
+    user: OperatorUser,
+    id: String,
+    download: u32,
+    upload: u32,
+) -> Result<Json<ShaperTreeConfig>, Status> {
+    println!(
+        "{} set access point {id} limit to {download}/{upload}",
+        user.0.username
+    );
+    let details = format!("{id}: {download}/{upload}");
     if let Some(mut lock) = SITE_CONFIG.try_write_for(Duration::from_secs(2)) {
         if let Some(ap) = lock.access_points.iter_mut().find(|s| s.id == id) {
             ap.download = download;
             ap.upload = upload;
         } else {
             lock.access_points.push(ApLimit {
-                id,
+                id: id.clone(),
                 download,
                 upload,
             })
         }
+    } else {
+        println!("Unable to set the limit: the speed limits are busy");
+        return Err(Status::ServiceUnavailable);
+    }
+    audit(&user.0.username, "set_ap_limit", &details);
+    record_limit_edit(&id, &user.0.username, download, upload);
+    if let Err(e) = save_config() {
+        println!("Unable to store the speed limits: {e:?}");
     }
-    let _ = save_config();
 
     let lock = SITE_CONFIG.read();
-    Json(lock.clone())
+    Ok(Json(lock.clone()))
 }
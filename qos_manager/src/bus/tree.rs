@@ -1,78 +1,266 @@
+use super::BusJson;
 use anyhow::Result;
 use lazy_static::*;
 use parking_lot::RwLock;
-use rocket::serde::json::Json;
-use ron::ser::{to_string_pretty, PrettyConfig};
-use shared_rest::QueueTreeEntry;
-use std::time::Duration;
+use rocket::serde::Deserialize;
+use shared_rest::{NodeKind, NodeMetadata, QueueTreeEntry};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
 
 lazy_static! {
-    static ref QUEUE_TREE: RwLock<Vec<QueueTreeEntry>> = RwLock::new(Vec::new());
+    /// The most recent queue tree from each shaper, keyed by shaper ID.
+    static ref SHAPER_TREES: RwLock<BTreeMap<String, Vec<QueueTreeEntry>>> =
+        RwLock::new(BTreeMap::new());
+
+    /// All of the shaper trees merged together, used for search and reports.
+    static ref QUEUE_TREE: RwLock<IndexedTree> = RwLock::new(IndexedTree::default());
+}
+
+/// The merged queue tree, with indices built when it is received so that
+/// lookups don't have to scan it.
+#[derive(Default)]
+struct IndexedTree {
+    entries: Vec<QueueTreeEntry>,
+    /// Node ID to index in `entries`.
+    by_id: HashMap<String, usize>,
+    /// The indices of each node's children.
+    children: Vec<Vec<usize>>,
+    /// Each node's index, followed by those of its parent, grandparent and so
+    /// on up to the root.
+    ancestors: Vec<Vec<usize>>,
+    /// The indices of the client queues (clients and infrastructure) at or
+    /// beneath each node.
+    subtree_clients: Vec<Vec<usize>>,
+}
+
+impl IndexedTree {
+    fn new(entries: Vec<QueueTreeEntry>) -> Self {
+        let len = entries.len();
+        let mut by_id = HashMap::new();
+        let mut children = vec![Vec::new(); len];
+        for (i, entry) in entries.iter().enumerate() {
+            by_id.entry(entry.id.clone()).or_insert(i);
+            if let Some(parent) = entry.parent {
+                if parent < len && parent != i {
+                    children[parent].push(i);
+                }
+            }
+        }
+
+        // Walk up from each node. The walk is capped at the size of the
+        // tree, so a malformed tree with a cycle can't hang the manager.
+        let mut ancestors = Vec::with_capacity(len);
+        let mut subtree_clients = vec![Vec::new(); len];
+        for (i, entry) in entries.iter().enumerate() {
+            let mut chain = vec![i];
+            let mut current = entry.parent;
+            while let Some(parent) = current {
+                if parent >= len || chain.len() > len {
+                    break;
+                }
+                chain.push(parent);
+                current = entries[parent].parent;
+            }
+            if entry.level_type.is_client_queue() {
+                for node in chain.iter() {
+                    subtree_clients[*node].push(i);
+                }
+            }
+            ancestors.push(chain);
+        }
+
+        Self {
+            entries,
+            by_id,
+            children,
+            ancestors,
+            subtree_clients,
+        }
+    }
+
+    fn index_of(&self, id: &str) -> Option<usize> {
+        self.by_id.get(id).cloned()
+    }
+
+    fn collect(&self, indices: &[usize]) -> Vec<QueueTreeEntry> {
+        indices.iter().map(|i| self.entries[*i].clone()).collect()
+    }
 }
 
 #[post("/bus/tree", data = "<tree>")]
-pub async fn queue_tree(tree: Json<Vec<QueueTreeEntry>>) {
+pub async fn queue_tree(tree: BusJson<Vec<QueueTreeEntry>>) {
     //println!("{:?}", tree);
+    let mut entries = tree.to_vec();
+    // Daemons before protocol v5 only sent root, tower, ap and client.
+    if tree.protocol_version < 5 {
+        for entry in entries.iter_mut() {
+            entry.level_type =
+                NodeKind::from_legacy(entry.level_type.legacy_name(), &entry.id, &entry.name);
+        }
+    }
+    if let Some(mut lock) = SHAPER_TREES.try_write_for(Duration::from_secs(2)) {
+        let previous = lock.insert(tree.shaper_id.clone(), entries.clone());
+        crate::topology::record_tree(&tree.shaper_id, previous.as_deref(), &entries);
+    }
+    merge_trees();
+    if let Err(e) = crate::store::save_tree(&tree.shaper_id, &entries) {
+        println!("Unable to store the tree from {}: {e:?}", tree.shaper_id);
+    }
+}
+
+/// Rebuilds the merged tree. Each shaper's tree has its own root; these are
+/// combined into a single root (with the sum of the shapers' Internet
+/// capacity), and everything else is appended with its parent indices moved.
+fn merge_trees() {
+    let trees = SHAPER_TREES.read();
+    let mut merged: Vec<QueueTreeEntry> = Vec::new();
+    for tree in trees.values() {
+        if tree.is_empty() {
+            continue;
+        }
+        if merged.is_empty() {
+            merged.push(tree[0].clone());
+        } else {
+            merged[0].down_mbps += tree[0].down_mbps;
+            merged[0].up_mbps += tree[0].up_mbps;
+        }
+        // Index 0 (the shaper's root) maps to the merged root; everything
+        // else moves to the end of the merged tree.
+        let offset = merged.len() - 1;
+        for entry in tree.iter().skip(1) {
+            let mut entry = entry.clone();
+            entry.parent = entry
+                .parent
+                .map(|parent| if parent == 0 { 0 } else { parent + offset });
+            merged.push(entry);
+        }
+    }
+    let indexed = IndexedTree::new(merged);
     if let Some(mut lock) = QUEUE_TREE.try_write_for(Duration::from_secs(2)) {
-        *lock = tree.to_vec();
+        *lock = indexed;
     }
-    let _ = save_tree();
+}
+
+/// Lists the shapers for which a queue tree is stored.
+pub fn get_tree_shapers() -> Vec<String> {
+    SHAPER_TREES.read().keys().cloned().collect()
+}
+
+/// Returns one shaper's queue tree, as it sent it.
+pub fn get_shaper_tree(shaper_id: &str) -> Option<Vec<QueueTreeEntry>> {
+    SHAPER_TREES.read().get(shaper_id).cloned()
 }
 
 pub fn get_queue_tree() -> Vec<QueueTreeEntry> {
-    QUEUE_TREE.read().clone()
+    QUEUE_TREE.read().entries.clone()
 }
 
-pub fn get_tree_node_by_id(id: &str) -> QueueTreeEntry {
-    QUEUE_TREE
-        .read()
-        .iter()
-        .find(|t| t.id == id)
-        .cloned()
-        .unwrap()
+pub fn get_tree_node_by_id(id: &str) -> Option<QueueTreeEntry> {
+    let tree = QUEUE_TREE.read();
+    tree.index_of(id).map(|i| tree.entries[i].clone())
 }
 
-pub fn get_tree_node_by_index(id: usize) -> QueueTreeEntry {
-    QUEUE_TREE.read().iter().nth(id).cloned().unwrap()
+pub fn get_tree_node_by_index(index: usize) -> Option<QueueTreeEntry> {
+    QUEUE_TREE.read().entries.get(index).cloned()
 }
 
-pub fn get_tree_children(id: &str) -> Vec<QueueTreeEntry> {
+/// The children of a node, or `None` if the node isn't in the tree.
+pub fn get_tree_children(id: &str) -> Option<Vec<QueueTreeEntry>> {
     let tree = QUEUE_TREE.read();
-    tree.iter()
-        .filter(|t| {
-            if let Some(parent) = t.parent {
-                if tree[parent].id == id {
-                    return true;
-                }
-            }
-            false
-        })
-        .cloned()
-        .collect()
+    tree.index_of(id).map(|i| tree.collect(&tree.children[i]))
+}
+
+/// A node, followed by its parent, grandparent and so on up to the root.
+pub fn get_tree_ancestors(id: &str) -> Option<Vec<QueueTreeEntry>> {
+    let tree = QUEUE_TREE.read();
+    tree.index_of(id).map(|i| tree.collect(&tree.ancestors[i]))
+}
+
+/// The client queues (clients and infrastructure) at or beneath a node.
+pub fn get_subtree_clients(id: &str) -> Option<Vec<QueueTreeEntry>> {
+    let tree = QUEUE_TREE.read();
+    tree.index_of(id)
+        .map(|i| tree.collect(&tree.subtree_clients[i]))
 }
 
+/// Loads the shaper trees from the state store.
 pub fn load_tree() -> Result<()> {
-    let data = std::fs::read_to_string("tree.ron")?;
-    let map_file: Vec<QueueTreeEntry> = ron::from_str(&data)?;
-    if let Some(mut lock) = QUEUE_TREE.try_write_for(Duration::from_secs(2)) {
-        *lock = map_file;
+    let trees = crate::store::load_trees()?;
+    if let Some(mut lock) = SHAPER_TREES.try_write_for(Duration::from_secs(2)) {
+        *lock = trees;
     }
+    merge_trees();
     Ok(())
 }
 
-fn save_tree() -> Result<()> {
-    let data = QUEUE_TREE.read().clone();
-    let header_ron = to_string_pretty(&data, PrettyConfig::new())?;
-    std::fs::write("tree.ron", header_ron)?;
-    Ok(())
+/// Parses a `tree.ron` written by an older manager. One from before
+/// multi-shaper support (holding a single tree) is loaded as the `default`
+/// shaper's tree.
+pub fn parse_tree_file(data: &str) -> Result<BTreeMap<String, Vec<QueueTreeEntry>>> {
+    match ron::from_str(data) {
+        Ok(trees) => Ok(trees),
+        Err(_) => load_legacy_tree(data),
+    }
+}
+
+/// A tree entry as stored before node kinds were typed, when `level_type`
+/// was a string.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct LegacyTreeEntry {
+    name: String,
+    id: String,
+    level_type: String,
+    parent: Option<usize>,
+    down_mbps: u32,
+    up_mbps: u32,
+    ip_addresses: HashSet<String>,
+}
+
+impl LegacyTreeEntry {
+    fn into_entry(self) -> QueueTreeEntry {
+        QueueTreeEntry {
+            level_type: NodeKind::from_legacy(&self.level_type, &self.id, &self.name),
+            name: self.name,
+            id: self.id,
+            parent: self.parent,
+            down_mbps: self.down_mbps,
+            up_mbps: self.up_mbps,
+            ip_addresses: self.ip_addresses,
+            metadata: NodeMetadata::default(),
+        }
+    }
+}
+
+/// Loads a `tree.ron` written by an older manager: either a map of shaper
+/// trees, or a single tree (loaded as the `default` shaper's).
+fn load_legacy_tree(data: &str) -> Result<BTreeMap<String, Vec<QueueTreeEntry>>> {
+    let legacy: BTreeMap<String, Vec<LegacyTreeEntry>> = match ron::from_str(data) {
+        Ok(legacy) => legacy,
+        Err(_) => BTreeMap::from([("default".to_string(), ron::from_str(data)?)]),
+    };
+    Ok(legacy
+        .into_iter()
+        .map(|(shaper_id, tree)| {
+            (
+                shaper_id,
+                tree.into_iter().map(LegacyTreeEntry::into_entry).collect(),
+            )
+        })
+        .collect())
 }
 
+/// The IDs of a node and all of its ancestors. A node that isn't in the tree
+/// (yet) is returned on its own.
 pub fn get_parent_ids(start: &str) -> Vec<String> {
-    let mut current = get_tree_node_by_id(start);
-    let mut result = vec![current.id.clone()];
-    while current.parent.is_some() {
-        current = get_tree_node_by_index(current.parent.unwrap());
-        result.push(current.id);
+    let tree = QUEUE_TREE.read();
+    match tree.index_of(start) {
+        Some(i) => tree.ancestors[i]
+            .iter()
+            .map(|n| tree.entries[*n].id.clone())
+            .collect(),
+        None => vec![start.to_string()],
     }
-    result
 }
@@ -1,6 +1,7 @@
 use lazy_static::*;
 use parking_lot::RwLock;
-use rocket::serde::json::Json;
+use super::BusJson;
+use crate::store::{record_sightings, sightings, SightingKind};
 use std::time::Duration;
 
 lazy_static! {
@@ -8,12 +9,34 @@ lazy_static! {
 }
 
 #[post("/bus/unmapped_clients", data = "<dupes>")]
-pub async fn unmapped_clients(dupes: Json<shared_rest::Unmapped>) {
+pub async fn unmapped_clients(dupes: BusJson<shared_rest::Unmapped>) {
     if let Some(mut lock) = UNMAPPED.try_write_for(Duration::from_secs(2)) {
         *lock = dupes.clients.clone();
     }
+    let stored = record_sightings(
+        SightingKind::UnmappedClient,
+        &dupes.shaper_id,
+        &dupes.clients,
+        true,
+    );
+    if let Err(e) = stored {
+        println!("Unable to store unmapped clients: {e:?}");
+    }
 }
 
 pub fn unmapped_client_list() -> Vec<String> {
     UNMAPPED.read().clone()
 }
+
+/// Loads the unmapped clients that shapers last reported.
+pub fn load_unmapped_clients() -> anyhow::Result<()> {
+    let mut clients: Vec<String> = sightings(SightingKind::UnmappedClient, None)?
+        .into_iter()
+        .filter(|s| s.active)
+        .map(|s| s.value)
+        .collect();
+    clients.sort();
+    clients.dedup();
+    *UNMAPPED.write() = clients;
+    Ok(())
+}
@@ -8,19 +8,125 @@ use std::{fs::File, path::Path, time::Duration};
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct QosManagerConfig {
+    /// Where statistics are stored. Defaults to InfluxDB 2, using the
+    /// `influx_` settings below.
+    #[serde(default)]
+    pub metrics_backend: MetricsBackend,
+    #[serde(default)]
     pub influx_url: String,
+    #[serde(default)]
     pub influx_org: String,
+    #[serde(default)]
     pub influx_token: String,
+    #[serde(default)]
     pub influx_bucket: String,
     pub nms_key: String,
     pub nms_url: String,
     pub crm_key: String,
     pub crm_url: String,
+
+    /// Keys accepted when verifying signed messages from `qos_daemon`. Each
+    /// shaper's `bus_key` must match one of these. List more than one while
+    /// rotating keys.
+    #[serde(default)]
+    pub bus_keys: Vec<String>,
+
+    /// Seconds between UISP/CRM refreshes. Defaults to 300.
+    #[serde(default = "default_uisp_refresh_secs")]
+    pub uisp_refresh_secs: u64,
+
+    /// Seconds between nightly report runs. Defaults to 86400 (a day).
+    #[serde(default = "default_nightly_report_secs")]
+    pub nightly_report_secs: u64,
+
+    /// The shortest `stats_interval_secs` of any shaper. Graphs of short time
+    /// ranges use aggregation windows this fine. Defaults to 60.
+    #[serde(default = "default_stats_resolution_secs")]
+    pub stats_resolution_secs: u64,
+
+    /// Seconds between outage checks. Defaults to 60.
+    #[serde(default = "default_outage_check_secs")]
+    pub outage_check_secs: u64,
+
+    /// A client is silent once it has passed no traffic, answered no probes
+    /// and produced no latency samples for this many seconds. Defaults to 600.
+    #[serde(default = "default_outage_silence_secs")]
+    pub outage_silence_secs: u64,
+
+    /// The share (0 to 1) of a node's clients that must be silent for the
+    /// node to be considered down. Defaults to 0.5.
+    #[serde(default = "default_outage_client_share")]
+    pub outage_client_share: f64,
+
+    /// Nodes with fewer active clients than this are only judged down by
+    /// UISP's device status. Defaults to 3.
+    #[serde(default = "default_outage_min_clients")]
+    pub outage_min_clients: usize,
+
+    /// A shaper's bandwidth, latency or host feed is stale once nothing has
+    /// been received on it for this many seconds. Defaults to 300.
+    #[serde(default = "default_feed_stale_secs")]
+    pub feed_stale_secs: u64,
+
+    /// The SQLite database holding speed limits, trees, report history and
+    /// the audit log. Defaults to `qos_manager.db`.
+    #[serde(default = "default_state_db_path")]
+    pub state_db_path: String,
+}
+
+fn default_uisp_refresh_secs() -> u64 {
+    300
+}
+
+fn default_nightly_report_secs() -> u64 {
+    86_400
+}
+
+fn default_stats_resolution_secs() -> u64 {
+    60
+}
+
+fn default_outage_check_secs() -> u64 {
+    60
+}
+
+fn default_outage_silence_secs() -> u64 {
+    600
+}
+
+fn default_outage_client_share() -> f64 {
+    0.5
+}
+
+fn default_outage_min_clients() -> usize {
+    3
+}
+
+fn default_feed_stale_secs() -> u64 {
+    300
+}
+
+fn default_state_db_path() -> String {
+    "qos_manager.db".to_string()
+}
+
+/// The statistics storage backends.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(crate = "rocket::serde")]
+pub enum MetricsBackend {
+    /// An InfluxDB 2 server.
+    #[default]
+    Influx,
+
+    /// Files on the manager's own disk; no database server needed. Data older
+    /// than `retention_days` is deleted.
+    Embedded { path: String, retention_days: u32 },
 }
 
 impl QosManagerConfig {
     fn default() -> Self {
         Self {
+            metrics_backend: MetricsBackend::Influx,
             influx_url: String::new(),
             influx_org: String::new(),
             influx_token: String::new(),
@@ -29,6 +135,16 @@ impl QosManagerConfig {
             nms_url: String::new(),
             crm_key: String::new(),
             crm_url: String::new(),
+            bus_keys: Vec::new(),
+            uisp_refresh_secs: default_uisp_refresh_secs(),
+            nightly_report_secs: default_nightly_report_secs(),
+            stats_resolution_secs: default_stats_resolution_secs(),
+            outage_check_secs: default_outage_check_secs(),
+            outage_silence_secs: default_outage_silence_secs(),
+            outage_client_share: default_outage_client_share(),
+            outage_min_clients: default_outage_min_clients(),
+            feed_stale_secs: default_feed_stale_secs(),
+            state_db_path: default_state_db_path(),
         }
     }
 }
@@ -45,7 +161,15 @@ pub fn load_config() -> Result<()> {
         return Err(Error::msg("Please setup {CONFIG_FILENAME}"));
     }
     let f = File::open(CONFIG_FILENAME).unwrap();
-    let cfg: QosManagerConfig = from_reader(f)?;
+    let mut cfg: QosManagerConfig = from_reader(f)?;
+    // A zero interval would spin.
+    cfg.uisp_refresh_secs = cfg.uisp_refresh_secs.max(1);
+    cfg.nightly_report_secs = cfg.nightly_report_secs.max(1);
+    cfg.stats_resolution_secs = cfg.stats_resolution_secs.max(1);
+    cfg.outage_check_secs = cfg.outage_check_secs.max(1);
+    if cfg.bus_keys.iter().all(|k| k.is_empty()) {
+        println!("No bus_keys are configured: all reports from shapers will be rejected.");
+    }
     if let Some(mut lock) = CONFIG.try_write_for(Duration::from_secs(2)) {
         *lock = cfg;
     }
@@ -1,9 +1,11 @@
+#[derive(Clone)]
 pub enum AggregateFunction {
     Min,
     Max,
     Last,
     Median,
     Mean,
+    Sum,
 }
 
 impl AggregateFunction {
@@ -14,6 +16,7 @@ impl AggregateFunction {
             AggregateFunction::Min => "min".to_string(),
             AggregateFunction::Median => "median".to_string(),
             AggregateFunction::Mean => "mean".to_string(),
+            AggregateFunction::Sum => "sum".to_string(),
         }
     }
 }
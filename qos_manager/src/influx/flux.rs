@@ -0,0 +1,306 @@
+use chrono::{DateTime, FixedOffset};
+use rocket::request::FromParam;
+use std::fmt;
+
+/// Quotes a value as a Flux string literal. Quotes, backslashes and `$`
+/// (which would start string interpolation) are escaped, so the value can't
+/// alter the query around it.
+pub fn flux_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '$' => result.push_str("\\$"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Units accepted in a Flux duration literal.
+const DURATION_UNITS: [&str; 10] = ["ns", "us", "ms", "s", "m", "h", "d", "w", "mo", "y"];
+
+/// A Flux duration literal, such as `5m`, `-24h` or `1h30m`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FluxDuration(String);
+
+impl FluxDuration {
+    /// Parses a duration, rejecting anything that isn't a valid Flux
+    /// duration literal.
+    pub fn parse(duration: &str) -> Option<Self> {
+        let body = duration.strip_prefix('-').unwrap_or(duration);
+        let mut rest = body;
+        if rest.is_empty() {
+            return None;
+        }
+        while !rest.is_empty() {
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                return None;
+            }
+            rest = &rest[digits..];
+            let unit_len = rest.chars().take_while(|c| c.is_ascii_lowercase()).count();
+            if !DURATION_UNITS.contains(&&rest[..unit_len]) {
+                return None;
+            }
+            rest = &rest[unit_len..];
+        }
+        Some(Self(duration.to_string()))
+    }
+
+    pub fn seconds(n: u32) -> Self {
+        Self(format!("{n}s"))
+    }
+
+    pub fn minutes(n: u32) -> Self {
+        Self(format!("{n}m"))
+    }
+
+    pub fn hours(n: u32) -> Self {
+        Self(format!("{n}h"))
+    }
+
+    pub fn days(n: u32) -> Self {
+        Self(format!("{n}d"))
+    }
+
+    /// The length of the duration in nanoseconds (negative if it points into
+    /// the past). Months are taken as 30 days, and years as 365.
+    pub fn as_nanos(&self) -> i64 {
+        let (sign, body) = match self.0.strip_prefix('-') {
+            Some(body) => (-1, body),
+            None => (1, self.0.as_str()),
+        };
+        let mut total: i64 = 0;
+        let mut rest = body;
+        while !rest.is_empty() {
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            let amount = rest[..digits].parse::<i64>().unwrap_or(0);
+            rest = &rest[digits..];
+            let unit_len = rest.chars().take_while(|c| c.is_ascii_lowercase()).count();
+            let unit: i64 = match &rest[..unit_len] {
+                "ns" => 1,
+                "us" => 1_000,
+                "ms" => 1_000_000,
+                "s" => 1_000_000_000,
+                "m" => 60_000_000_000,
+                "h" => 3_600_000_000_000,
+                "d" => 86_400_000_000_000,
+                "w" => 7 * 86_400_000_000_000,
+                "mo" => 30 * 86_400_000_000_000,
+                _ => 365 * 86_400_000_000_000,
+            };
+            rest = &rest[unit_len..];
+            total = total.saturating_add(amount.saturating_mul(unit));
+        }
+        sign * total
+    }
+
+    /// The same duration, pointing into the past.
+    fn negated(&self) -> Self {
+        match self.0.strip_prefix('-') {
+            Some(positive) => Self(positive.to_string()),
+            None => Self(format!("-{}", self.0)),
+        }
+    }
+}
+
+impl fmt::Display for FluxDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'a> FromParam<'a> for FluxDuration {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(param).ok_or(param)
+    }
+}
+
+/// One end of a Flux time range: either relative to now, or an absolute time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FluxTime {
+    Relative(FluxDuration),
+    Absolute(DateTime<FixedOffset>),
+}
+
+impl FluxTime {
+    fn parse(time: &str) -> Option<Self> {
+        let time = time.trim();
+        if let Some(duration) = FluxDuration::parse(time) {
+            Some(FluxTime::Relative(duration))
+        } else {
+            DateTime::parse_from_rfc3339(time)
+                .ok()
+                .map(FluxTime::Absolute)
+        }
+    }
+
+    /// The time in nanoseconds since the Unix epoch, with relative times
+    /// measured from `now`.
+    pub fn resolve(&self, now: i64) -> i64 {
+        match self {
+            FluxTime::Relative(duration) => now.saturating_add(duration.as_nanos()),
+            FluxTime::Absolute(time) => time.timestamp_nanos(),
+        }
+    }
+}
+
+impl fmt::Display for FluxTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FluxTime::Relative(duration) => write!(f, "{duration}"),
+            FluxTime::Absolute(time) => write!(f, "{}", time.to_rfc3339()),
+        }
+    }
+}
+
+/// The time range of a query, as passed to Flux's `range()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxRange {
+    pub start: FluxTime,
+    pub stop: Option<FluxTime>,
+}
+
+impl FluxRange {
+    /// From `duration` ago until now.
+    pub fn last(duration: FluxDuration) -> Self {
+        Self {
+            start: FluxTime::Relative(duration.negated()),
+            stop: None,
+        }
+    }
+
+    /// Parses the range format sent by the web interface: `start: -24h`, or
+    /// `start: <RFC3339 time>, stop: <RFC3339 time>`.
+    pub fn parse(range: &str) -> Option<Self> {
+        let mut start = None;
+        let mut stop = None;
+        for part in range.split(',') {
+            let (key, value) = part.split_once(':')?;
+            let value = FluxTime::parse(value)?;
+            match key.trim() {
+                "start" if start.is_none() => start = Some(value),
+                "stop" if stop.is_none() => stop = Some(value),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            start: start?,
+            stop,
+        })
+    }
+}
+
+impl fmt::Display for FluxRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "start: {}", self.start)?;
+        if let Some(stop) = &self.stop {
+            write!(f, ", stop: {stop}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> FromParam<'a> for FluxRange {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(param).ok_or(param)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{flux_string, FluxDuration, FluxRange, FluxTime};
+
+    #[test]
+    fn strings_are_quoted_and_escaped() {
+        assert_eq!(flux_string("site-1"), r#""site-1""#);
+        assert_eq!(flux_string(r#"a"b"#), r#""a\"b""#);
+        assert_eq!(flux_string(r"a\b"), r#""a\\b""#);
+        assert_eq!(flux_string("${secret}"), r#""\${secret}""#);
+        assert_eq!(flux_string("a\nb\r\tc"), r#""a\nb\r\tc""#);
+    }
+
+    #[test]
+    fn strings_cannot_close_the_literal() {
+        // A trailing backslash can't escape the closing quote, and a quote
+        // can't end the literal early.
+        assert_eq!(flux_string(r"x\"), r#""x\\""#);
+        let quoted = flux_string(r#"x") |> drop(columns: ["_value"]) //"#);
+        let inner = &quoted[1..quoted.len() - 1];
+        let mut escaped = false;
+        for c in inner.chars() {
+            assert!(escaped || c != '"', "unescaped quote in {quoted}");
+            escaped = !escaped && c == '\\';
+        }
+    }
+
+    #[test]
+    fn valid_durations_parse() {
+        for duration in ["5m", "-24h", "1h30m", "1mo", "250ms", "2w", "1y"] {
+            assert_eq!(
+                FluxDuration::parse(duration).map(|d| d.to_string()),
+                Some(duration.to_string())
+            );
+        }
+        assert_eq!(
+            FluxDuration::parse("1h30m").unwrap().as_nanos(),
+            5_400_000_000_000
+        );
+        assert_eq!(
+            FluxDuration::parse("-1s").unwrap().as_nanos(),
+            -1_000_000_000
+        );
+    }
+
+    #[test]
+    fn invalid_durations_are_rejected() {
+        for duration in [
+            "", "-", "5", "m", "5x", "5M", "-5", "1h 30m", "5m)", "5m,", "5m\n", "${d}", "5m|>",
+        ] {
+            assert_eq!(FluxDuration::parse(duration), None, "{duration:?}");
+        }
+    }
+
+    #[test]
+    fn ranges_parse() {
+        let range = FluxRange::parse("start: -24h").unwrap();
+        assert_eq!(
+            range.start,
+            FluxTime::Relative(FluxDuration::parse("-24h").unwrap())
+        );
+        assert_eq!(range.stop, None);
+
+        let range =
+            FluxRange::parse("start: 2022-01-01T00:00:00Z, stop: 2022-01-02T00:00:00+00:00")
+                .unwrap();
+        assert!(matches!(range.start, FluxTime::Absolute(_)));
+        assert!(matches!(range.stop, Some(FluxTime::Absolute(_))));
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        for range in [
+            "",
+            "-24h",
+            "stop: -1h",
+            "start: -1h, start: -2h",
+            "start: -1h, end: now()",
+            "start: -1h) |> drop(columns: [\"_value\"]",
+            "start: ${start}",
+            "start: yesterday",
+        ] {
+            assert_eq!(FluxRange::parse(range), None, "{range:?}");
+        }
+    }
+}
@@ -1,3 +1,5 @@
+mod flux;
+pub use flux::*;
 mod query;
 pub use query::*;
 mod query_filter;
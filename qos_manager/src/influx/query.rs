@@ -1,6 +1,6 @@
-use super::InfluxQuery;
+use super::{flux_string, InfluxQuery};
+use crate::metrics::metrics_store;
 use anyhow::Result;
-use influxdb2::{models::Query, Client};
 
 pub struct InfluxResult<T>(pub Vec<T>);
 
@@ -11,14 +11,15 @@ impl<T: PartialOrd> InfluxResult<T> {
     }
 }
 
-fn build_query(query_builder: InfluxQuery) -> String {
-    let bucket = &query_builder.bucket;
+/// Writes a query in Flux, for InfluxDB.
+pub fn build_query(query_builder: &InfluxQuery) -> String {
+    let bucket = flux_string(&query_builder.bucket);
     let range = &query_builder.range;
-    let measurement = &query_builder.measurement;
+    let measurement = flux_string(&query_builder.measurement);
     let mut query = format!(
-        "from(bucket: \"{bucket}\")
+        "from(bucket: {bucket})
     |> range({range})
-    |> filter(fn: (r) => r[\"_measurement\"] == \"{measurement}\")
+    |> filter(fn: (r) => r[\"_measurement\"] == {measurement})
     "
     );
     query_builder
@@ -28,7 +29,7 @@ fn build_query(query_builder: InfluxQuery) -> String {
     let aggregate_function = query_builder.aggregate_function.name();
     let aggregate = &query_builder.aggregate;
     if let Some(group) = &query_builder.group {
-        query += &format!("|> group(columns: [\"{group}\"])\n");
+        query += &format!("|> group(columns: [{}])\n", flux_string(group));
     }
     query += &format!(
         "|> aggregateWindow(every: {aggregate}, fn: {aggregate_function}, createEmpty: false)"
@@ -48,14 +49,12 @@ fn build_query(query_builder: InfluxQuery) -> String {
     query
 }
 
+/// Runs a query against the configured statistics store.
 pub async fn influx_query<T: influxdb2_structmap::FromMap>(
     query_builder: InfluxQuery,
 ) -> Result<InfluxResult<T>> {
-    let cfg = crate::configuration();
-    let query = Query::new(build_query(query_builder));
-
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result: Vec<T> = client.query::<T>(Some(query)).await?;
-
-    Ok(InfluxResult(result))
+    let rows = metrics_store().query(&query_builder).await?;
+    Ok(InfluxResult(
+        rows.into_iter().map(T::from_genericmap).collect(),
+    ))
 }
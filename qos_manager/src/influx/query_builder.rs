@@ -1,10 +1,11 @@
-use super::{AggregateFunction, InfluxResult, QueryFilter};
+use super::{AggregateFunction, FluxDuration, FluxRange, InfluxResult, QueryFilter};
 use anyhow::Result;
 
+#[derive(Clone)]
 pub struct InfluxQuery {
     pub bucket: String,
-    pub range: String,
-    pub aggregate: String,
+    pub range: FluxRange,
+    pub aggregate: FluxDuration,
     pub aggregate_function: AggregateFunction,
     pub measurement: String,
     pub filters: Vec<QueryFilter>,
@@ -17,8 +18,8 @@ impl InfluxQuery {
         let cfg = crate::configuration();
         Self {
             bucket: cfg.influx_bucket.clone(),
-            range: String::new(),
-            aggregate: String::new(),
+            range: FluxRange::last(FluxDuration::hours(1)),
+            aggregate: FluxDuration::minutes(1),
             aggregate_function: AggregateFunction::Last,
             measurement: String::new(),
             filters: Vec::new(),
@@ -27,32 +28,17 @@ impl InfluxQuery {
         }
     }
 
-    pub fn with_range_string<T: ToString>(mut self, range: T) -> Self {
-        self.range = range.to_string();
+    pub fn with_range(mut self, range: &FluxRange) -> Self {
+        self.range = range.clone();
         self
     }
 
-    pub fn with_range_from_url(mut self, range: &str) -> Self {
-        self.range = urlencoding::decode(range).unwrap().to_string();
-        self
-    }
-
-    pub fn with_aggregate<T: ToString>(
-        mut self,
-        aggregate: T,
-        aggregate_function: AggregateFunction,
-    ) -> Self {
-        self.aggregate = aggregate.to_string();
-        self.aggregate_function = aggregate_function;
-        self
-    }
-
-    pub fn with_aggregate_from_url(
+    pub fn with_aggregate(
         mut self,
-        aggregate: &str,
+        aggregate: &FluxDuration,
         aggregate_function: AggregateFunction,
     ) -> Self {
-        self.aggregate = urlencoding::decode(aggregate).unwrap().to_string();
+        self.aggregate = aggregate.clone();
         self.aggregate_function = aggregate_function;
         self
     }
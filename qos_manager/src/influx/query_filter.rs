@@ -1,3 +1,7 @@
+use super::flux_string;
+
+/// A filter on a column of the query. Column names and values are escaped.
+#[derive(Clone)]
 pub enum QueryFilter {
     MatchOne {
         field: String,
@@ -8,13 +12,78 @@ pub enum QueryFilter {
         value_1: String,
         value_2: String,
     },
+    AnyOf {
+        field: String,
+        values: Vec<String>,
+    },
+    /// Like `MatchOne`, but also matches rows without the column.
+    MatchOrMissing {
+        field: String,
+        value: String,
+    },
 }
 
 impl QueryFilter {
-    pub fn to_query_string(&self) -> String {
+    /// Matches rows whose `_field` is `name`.
+    pub fn field<T: ToString>(name: T) -> Self {
+        QueryFilter::MatchOne {
+            field: "_field".to_string(),
+            value: name.to_string(),
+        }
+    }
+
+    /// Matches rows where the column `field` (a tag, such as `site`) equals `value`.
+    pub fn tag<T: ToString, U: ToString>(field: T, value: U) -> Self {
+        QueryFilter::MatchOne {
+            field: field.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Matches rows where the tag `field` equals `value`, or isn't set.
+    pub fn tag_or_missing<T: ToString, U: ToString>(field: T, value: U) -> Self {
+        QueryFilter::MatchOrMissing {
+            field: field.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Whether rows without the filter's column pass it.
+    pub fn matches_missing(&self) -> bool {
+        matches!(self, QueryFilter::MatchOrMissing { .. })
+    }
+
+    /// The column this filter tests, and the values it accepts.
+    pub fn column_and_values(&self) -> (&str, Vec<&str>) {
         match self {
-            QueryFilter::MatchOne{field, value} => format!("|> filter(fn: (r) => r[\"{field}\"] == \"{value}\")\n"),
-            QueryFilter::Either{field, value_1, value_2} => format!("|> filter(fn: (r) => r[\"{field}\"] == \"{value_1}\" or r[\"{field}\"] == \"{value_2}\")\n"),
+            QueryFilter::MatchOne { field, value }
+            | QueryFilter::MatchOrMissing { field, value } => (field, vec![value]),
+            QueryFilter::Either {
+                field,
+                value_1,
+                value_2,
+            } => (field, vec![value_1, value_2]),
+            QueryFilter::AnyOf { field, values } => {
+                (field, values.iter().map(|v| v.as_str()).collect())
+            }
+        }
+    }
+
+    pub fn to_query_string(&self) -> String {
+        let (field, values) = self.column_and_values();
+        let column = format!("r[{}]", flux_string(field));
+        let mut conditions: Vec<String> = values
+            .iter()
+            .map(|value| format!("{column} == {}", flux_string(value)))
+            .collect();
+        if self.matches_missing() {
+            conditions.insert(0, format!("not exists {column}"));
         }
+        let condition = if conditions.is_empty() {
+            "false".to_string()
+        } else {
+            conditions.join(" or ")
+        };
+        format!("|> filter(fn: (r) => {condition})\n")
     }
 }
@@ -1,30 +1,39 @@
 #[macro_use]
 extern crate rocket;
+mod auth;
+use auth::ReadOnlyUser;
 mod bus;
 use bus::*;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 mod queries;
 use rocket::{fairing::AdHoc, fs::NamedFile};
 mod config;
 use crate::config::configuration;
 pub mod influx;
+mod metrics;
+mod prometheus;
 mod reports;
+mod outages;
+mod alerts;
+mod topology;
+mod store;
+mod backup;
 
 /// Opens the index page
 #[get("/")]
-pub async fn index<'a>() -> Option<NamedFile> {
+pub async fn index<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/index.html").await.ok()
 }
 
 /// Opens the system page
 #[get("/system")]
-pub async fn system<'a>() -> Option<NamedFile> {
+pub async fn system<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/system.html").await.ok()
 }
 
 /// Opens the tree page
 #[get("/tree")]
-pub async fn tree<'a>() -> Option<NamedFile> {
+pub async fn tree<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/tree.html").await.ok()
 }
 
@@ -36,95 +45,125 @@ pub async fn plotly<'a>() -> Option<NamedFile> {
 
 /// Opens a site page. id is passed but ignored, it is used client-side.
 #[get("/site/<_id>")]
-pub async fn site<'a>(_id: String) -> Option<NamedFile> {
+pub async fn site<'a>(_user: ReadOnlyUser, _id: String) -> Option<NamedFile> {
     NamedFile::open("static/site.html").await.ok()
 }
 
 /// Opens an access point page. id is passed but ignored, it is used client-side.
 #[get("/access_point/<_id>")]
-pub async fn access_point<'a>(_id: String) -> Option<NamedFile> {
+pub async fn access_point<'a>(_user: ReadOnlyUser, _id: String) -> Option<NamedFile> {
     NamedFile::open("static/access_point.html").await.ok()
 }
 
 /// Opens a client page. id is passed but ignored, it is used client-side.
 #[get("/client/<_id>")]
-pub async fn client<'a>(_id: String) -> Option<NamedFile> {
+pub async fn client<'a>(_user: ReadOnlyUser, _id: String) -> Option<NamedFile> {
     NamedFile::open("static/client.html").await.ok()
 }
 
 /// Opens a list of duplicate IP addresses
 #[get("/ip_dupe")]
-pub async fn ip_dupe<'a>() -> Option<NamedFile> {
+pub async fn ip_dupe<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/ip_dupe.html").await.ok()
 }
 
 /// Opens the reports page.
 #[get("/reports")]
-pub async fn reports_page<'a>() -> Option<NamedFile> {
+pub async fn reports_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/reports.html").await.ok()
 }
 
 /// Opens the congestion report
 #[get("/congestion")]
-pub async fn congestion<'a>() -> Option<NamedFile> {
+pub async fn congestion<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/congestion.html").await.ok()
 }
 
 /// Opens the AP congestion report
 #[get("/congestion_ap")]
-pub async fn congestion_ap<'a>() -> Option<NamedFile> {
+pub async fn congestion_ap<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/congestion_ap.html").await.ok()
 }
 
 /// Opens the client congestion report
 #[get("/congestion_client")]
-pub async fn congestion_client<'a>() -> Option<NamedFile> {
+pub async fn congestion_client<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/congestion_client.html").await.ok()
 }
 
 /// Opens the site latency report
 #[get("/site_latency")]
-pub async fn site_latency_page<'a>() -> Option<NamedFile> {
+pub async fn site_latency_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/site_latency.html").await.ok()
 }
 
 /// Opens the AP latency report
 #[get("/ap_latency")]
-pub async fn ap_latency_page<'a>() -> Option<NamedFile> {
+pub async fn ap_latency_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/ap_latency.html").await.ok()
 }
 
 /// Opens the client latency report
 #[get("/client_latency")]
-pub async fn client_latency_page<'a>() -> Option<NamedFile> {
+pub async fn client_latency_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/client_latency.html").await.ok()
 }
 
 /// Opens the page compiling billing plan information
 #[get("/billing_plans")]
-pub async fn billing_plans_page<'a>() -> Option<NamedFile> {
+pub async fn billing_plans_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/billing_plans.html").await.ok()
 }
 
 /// Opens the unknown IP addresses page
 #[get("/unknown_ips")]
-pub async fn unknown_ip_addresses_page<'a>() -> Option<NamedFile> {
+pub async fn unknown_ip_addresses_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/unknown_ip.html").await.ok()
 }
 
 /// Opens the unmapped clients report
 #[get("/unmapped")]
-pub async fn unmapped_page<'a>() -> Option<NamedFile> {
+pub async fn unmapped_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/unmapped.html").await.ok()
 }
 
+/// Opens the list of clients that aren't answering pings.
+#[get("/down")]
+pub async fn down_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
+    NamedFile::open("static/down.html").await.ok()
+}
+
+/// Opens the outage report.
+#[get("/outages")]
+pub async fn outages_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
+    NamedFile::open("static/outages.html").await.ok()
+}
+
+/// Opens the queue rebuild timeline.
+#[get("/rebuilds")]
+pub async fn rebuilds_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
+    NamedFile::open("static/rebuilds.html").await.ok()
+}
+
+/// Opens the topology change history.
+#[get("/topology")]
+pub async fn topology_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
+    NamedFile::open("static/topology.html").await.ok()
+}
+
+/// Opens the list of raised alerts.
+#[get("/alerts")]
+pub async fn alerts_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
+    NamedFile::open("static/alerts.html").await.ok()
+}
+
 #[get("/oversell")]
-pub async fn oversell_page<'a>() -> Option<NamedFile> {
+pub async fn oversell_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/oversell.html").await.ok()
 }
 
 #[get("/nightly")]
-pub async fn nightly_page<'a>() -> Option<NamedFile> {
+pub async fn nightly_page<'a>(_user: ReadOnlyUser) -> Option<NamedFile> {
     NamedFile::open("static/nightly.html").await.ok()
 }
 
@@ -144,6 +183,7 @@ async fn nightly_reports() {
 
 async fn periodic_uisp_refresh() {
     loop {
+        let started = Instant::now();
         let (_, _, _) = rocket::tokio::join!(
             queries::get_uisp_devices(),
             queries::get_uisp_sites(),
@@ -151,30 +191,71 @@ async fn periodic_uisp_refresh() {
         );
         queries::poll_ap_frequencies().await;
         queries::poll_signals().await;
+        prometheus::record_uisp_refresh(started.elapsed());
         println!("Completed UISP Refresh");
-        rocket::tokio::time::sleep(Duration::from_secs(300)).await;
+        let interval = configuration().uisp_refresh_secs;
+        rocket::tokio::time::sleep(Duration::from_secs(interval)).await;
     }
 }
 
 #[launch]
 fn rocket() -> _ {
     config::load_config().unwrap();
+    auth::load_users().unwrap();
+    store::open_store(&config::configuration().state_db_path).unwrap();
+    if let Some(code) = backup::run_cli(&std::env::args().collect::<Vec<String>>()) {
+        std::process::exit(code);
+    }
     let _ = load_config();
     let _ = load_tree();
+    let _ = bus::load_duplicate_ips();
+    let _ = bus::load_unmapped_clients();
+    let _ = bus::load_unknown_ips();
+    let _ = outages::load_outages();
+    let _ = bus::load_rebuilds();
+    let _ = topology::load_topology_history();
+    let _ = alerts::load_alerts();
     rocket::build()
         .attach(AdHoc::on_liftoff("Get Devices", |_| {
             Box::pin(async move {
                 rocket::tokio::spawn(periodic_uisp_refresh());
             })
         }))
+        .attach(AdHoc::on_liftoff("Metrics Writer", |_| {
+            Box::pin(async move {
+                rocket::tokio::spawn(metrics::run_metrics_writer());
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Outage Detection", |_| {
+            Box::pin(async move {
+                rocket::tokio::spawn(outages::outage_detector());
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Alerts", |_| {
+            Box::pin(async move {
+                rocket::tokio::spawn(alerts::alert_runner());
+            })
+        }))
         .attach(AdHoc::on_liftoff("Nightly Reports", |_| {
             Box::pin(async move {
                 rocket::tokio::spawn(nightly_reports());
             })
         }))
+        .register("/", catchers![auth::unauthorized])
         .mount(
             "/",
             routes![
+                auth::login_page,
+                auth::users_page,
+                auth::login,
+                auth::logout,
+                auth::whoami,
+                auth::list_users,
+                auth::add_user,
+                auth::remove_user,
+                backup::backup_download,
+                backup::restore_preview,
+                backup::restore_archive,
                 bq,
                 spinner,
                 index,
@@ -194,9 +275,13 @@ fn rocket() -> _ {
                 billing_plans_page,
                 unknown_ip_addresses_page,
                 unmapped_page,
+                shaper_hello,
                 host_usage,
                 latency_report,
                 bandwidth_report,
+                cake_report,
+                reachability_report,
+                rebuild_event,
                 duplicate_ip,
                 unmapped_clients,
                 get_site_config,
@@ -207,6 +292,8 @@ fn rocket() -> _ {
                 queries::site_bandwidth,
                 queries::latency_site,
                 queries::all_tree,
+                queries::shapers,
+                queries::shaper_tree,
                 queries::node_by_id,
                 queries::node_by_index,
                 queries::node_children,
@@ -220,6 +307,20 @@ fn rocket() -> _ {
                 queries::site_funnel,
                 queries::site_funnel_sites,
                 queries::site_drops,
+                queries::site_cake,
+                queries::site_class_stats,
+                queries::site_reachability,
+                queries::down_clients,
+                queries::outage_list,
+                queries::rebuild_list,
+                queries::topology_history,
+                queries::topology_version,
+                queries::node_topology_history,
+                queries::plan_history,
+                queries::alert_list,
+                queries::shaper_status_list,
+                queries::test_alert,
+                queries::audit_entries,
                 queries::find_interface_speed,
                 queries::ap_at_10,
                 queries::device_at_10,
@@ -229,6 +330,9 @@ fn rocket() -> _ {
                 queries::access_point_info,
                 queries::site_device_list,
                 queries::site_suspended,
+                queries::health_status,
+                queries::graph_settings,
+                prometheus::prometheus_metrics,
                 reports_page,
                 reports::site_congestion,
                 reports::ap_congestion,
@@ -239,10 +343,16 @@ fn rocket() -> _ {
                 reports::plan_5m,
                 reports::billing_plans,
                 reports::unknown_ip_addresses,
+                reports::unknown_ip_history,
                 reports::oversell_report,
                 reports::nightly_json,
                 oversell_page,
                 nightly_page,
+                down_page,
+                outages_page,
+                rebuilds_page,
+                topology_page,
+                alerts_page,
             ],
         )
 }
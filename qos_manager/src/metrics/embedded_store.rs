@@ -0,0 +1,429 @@
+use super::{MetricPoint, MetricsStore};
+use crate::influx::{AggregateFunction, InfluxQuery};
+use anyhow::Result;
+use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
+use influxdb2_structmap::{value::Value, GenericMap};
+use parking_lot::Mutex;
+use rocket::serde::json;
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+const NANOS_PER_DAY: i64 = 86_400_000_000_000;
+
+/// Series, by group key (tag name and value pairs), holding (time, value)
+/// rows.
+type SeriesByGroup = BTreeMap<Vec<(String, String)>, Vec<(i64, f64)>>;
+
+/// Stores statistics in files on the manager's disk, so that no database
+/// server is needed. Points are appended (one JSON object per line) to a file
+/// per measurement and day, e.g. `latency/2022-06-01.jsonl`. Queries read the
+/// files covering their range, and aggregate in memory.
+#[derive(Clone)]
+pub struct EmbeddedStore {
+    path: PathBuf,
+    retention_days: u32,
+    /// Serializes writes. Holds the day of the last retention sweep.
+    last_sweep: Arc<Mutex<Option<NaiveDate>>>,
+}
+
+impl EmbeddedStore {
+    pub fn new(path: &str, retention_days: u32) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            retention_days,
+            last_sweep: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Measurement names become directory names, so keep them simple.
+    fn measurement_dir(&self, measurement: &str) -> PathBuf {
+        let name: String = measurement
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.path.join(name)
+    }
+
+    fn day(nanos: i64) -> NaiveDate {
+        Utc.timestamp_nanos(nanos).date_naive()
+    }
+
+    fn write_blocking(&self, points: Vec<MetricPoint>) -> Result<()> {
+        let mut last_sweep = self.last_sweep.lock();
+        let now = Utc::now().timestamp_nanos();
+
+        let mut files: BTreeMap<PathBuf, String> = BTreeMap::new();
+        for mut point in points {
+            let timestamp = *point.timestamp.get_or_insert(now);
+            let file = self
+                .measurement_dir(&point.measurement)
+                .join(format!("{}.jsonl", Self::day(timestamp)));
+            let lines = files.entry(file).or_default();
+            lines.push_str(&json::to_string(&point)?);
+            lines.push('\n');
+        }
+        for (file, lines) in files {
+            if let Some(dir) = file.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&file)?
+                .write_all(lines.as_bytes())?;
+        }
+
+        let today = Self::day(now);
+        if *last_sweep != Some(today) {
+            *last_sweep = Some(today);
+            self.sweep(today);
+        }
+        Ok(())
+    }
+
+    /// Deletes day files older than the retention period.
+    fn sweep(&self, today: NaiveDate) {
+        let oldest = today - chrono::Duration::days(self.retention_days as i64);
+        let dirs = match std::fs::read_dir(&self.path) {
+            Ok(dirs) => dirs,
+            Err(_) => return,
+        };
+        for dir in dirs.filter_map(|d| d.ok()) {
+            if let Ok(files) = std::fs::read_dir(dir.path()) {
+                for file in files.filter_map(|f| f.ok()) {
+                    let path = file.path();
+                    let day = path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+                    if matches!(day, Some(day) if day < oldest) {
+                        let _ = std::fs::remove_file(path);
+                    }
+                }
+            }
+        }
+    }
+
+    fn query_blocking(&self, query: &InfluxQuery) -> Result<Vec<GenericMap>> {
+        let now = Utc::now().timestamp_nanos();
+        let start = query.range.start.resolve(now);
+        let stop = query
+            .range
+            .stop
+            .as_ref()
+            .map(|stop| stop.resolve(now))
+            .unwrap_or(now);
+        let every = query.aggregate.as_nanos().max(1);
+
+        let mut tables: SeriesByGroup = BTreeMap::new();
+        let dir = self.measurement_dir(&query.measurement);
+        let mut day = start - start.rem_euclid(NANOS_PER_DAY);
+        while day < stop {
+            let file = dir.join(format!("{}.jsonl", Self::day(day)));
+            day += NANOS_PER_DAY;
+            let file = match std::fs::File::open(file) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            for line in BufReader::new(file).lines() {
+                let point: MetricPoint = match json::from_str(&line?) {
+                    Ok(point) => point,
+                    Err(_) => continue,
+                };
+                let time = point.timestamp.unwrap_or(0);
+                if time < start || time >= stop || point.measurement != query.measurement {
+                    continue;
+                }
+                for (field, value) in point.fields.iter() {
+                    let column = |name: &str| match name {
+                        "_field" => Some(field.as_str()),
+                        "_measurement" => Some(point.measurement.as_str()),
+                        tag => point.tags.get(tag).map(|t| t.as_str()),
+                    };
+                    let matched = query.filters.iter().all(|filter| {
+                        let (name, values) = filter.column_and_values();
+                        column(name)
+                            .map(|c| values.contains(&c))
+                            .unwrap_or(filter.matches_missing())
+                    });
+                    if !matched {
+                        continue;
+                    }
+                    let key = match &query.group {
+                        Some(group) => column(group)
+                            .map(|value| vec![(group.clone(), value.to_string())])
+                            .unwrap_or_default(),
+                        None => {
+                            let mut key = vec![
+                                ("_field".to_string(), field.clone()),
+                                ("_measurement".to_string(), point.measurement.clone()),
+                            ];
+                            key.extend(point.tags.iter().map(|(k, v)| (k.clone(), v.clone())));
+                            key.sort();
+                            key
+                        }
+                    };
+                    tables.entry(key).or_default().push((time, *value));
+                }
+            }
+        }
+
+        // Aggregate each series into windows of `every`, timestamped with the
+        // end of the window (as Flux's `aggregateWindow` does).
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let mut result = Vec::new();
+        for (key, mut rows) in tables {
+            rows.sort_by_key(|(time, _)| *time);
+            let mut windows: BTreeMap<i64, Vec<f64>> = BTreeMap::new();
+            for (time, value) in rows {
+                let window_stop = (time - time.rem_euclid(every)).saturating_add(every);
+                windows
+                    .entry(i64::min(window_stop, stop))
+                    .or_default()
+                    .push(value);
+            }
+            let mut aggregated: Vec<(i64, f64)> = windows
+                .into_iter()
+                .map(|(time, values)| (time, aggregate(&query.aggregate_function, values)))
+                .collect();
+            if query.with_last {
+                aggregated = aggregated.pop().into_iter().collect();
+            }
+            for (time, value) in aggregated {
+                let mut row: GenericMap = key
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                    .collect();
+                row.insert("_value".to_string(), Value::Double(value));
+                row.insert(
+                    "_time".to_string(),
+                    Value::TimeRFC(utc.timestamp_nanos(time)),
+                );
+                result.push(row);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Applies an aggregate function to a window's values (in time order).
+fn aggregate(function: &AggregateFunction, mut values: Vec<f64>) -> f64 {
+    match function {
+        AggregateFunction::Last => *values.last().unwrap_or(&0.0),
+        AggregateFunction::Min => values.iter().cloned().fold(f64::INFINITY, f64::min),
+        AggregateFunction::Max => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        AggregateFunction::Mean => values.iter().sum::<f64>() / values.len() as f64,
+        AggregateFunction::Sum => values.iter().sum(),
+        AggregateFunction::Median => {
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let middle = values.len() / 2;
+            if values.len().is_multiple_of(2) {
+                (values[middle - 1] + values[middle]) / 2.0
+            } else {
+                values[middle]
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetricsStore for EmbeddedStore {
+    async fn write(&self, points: Vec<MetricPoint>) -> Result<()> {
+        let store = self.clone();
+        rocket::tokio::task::spawn_blocking(move || store.write_blocking(points)).await?
+    }
+
+    async fn query(&self, query: &InfluxQuery) -> Result<Vec<GenericMap>> {
+        let store = self.clone();
+        let query = query.clone();
+        rocket::tokio::task::spawn_blocking(move || store.query_blocking(&query)).await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{aggregate, EmbeddedStore};
+    use crate::influx::{AggregateFunction, FluxDuration, FluxRange, InfluxQuery, QueryFilter};
+    use crate::metrics::MetricPoint;
+    use chrono::TimeZone;
+    use influxdb2_structmap::{value::Value, GenericMap};
+
+    /// 2022-06-01T00:00:00Z, in nanoseconds.
+    const BASE: i64 = 1_654_041_600_000_000_000;
+    const SECOND: i64 = 1_000_000_000;
+
+    /// A store in its own temporary directory, removed when dropped.
+    struct TempStore {
+        store: EmbeddedStore,
+        path: std::path::PathBuf,
+    }
+
+    impl TempStore {
+        fn new(name: &str, points: Vec<MetricPoint>) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "qos_manager_embedded_store_{name}_{}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            // Long enough that the test data isn't swept away.
+            let store = EmbeddedStore::new(path.to_str().unwrap(), 365_000);
+            store.write_blocking(points).unwrap();
+            Self { store, path }
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn point(site: &str, seconds: i64, value: f64) -> MetricPoint {
+        MetricPoint::new("latency")
+            .tag("site", site)
+            .field("latency", value)
+            .timestamp(BASE + seconds * SECOND)
+    }
+
+    fn query(start_secs: i64, stop_secs: i64, function: AggregateFunction) -> InfluxQuery {
+        let time = |seconds: i64| {
+            chrono::Utc
+                .timestamp_nanos(BASE + seconds * SECOND)
+                .to_rfc3339()
+        };
+        InfluxQuery {
+            bucket: String::new(),
+            range: FluxRange::parse(&format!(
+                "start: {}, stop: {}",
+                time(start_secs),
+                time(stop_secs)
+            ))
+            .unwrap(),
+            aggregate: FluxDuration::minutes(1),
+            aggregate_function: function,
+            measurement: "latency".to_string(),
+            filters: vec![QueryFilter::field("latency")],
+            group: Some("site".to_string()),
+            with_last: false,
+        }
+    }
+
+    /// Each row's window end (seconds after `BASE`) and value.
+    fn rows(result: Vec<GenericMap>) -> Vec<(i64, f64)> {
+        result
+            .iter()
+            .map(|row| {
+                let time = match &row["_time"] {
+                    Value::TimeRFC(time) => (time.timestamp_nanos() - BASE) / SECOND,
+                    other => panic!("unexpected _time {other:?}"),
+                };
+                let value = match &row["_value"] {
+                    Value::Double(value) => *value,
+                    other => panic!("unexpected _value {other:?}"),
+                };
+                (time, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn windows_are_stamped_with_their_end() {
+        let store = TempStore::new(
+            "windows",
+            vec![
+                point("a", 10, 1.0),
+                point("a", 50, 3.0),
+                point("a", 70, 5.0),
+            ],
+        );
+        let result = store
+            .store
+            .query_blocking(&query(0, 180, AggregateFunction::Mean))
+            .unwrap();
+        assert_eq!(rows(result), vec![(60, 2.0), (120, 5.0)]);
+    }
+
+    #[test]
+    fn last_window_is_clamped_to_stop() {
+        let store = TempStore::new(
+            "clamp",
+            vec![
+                point("a", 30, 1.0),
+                point("a", 70, 2.0),
+                point("a", 95, 4.0),
+            ],
+        );
+        // The point at 95s is outside the range; the one at 70s falls in a
+        // window cut short at 90s.
+        let result = store
+            .store
+            .query_blocking(&query(0, 90, AggregateFunction::Max))
+            .unwrap();
+        assert_eq!(rows(result), vec![(60, 1.0), (90, 2.0)]);
+    }
+
+    #[test]
+    fn with_last_keeps_only_the_final_window() {
+        let store = TempStore::new(
+            "last",
+            vec![
+                point("a", 10, 1.0),
+                point("a", 70, 2.0),
+                point("a", 130, 3.0),
+            ],
+        );
+        let mut query = query(0, 180, AggregateFunction::Last);
+        query.with_last = true;
+        let result = store.store.query_blocking(&query).unwrap();
+        assert_eq!(rows(result), vec![(180, 3.0)]);
+    }
+
+    #[test]
+    fn points_are_filtered_and_grouped() {
+        let store = TempStore::new(
+            "filter",
+            vec![
+                point("a", 10, 1.0),
+                point("b", 20, 7.0),
+                point("a", 30, 3.0).tag("source", "icmp"),
+            ],
+        );
+        let mut query = query(0, 60, AggregateFunction::Sum);
+        query
+            .filters
+            .push(QueryFilter::tag_or_missing("source", "pping"));
+        let result = store.store.query_blocking(&query).unwrap();
+        let sites: Vec<String> = result
+            .iter()
+            .map(|row| match &row["site"] {
+                Value::String(site) => site.clone(),
+                other => panic!("unexpected site {other:?}"),
+            })
+            .collect();
+        assert_eq!(sites, vec!["a", "b"]);
+        assert_eq!(rows(result), vec![(60, 1.0), (60, 7.0)]);
+    }
+
+    #[test]
+    fn median_of_odd_and_even_counts() {
+        let median = |values: &[f64]| aggregate(&AggregateFunction::Median, values.to_vec());
+        assert_eq!(median(&[5.0, 1.0, 3.0]), 3.0);
+        assert_eq!(median(&[4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median(&[7.0]), 7.0);
+    }
+
+    #[test]
+    fn other_aggregates() {
+        let values = vec![2.0, 8.0, 5.0];
+        assert_eq!(aggregate(&AggregateFunction::Min, values.clone()), 2.0);
+        assert_eq!(aggregate(&AggregateFunction::Max, values.clone()), 8.0);
+        assert_eq!(aggregate(&AggregateFunction::Mean, values.clone()), 5.0);
+        assert_eq!(aggregate(&AggregateFunction::Sum, values.clone()), 15.0);
+        assert_eq!(aggregate(&AggregateFunction::Last, values), 5.0);
+    }
+}
@@ -0,0 +1,66 @@
+use super::{MetricPoint, MetricsStore};
+use crate::influx::{build_query, InfluxQuery};
+use anyhow::Result;
+use influxdb2::{
+    models::{DataPoint, Query},
+    Client,
+};
+use influxdb2_structmap::{FromMap, GenericMap};
+use rocket::futures::stream;
+
+/// Stores statistics in an InfluxDB 2 server.
+pub struct InfluxStore {
+    client: Client,
+    bucket: String,
+}
+
+impl InfluxStore {
+    pub fn new(url: &str, org: &str, token: &str, bucket: &str) -> Self {
+        Self {
+            client: Client::new(url, org, token),
+            bucket: bucket.to_string(),
+        }
+    }
+}
+
+/// A query result row, kept as Influx returned it.
+#[derive(Default)]
+struct Row(GenericMap);
+
+impl FromMap for Row {
+    fn from_genericmap(map: GenericMap) -> Self {
+        Row(map)
+    }
+}
+
+#[rocket::async_trait]
+impl MetricsStore for InfluxStore {
+    async fn write(&self, points: Vec<MetricPoint>) -> Result<()> {
+        let mut data_points = Vec::with_capacity(points.len());
+        for point in points {
+            let mut builder = DataPoint::builder(point.measurement);
+            for (key, value) in point.tags {
+                builder = builder.tag(key, value);
+            }
+            for (key, value) in point.fields {
+                builder = builder.field(key, value);
+            }
+            if let Some(timestamp) = point.timestamp {
+                builder = builder.timestamp(timestamp);
+            }
+            data_points.push(builder.build()?);
+        }
+        self.client
+            .write(&self.bucket, stream::iter(data_points))
+            .await?;
+        Ok(())
+    }
+
+    async fn query(&self, query: &InfluxQuery) -> Result<Vec<GenericMap>> {
+        let rows = self
+            .client
+            .query::<Row>(Some(Query::new(build_query(query))))
+            .await?;
+        Ok(rows.into_iter().map(|row| row.0).collect())
+    }
+}
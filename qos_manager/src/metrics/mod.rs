@@ -0,0 +1,63 @@
+//! Statistics storage. Reports are written, and `InfluxQuery` reads them back,
+//! through a `MetricsStore`: either an InfluxDB 2 server or files on the
+//! manager's own disk, depending upon `metrics_backend` in the configuration.
+//! Writes are queued by `write_points`, and stored in batches by a single
+//! background task (`run_metrics_writer`).
+
+mod point;
+pub use point::*;
+mod influx_store;
+pub use influx_store::*;
+mod embedded_store;
+pub use embedded_store::*;
+mod writer;
+pub use writer::*;
+
+use crate::{
+    config::{configuration, MetricsBackend},
+    influx::InfluxQuery,
+};
+use anyhow::Result;
+use influxdb2_structmap::GenericMap;
+use lazy_static::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+#[rocket::async_trait]
+pub trait MetricsStore: Send + Sync {
+    /// Stores a batch of points.
+    async fn write(&self, points: Vec<MetricPoint>) -> Result<()>;
+
+    /// Runs a query (range, filters, grouping, aggregation and optionally only
+    /// the last window). Returns one map per row, holding `_time`, `_value`
+    /// and the series' tags (including `_field`, unless regrouped).
+    async fn query(&self, query: &InfluxQuery) -> Result<Vec<GenericMap>>;
+}
+
+lazy_static! {
+    static ref STORE: RwLock<Option<Arc<dyn MetricsStore>>> = RwLock::new(None);
+}
+
+/// The configured statistics store.
+pub fn metrics_store() -> Arc<dyn MetricsStore> {
+    if let Some(store) = STORE.read().as_ref() {
+        return store.clone();
+    }
+    let mut lock = STORE.write();
+    lock.get_or_insert_with(|| {
+        let cfg = configuration();
+        match &cfg.metrics_backend {
+            MetricsBackend::Influx => Arc::new(InfluxStore::new(
+                &cfg.influx_url,
+                &cfg.influx_org,
+                &cfg.influx_token,
+                &cfg.influx_bucket,
+            )),
+            MetricsBackend::Embedded {
+                path,
+                retention_days,
+            } => Arc::new(EmbeddedStore::new(path, *retention_days)),
+        }
+    })
+    .clone()
+}
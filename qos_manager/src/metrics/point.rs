@@ -0,0 +1,41 @@
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A single measurement to store: for example, one site's bandwidth at one
+/// moment. Tags identify the series; fields hold the values.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct MetricPoint {
+    pub measurement: String,
+    pub tags: BTreeMap<String, String>,
+    pub fields: BTreeMap<String, f64>,
+
+    /// Nanoseconds since the Unix epoch. If `None`, the time of writing is used.
+    pub timestamp: Option<i64>,
+}
+
+impl MetricPoint {
+    pub fn new<T: ToString>(measurement: T) -> Self {
+        Self {
+            measurement: measurement.to_string(),
+            tags: BTreeMap::new(),
+            fields: BTreeMap::new(),
+            timestamp: None,
+        }
+    }
+
+    pub fn tag<K: ToString, V: ToString>(mut self, key: K, value: V) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn field<K: ToString>(mut self, key: K, value: f64) -> Self {
+        self.fields.insert(key.to_string(), value);
+        self
+    }
+
+    pub fn timestamp(mut self, nanos: i64) -> Self {
+        self.timestamp = Some(nanos);
+        self
+    }
+}
@@ -0,0 +1,147 @@
+use super::{metrics_store, MetricPoint};
+use lazy_static::*;
+use parking_lot::Mutex;
+use rocket::{
+    serde::Serialize,
+    tokio::{sync::Notify, time::timeout},
+};
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Points are written in batches of (at most) this many.
+const BATCH_SIZE: usize = 5000;
+
+/// Queued points are written at least this often, even if there aren't
+/// enough to fill a batch.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// If the store is unreachable, at most this many points are kept (the
+/// oldest are discarded first).
+const MAX_QUEUED_POINTS: usize = 500_000;
+
+/// Longest wait between retries when writes are failing.
+const MAX_BACKOFF_SECS: u64 = 60;
+
+/// A point waiting to be written, with the time (Unix seconds) it was queued.
+struct QueuedPoint {
+    queued_at: i64,
+    point: MetricPoint,
+}
+
+lazy_static! {
+    static ref QUEUE: Mutex<VecDeque<QueuedPoint>> = Mutex::new(VecDeque::new());
+    static ref WAKE_WRITER: Notify = Notify::new();
+    static ref LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+}
+
+static POINTS_WRITTEN: AtomicU64 = AtomicU64::new(0);
+static POINTS_DROPPED: AtomicU64 = AtomicU64::new(0);
+static WRITE_ERRORS: AtomicU64 = AtomicU64::new(0);
+static LAST_SUCCESS: AtomicI64 = AtomicI64::new(0);
+
+/// Queues points for the background writer. Points without a timestamp are
+/// stamped now, so that buffering doesn't delay them.
+pub fn write_points(points: Vec<MetricPoint>) {
+    let now = chrono::Utc::now();
+    let mut queue = QUEUE.lock();
+    for mut point in points {
+        point.timestamp.get_or_insert(now.timestamp_nanos());
+        queue.push_back(QueuedPoint {
+            queued_at: now.timestamp(),
+            point,
+        });
+    }
+    trim_queue(&mut queue);
+    if queue.len() >= BATCH_SIZE {
+        WAKE_WRITER.notify_one();
+    }
+}
+
+fn trim_queue(queue: &mut VecDeque<QueuedPoint>) {
+    if queue.len() > MAX_QUEUED_POINTS {
+        let excess = queue.len() - MAX_QUEUED_POINTS;
+        queue.drain(..excess);
+        POINTS_DROPPED.fetch_add(excess as u64, Ordering::Relaxed);
+    }
+}
+
+/// Writes queued points to the metrics store, in batches, forever. Failed
+/// batches go back on the queue and are retried with exponential backoff.
+pub async fn run_metrics_writer() {
+    let mut backoff = 1;
+    loop {
+        let _ = timeout(FLUSH_INTERVAL, WAKE_WRITER.notified()).await;
+        loop {
+            let batch: Vec<QueuedPoint> = {
+                let mut queue = QUEUE.lock();
+                let n = usize::min(queue.len(), BATCH_SIZE);
+                queue.drain(..n).collect()
+            };
+            if batch.is_empty() {
+                break;
+            }
+            let points = batch.iter().map(|q| q.point.clone()).collect();
+            match metrics_store().write(points).await {
+                Ok(_) => {
+                    POINTS_WRITTEN.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    LAST_SUCCESS.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+                    backoff = 1;
+                }
+                Err(e) => {
+                    println!("Unable to store statistics (retrying in {backoff}s): {e:?}");
+                    WRITE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                    *LAST_ERROR.lock() = Some(e.to_string());
+                    {
+                        let mut queue = QUEUE.lock();
+                        for queued in batch.into_iter().rev() {
+                            queue.push_front(queued);
+                        }
+                        trim_queue(&mut queue);
+                    }
+                    rocket::tokio::time::sleep(Duration::from_secs(backoff)).await;
+                    backoff = u64::min(backoff * 2, MAX_BACKOFF_SECS);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The state of the background writer, for the health endpoint.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WriterHealth {
+    /// Points waiting to be written.
+    pub queued_points: usize,
+    /// How long (seconds) the oldest queued point has been waiting.
+    pub write_lag_seconds: i64,
+    pub points_written: u64,
+    /// Points discarded because the queue was full.
+    pub points_dropped: u64,
+    pub write_errors: u64,
+    pub last_error: Option<String>,
+    /// When a batch was last written (Unix seconds), or 0 if never.
+    pub last_success: i64,
+}
+
+pub fn writer_health() -> WriterHealth {
+    let (queued_points, oldest) = {
+        let queue = QUEUE.lock();
+        (queue.len(), queue.front().map(|q| q.queued_at))
+    };
+    let write_lag_seconds = oldest
+        .map(|queued_at| chrono::Utc::now().timestamp() - queued_at)
+        .unwrap_or(0);
+    WriterHealth {
+        queued_points,
+        write_lag_seconds,
+        points_written: POINTS_WRITTEN.load(Ordering::Relaxed),
+        points_dropped: POINTS_DROPPED.load(Ordering::Relaxed),
+        write_errors: WRITE_ERRORS.load(Ordering::Relaxed),
+        last_error: LAST_ERROR.lock().clone(),
+        last_success: LAST_SUCCESS.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,27 @@
+use lazy_static::*;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+lazy_static! {
+    /// When each client queue last passed traffic, answered a probe or
+    /// produced a latency sample (Unix seconds, from the report time).
+    static ref LAST_ACTIVITY: RwLock<HashMap<String, i64>> = RwLock::new(HashMap::new());
+}
+
+/// Records that a client queue was active at `time` (nanoseconds, as
+/// returned by `report_time`). Spooled reports that arrive late don't move
+/// the time backwards.
+pub fn record_activity(site_id: &str, time: i64) {
+    let seconds = time / 1_000_000_000;
+    let mut lock = LAST_ACTIVITY.write();
+    if let Some(last) = lock.get_mut(site_id) {
+        *last = (*last).max(seconds);
+    } else {
+        lock.insert(site_id.to_string(), seconds);
+    }
+}
+
+/// When each client queue was last active (Unix seconds).
+pub(crate) fn last_activity() -> HashMap<String, i64> {
+    LAST_ACTIVITY.read().clone()
+}
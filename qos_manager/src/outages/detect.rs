@@ -0,0 +1,316 @@
+use super::last_activity;
+use crate::bus::{
+    get_queue_tree, get_shaper_tree, get_subtree_clients, get_tree_ancestors, shaper_status,
+};
+use crate::config::configuration;
+use crate::queries::DEVICES;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::serde::{Deserialize, Serialize};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use shared_rest::{NodeKind, QueueTreeEntry};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+const OUTAGES_FILENAME: &str = "outages.ron";
+
+/// Closed outages older than this are forgotten.
+const OUTAGE_RETENTION_DAYS: i64 = 30;
+
+/// Why a node was judged to be down.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub enum OutageCause {
+    /// UISP reports all of the node's devices as disconnected.
+    UispDisconnected,
+    /// Too many of the clients beneath the node stopped passing traffic.
+    ClientsSilent,
+}
+
+/// A client affected by an outage.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AffectedClient {
+    pub id: String,
+    pub name: String,
+}
+
+/// An outage of a node in the queue tree.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Outage {
+    pub id: u64,
+    pub node_id: String,
+    pub node_name: String,
+    pub level_type: NodeKind,
+    pub cause: OutageCause,
+    /// When the outage was detected (RFC 3339).
+    pub started: String,
+    /// When the node recovered (RFC 3339); `None` while the outage continues.
+    pub ended: Option<String>,
+    /// The clients beneath the node when the outage was detected.
+    pub total_clients: usize,
+    /// Every client that was out at some point during the outage.
+    pub affected_clients: Vec<AffectedClient>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "rocket::serde")]
+struct OutageLog {
+    next_id: u64,
+    outages: Vec<Outage>,
+}
+
+lazy_static! {
+    static ref OUTAGES: RwLock<OutageLog> = RwLock::new(OutageLog::default());
+}
+
+/// All remembered outages, ongoing ones first and then newest first.
+pub fn get_outages() -> Vec<Outage> {
+    let mut outages = OUTAGES.read().outages.clone();
+    outages.sort_by(|a, b| {
+        a.ended
+            .is_some()
+            .cmp(&b.ended.is_some())
+            .then_with(|| b.started.cmp(&a.started))
+    });
+    outages
+}
+
+/// The number of outages still in progress.
+pub fn open_outage_count() -> usize {
+    OUTAGES
+        .read()
+        .outages
+        .iter()
+        .filter(|o| o.ended.is_none())
+        .count()
+}
+
+/// Loads `outages.ron`, if there is one.
+pub fn load_outages() -> Result<()> {
+    let data = std::fs::read_to_string(OUTAGES_FILENAME)?;
+    let log: OutageLog = ron::from_str(&data)?;
+    *OUTAGES.write() = log;
+    Ok(())
+}
+
+fn save_outages(log: &OutageLog) -> Result<()> {
+    let ron = to_string_pretty(log, PrettyConfig::new())?;
+    std::fs::write(OUTAGES_FILENAME, ron)?;
+    Ok(())
+}
+
+/// Checks for outages every `outage_check_secs`, forever.
+pub async fn outage_detector() {
+    loop {
+        let config = configuration();
+        rocket::tokio::time::sleep(Duration::from_secs(config.outage_check_secs)).await;
+        let scan = find_down_nodes(
+            Local::now().timestamp() - config.outage_silence_secs as i64,
+            config.outage_client_share,
+            config.outage_min_clients,
+            config.feed_stale_secs,
+        );
+        update_outages(scan);
+    }
+}
+
+/// A node judged to be down, and the clients it affects.
+struct DownNode {
+    node: QueueTreeEntry,
+    cause: OutageCause,
+    total_clients: usize,
+    affected: Vec<AffectedClient>,
+}
+
+/// The result of one check for outages.
+struct OutageScan {
+    down: Vec<DownNode>,
+    /// Nodes whose clients can't be judged, so an outage of silent clients
+    /// stays open.
+    unknown: HashSet<String>,
+}
+
+/// The nodes in the trees of shapers whose scheduled feeds are stale. Their
+/// clients going quiet says nothing about the clients themselves.
+fn stale_shaper_nodes(stale_secs: u64) -> HashSet<String> {
+    shaper_status(stale_secs)
+        .into_iter()
+        .filter(|s| s.stale)
+        .filter_map(|s| get_shaper_tree(&s.shaper_id))
+        .flatten()
+        .map(|n| n.id)
+        .collect()
+}
+
+/// Finds the access points, sites and other parent nodes that are down.
+/// Clients that haven't been active since the manager started aren't
+/// counted either way, and nodes with too few such clients (or behind a
+/// shaper that has stopped reporting) are left undecided.
+fn find_down_nodes(
+    silent_before: i64,
+    client_share: f64,
+    min_clients: usize,
+    stale_secs: u64,
+) -> OutageScan {
+    let activity = last_activity();
+    let stale = stale_shaper_nodes(stale_secs);
+    let disconnected: HashSet<String> = DEVICES
+        .read()
+        .iter()
+        .filter(|d| {
+            d.overview
+                .as_ref()
+                .and_then(|o| o.status.as_ref())
+                .map(|s| s == "disconnected")
+                .unwrap_or(false)
+        })
+        .map(|d| d.get_id())
+        .collect();
+
+    let mut down = Vec::new();
+    let mut unknown = HashSet::new();
+    for node in get_queue_tree()
+        .into_iter()
+        .filter(|n| !n.level_type.is_client_queue() && n.level_type != NodeKind::Unmapped)
+    {
+        let clients = get_subtree_clients(&node.id).unwrap_or_default();
+        if clients.is_empty() {
+            continue;
+        }
+        let to_affected = |c: &QueueTreeEntry| AffectedClient {
+            id: c.id.clone(),
+            name: c.name.clone(),
+        };
+
+        let devices = &node.metadata.uisp_device_ids;
+        if !devices.is_empty() && devices.iter().all(|d| disconnected.contains(d)) {
+            down.push(DownNode {
+                total_clients: clients.len(),
+                affected: clients.iter().map(to_affected).collect(),
+                node,
+                cause: OutageCause::UispDisconnected,
+            });
+            continue;
+        }
+
+        if stale.contains(&node.id) {
+            unknown.insert(node.id);
+            continue;
+        }
+        let known: Vec<&QueueTreeEntry> = clients
+            .iter()
+            .filter(|c| activity.contains_key(&c.id))
+            .collect();
+        let silent: Vec<AffectedClient> = known
+            .iter()
+            .filter(|c| activity[&c.id] < silent_before)
+            .map(|c| to_affected(c))
+            .collect();
+        if known.len() < min_clients && clients.len() >= min_clients {
+            unknown.insert(node.id);
+            continue;
+        }
+        if known.len() >= min_clients
+            && !silent.is_empty()
+            && silent.len() as f64 >= known.len() as f64 * client_share
+        {
+            down.push(DownNode {
+                total_clients: clients.len(),
+                affected: silent,
+                node,
+                cause: OutageCause::ClientsSilent,
+            });
+        }
+    }
+    OutageScan { down, unknown }
+}
+
+/// Opens an outage for each newly down node whose parents are up (so that a
+/// site failure isn't also reported once per access point), and closes the
+/// outages of nodes that have recovered. Silent client outages of nodes that
+/// couldn't be judged are left open.
+fn update_outages(scan: OutageScan) {
+    let OutageScan { down, unknown } = scan;
+    let now = Local::now();
+    let down_ids: HashSet<String> = down.iter().map(|d| d.node.id.clone()).collect();
+    let mut log = OUTAGES.write();
+    let mut changed = false;
+
+    let mut open: HashMap<String, usize> = HashMap::new();
+    for (i, outage) in log.outages.iter_mut().enumerate() {
+        if outage.ended.is_some() {
+            continue;
+        }
+        if down_ids.contains(&outage.node_id) {
+            open.insert(outage.node_id.clone(), i);
+        } else if outage.cause != OutageCause::ClientsSilent || !unknown.contains(&outage.node_id) {
+            outage.ended = Some(now.to_rfc3339());
+            changed = true;
+        }
+    }
+
+    for node in down.into_iter() {
+        if let Some(i) = open.get(&node.node.id) {
+            // Still down; note any more clients that have gone with it.
+            let outage = &mut log.outages[*i];
+            let mut affected: BTreeMap<String, AffectedClient> = outage
+                .affected_clients
+                .drain(..)
+                .map(|c| (c.id.clone(), c))
+                .collect();
+            for client in node.affected.into_iter() {
+                if !affected.contains_key(&client.id) {
+                    changed = true;
+                    affected.insert(client.id.clone(), client);
+                }
+            }
+            outage.affected_clients = affected.into_values().collect();
+            continue;
+        }
+        let parent_down = get_tree_ancestors(&node.node.id)
+            .unwrap_or_default()
+            .iter()
+            .skip(1)
+            .any(|a| down_ids.contains(&a.id));
+        if parent_down {
+            continue;
+        }
+        let id = log.next_id;
+        log.next_id += 1;
+        println!("Outage detected: {} ({:?})", node.node.name, node.cause);
+        log.outages.push(Outage {
+            id,
+            node_id: node.node.id,
+            node_name: node.node.name,
+            level_type: node.node.level_type,
+            cause: node.cause,
+            started: now.to_rfc3339(),
+            ended: None,
+            total_clients: node.total_clients,
+            affected_clients: node.affected,
+        });
+        changed = true;
+    }
+
+    let forget_before = now - chrono::Duration::days(OUTAGE_RETENTION_DAYS);
+    let before = log.outages.len();
+    log.outages.retain(|o| match &o.ended {
+        Some(ended) => DateTime::parse_from_rfc3339(ended)
+            .map(|t| t > forget_before)
+            .unwrap_or(false),
+        None => true,
+    });
+    changed |= log.outages.len() != before;
+
+    if changed {
+        if let Err(e) = save_outages(&log) {
+            println!("Unable to save {OUTAGES_FILENAME}: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! Detects outages: access points, sites or whole branches of the tree whose
+//! clients stop passing traffic, or whose UISP devices are disconnected.
+//! Each outage is kept as an event with start and end times and the clients
+//! it affected, in `outages.ron`.
+
+mod activity;
+pub use activity::*;
+mod detect;
+pub use detect::*;
@@ -0,0 +1,167 @@
+//! Prometheus metrics for the manager itself, served at `/metrics`.
+
+use crate::metrics::writer_health;
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::http::ContentType;
+use shared_rest::{MetricType, PrometheusText};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+#[derive(Default)]
+struct BusCounters {
+    messages: u64,
+    bytes: u64,
+    rejected: u64,
+}
+
+lazy_static! {
+    /// Bus traffic, by route.
+    static ref BUS: RwLock<BTreeMap<String, BusCounters>> = RwLock::new(BTreeMap::new());
+}
+
+static UISP_REFRESHES: AtomicU64 = AtomicU64::new(0);
+static LAST_UISP_REFRESH_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts an accepted bus message, of `bytes` bytes, sent to `path`.
+pub fn record_bus_message(path: &str, bytes: usize) {
+    let mut lock = BUS.write();
+    let counters = lock.entry(path.to_string()).or_default();
+    counters.messages += 1;
+    counters.bytes += bytes as u64;
+}
+
+/// Counts a bus message to `path` that was rejected (bad signature, protocol
+/// version or body).
+pub fn record_bus_rejection(path: &str) {
+    BUS.write().entry(path.to_string()).or_default().rejected += 1;
+}
+
+/// Records a completed UISP refresh.
+pub fn record_uisp_refresh(duration: Duration) {
+    UISP_REFRESHES.fetch_add(1, Ordering::Relaxed);
+    LAST_UISP_REFRESH_MILLIS.store(duration.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Unauthenticated, so that Prometheus can scrape it.
+#[get("/metrics")]
+pub async fn prometheus_metrics() -> (ContentType, String) {
+    let mut page = PrometheusText::new();
+
+    {
+        let bus = BUS.read();
+        let families = [
+            (
+                "bracket_qos_bus_messages_total",
+                "Bus messages accepted from shapers, by route.",
+            ),
+            (
+                "bracket_qos_bus_bytes_total",
+                "Bytes of bus messages accepted from shapers, by route.",
+            ),
+            (
+                "bracket_qos_bus_rejected_total",
+                "Bus messages rejected, by route.",
+            ),
+        ];
+        for (i, (name, help)) in families.iter().enumerate() {
+            page.family(name, help, MetricType::Counter);
+            for (path, counters) in bus.iter() {
+                let value = match i {
+                    0 => counters.messages,
+                    1 => counters.bytes,
+                    _ => counters.rejected,
+                };
+                page.sample(name, &[("route", path)], value as f64);
+            }
+        }
+    }
+
+    page.single(
+        "bracket_qos_uisp_refreshes_total",
+        "Completed UISP refreshes.",
+        MetricType::Counter,
+        UISP_REFRESHES.load(Ordering::Relaxed) as f64,
+    )
+    .single(
+        "bracket_qos_last_uisp_refresh_duration_seconds",
+        "How long the most recent UISP refresh took.",
+        MetricType::Gauge,
+        LAST_UISP_REFRESH_MILLIS.load(Ordering::Relaxed) as f64 / 1000.0,
+    );
+
+    let reachability = crate::bus::current_reachability();
+    page.single(
+        "bracket_qos_clients_probed",
+        "Client queues with recent reachability probes.",
+        MetricType::Gauge,
+        reachability.len() as f64,
+    )
+    .single(
+        "bracket_qos_clients_down",
+        "Client queues that didn't answer their most recent probes.",
+        MetricType::Gauge,
+        reachability.values().filter(|state| !state.up).count() as f64,
+    )
+    .single(
+        "bracket_qos_open_outages",
+        "Outages that are still in progress.",
+        MetricType::Gauge,
+        crate::outages::open_outage_count() as f64,
+    )
+    .single(
+        "bracket_qos_active_alerts",
+        "Alerts that are currently raised.",
+        MetricType::Gauge,
+        crate::alerts::active_alerts().len() as f64,
+    )
+    .single(
+        "bracket_qos_stale_shapers",
+        "Shapers with a bandwidth, latency or host feed that has gone stale.",
+        MetricType::Gauge,
+        crate::bus::shaper_status(crate::config::configuration().feed_stale_secs)
+            .iter()
+            .filter(|s| s.stale)
+            .count() as f64,
+    );
+
+    let writer = writer_health();
+    page.single(
+        "bracket_qos_metrics_queued_points",
+        "Statistics waiting to be written.",
+        MetricType::Gauge,
+        writer.queued_points as f64,
+    )
+    .single(
+        "bracket_qos_metrics_write_lag_seconds",
+        "How long the oldest queued statistic has been waiting.",
+        MetricType::Gauge,
+        writer.write_lag_seconds as f64,
+    )
+    .single(
+        "bracket_qos_metrics_points_written_total",
+        "Statistics written to the metrics store.",
+        MetricType::Counter,
+        writer.points_written as f64,
+    )
+    .single(
+        "bracket_qos_metrics_points_dropped_total",
+        "Statistics discarded because the write queue was full.",
+        MetricType::Counter,
+        writer.points_dropped as f64,
+    )
+    .single(
+        "bracket_qos_metrics_write_errors_total",
+        "Failed writes to the metrics store.",
+        MetricType::Counter,
+        writer.write_errors as f64,
+    );
+
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        page.finish(),
+    )
+}
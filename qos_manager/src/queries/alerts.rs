@@ -0,0 +1,28 @@
+use crate::alerts::{active_alerts, alert_configuration, send_all, AlertNotification, AlertState};
+use crate::auth::{AdminUser, ReadOnlyUser};
+use rocket::serde::json::Json;
+
+/// Alerts that are currently raised.
+#[get("/query/alerts")]
+pub async fn alert_list(_user: ReadOnlyUser) -> Json<Vec<AlertState>> {
+    Json(active_alerts())
+}
+
+/// Sends a test alert to every target, returning any delivery errors.
+#[post("/query/alerts/test")]
+pub async fn test_alert(_user: AdminUser) -> Json<Vec<String>> {
+    let config = alert_configuration();
+    if config.targets.is_empty() {
+        return Json(vec!["No alert targets are configured.".to_string()]);
+    }
+    let test = AlertNotification {
+        rule: "Test".to_string(),
+        state: "test".to_string(),
+        id: "test".to_string(),
+        name: "BracketQOS".to_string(),
+        value: 0.0,
+        trigger: 0.0,
+        time: chrono::Local::now().to_rfc3339(),
+    };
+    Json(send_all(&config, &[test]).await)
+}
@@ -0,0 +1,9 @@
+use crate::auth::AdminUser;
+use crate::store::{audit_log, AuditEntry};
+use rocket::serde::json::Json;
+
+/// Changes made through the manager, newest first (the last 200 by default).
+#[get("/query/audit?<limit>")]
+pub async fn audit_entries(_user: AdminUser, limit: Option<usize>) -> Json<Vec<AuditEntry>> {
+    Json(audit_log(limit.unwrap_or(200)).unwrap_or_default())
+}
@@ -0,0 +1,107 @@
+use crate::auth::ReadOnlyUser;
+use crate::influx::*;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local};
+use influxdb2_structmap::FromMap;
+use rocket::serde::{json::Json, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(influxdb2_structmap_derive::FromMap)]
+pub struct CakeTinSample {
+    direction: String,
+    field: String,
+    value: f64,
+    time: DateTime<FixedOffset>,
+}
+
+impl Default for CakeTinSample {
+    fn default() -> Self {
+        Self {
+            direction: String::new(),
+            field: String::new(),
+            value: 0.0,
+            time: chrono::MIN_DATETIME.with_timezone(&chrono::FixedOffset::east(7 * 3600)),
+        }
+    }
+}
+
+/// CAKE statistics for one direction of a queue, combined across tins:
+/// delays are the worst tin's, drops and marks are totals.
+#[derive(Serialize, Default, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct CakeDirection {
+    pub avg_delay_ms: f64,
+    pub peak_delay_ms: f64,
+    pub drops: f64,
+    pub ecn_marks: f64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CakeStatsRest {
+    pub time: String,
+    pub down: CakeDirection,
+    pub up: CakeDirection,
+}
+
+pub async fn site_cake_query(
+    id: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
+) -> Result<Vec<CakeStatsRest>> {
+    let points = InfluxQuery::new()
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
+        .with_measurement("cake_tins")
+        .with_filter(QueryFilter::AnyOf {
+            field: "_field".to_string(),
+            values: vec![
+                "avg_delay_us".to_string(),
+                "peak_delay_us".to_string(),
+                "drops".to_string(),
+                "ecn_marks".to_string(),
+            ],
+        })
+        .with_filter(QueryFilter::tag("site", id))
+        .run::<CakeTinSample>()
+        .await?;
+
+    let mut by_time: BTreeMap<DateTime<FixedOffset>, (CakeDirection, CakeDirection)> =
+        BTreeMap::new();
+    for point in points.0.iter() {
+        let entry = by_time.entry(point.time).or_default();
+        let direction = if point.direction == "up" {
+            &mut entry.1
+        } else {
+            &mut entry.0
+        };
+        match point.field.as_str() {
+            "avg_delay_us" => {
+                direction.avg_delay_ms = f64::max(direction.avg_delay_ms, point.value / 1000.0)
+            }
+            "peak_delay_us" => {
+                direction.peak_delay_ms = f64::max(direction.peak_delay_ms, point.value / 1000.0)
+            }
+            "drops" => direction.drops += point.value,
+            _ => direction.ecn_marks += point.value,
+        }
+    }
+    Ok(by_time
+        .into_iter()
+        .map(|(time, (down, up))| CakeStatsRest {
+            time: chrono::DateTime::<Local>::from(time).to_rfc3339(),
+            down,
+            up,
+        })
+        .collect())
+}
+
+#[get("/query/site_cake/<id>/<range>/<aggregate>")]
+pub async fn site_cake(
+    _user: ReadOnlyUser,
+    id: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
+) -> Json<Vec<CakeStatsRest>> {
+    Json(site_cake_query(&id, &range, &aggregate).await.unwrap())
+}
@@ -0,0 +1,74 @@
+use crate::auth::ReadOnlyUser;
+use crate::influx::*;
+use crate::queries::InternetBandwidth;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local};
+use rocket::serde::{json::Json, Serialize};
+use std::collections::BTreeMap;
+
+/// HTB class counters for a tower or access point, per aggregation window.
+#[derive(Serialize, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct ClassStatsRest {
+    pub time: String,
+    pub down_overlimits: f64,
+    pub up_overlimits: f64,
+    pub down_borrowed: f64,
+    pub up_borrowed: f64,
+}
+
+pub async fn site_class_stats_query(
+    id: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
+) -> Result<Vec<ClassStatsRest>> {
+    let points = InfluxQuery::new()
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
+        .with_measurement("queues")
+        .with_filter(QueryFilter::AnyOf {
+            field: "_field".to_string(),
+            values: vec![
+                "down_overlimits".to_string(),
+                "up_overlimits".to_string(),
+                "down_borrowed".to_string(),
+                "up_borrowed".to_string(),
+            ],
+        })
+        .with_filter(QueryFilter::tag("site", id))
+        .run::<InternetBandwidth>()
+        .await?;
+
+    // A site reported by more than one shaper has a series per shaper.
+    let mut by_time: BTreeMap<DateTime<FixedOffset>, ClassStatsRest> = BTreeMap::new();
+    for point in points.0.iter() {
+        let entry = by_time.entry(point.time).or_default();
+        match point.field.as_str() {
+            "down_overlimits" => entry.down_overlimits += point.value,
+            "up_overlimits" => entry.up_overlimits += point.value,
+            "down_borrowed" => entry.down_borrowed += point.value,
+            _ => entry.up_borrowed += point.value,
+        }
+    }
+    Ok(by_time
+        .into_iter()
+        .map(|(time, mut stats)| {
+            stats.time = chrono::DateTime::<Local>::from(time).to_rfc3339();
+            stats
+        })
+        .collect())
+}
+
+#[get("/query/site_class_stats/<id>/<range>/<aggregate>")]
+pub async fn site_class_stats(
+    _user: ReadOnlyUser,
+    id: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
+) -> Json<Vec<ClassStatsRest>> {
+    Json(
+        site_class_stats_query(&id, &range, &aggregate)
+            .await
+            .unwrap(),
+    )
+}
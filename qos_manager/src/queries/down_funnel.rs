@@ -1,5 +1,7 @@
 use super::{site_bandwidth_query, InternetBandwidthRest};
+use crate::auth::ReadOnlyUser;
 use crate::bus::get_tree_children;
+use crate::influx::{FluxDuration, FluxRange};
 use rocket::{
     futures::future::join_all,
     serde::{json::Json, Serialize},
@@ -13,12 +15,13 @@ pub struct InternetBandwidthFunnel {
 
 #[get("/query/site_funnel/<id>/<range>/<aggregate>")]
 pub async fn site_funnel(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
-) -> Json<InternetBandwidthFunnel> {
+    range: FluxRange,
+    aggregate: FluxDuration,
+) -> Option<Json<InternetBandwidthFunnel>> {
     let mut result = InternetBandwidthFunnel { sites: Vec::new() };
-    for child in get_tree_children(&id).iter() {
+    for child in get_tree_children(&id)?.iter() {
         result
             .sites
             .push(site_funnel_data(child.id.clone(), &range, &aggregate, child.name.clone()).await);
@@ -30,17 +33,18 @@ pub async fn site_funnel(
         a_max.partial_cmp(&b_max).unwrap()
     });
 
-    Json(result)
+    Some(Json(result))
 }
 
 #[get("/query/site_funnel_sites/<id>/<range>/<aggregate>")]
 pub async fn site_funnel_sites(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
-) -> Json<InternetBandwidthFunnel> {
+    range: FluxRange,
+    aggregate: FluxDuration,
+) -> Option<Json<InternetBandwidthFunnel>> {
     let mut tasks = Vec::new();
-    let mut children: Vec<(String, String)> = get_tree_children(&id)
+    let mut children: Vec<(String, String)> = get_tree_children(&id)?
         .iter()
         .map(|child| (child.id.clone(), child.name.clone()))
         .collect();
@@ -83,13 +87,13 @@ pub async fn site_funnel_sites(
         result.sites.push(("Others".to_string(), others));
     }
 
-    Json(result)
+    Some(Json(result))
 }
 
 async fn site_funnel_data(
     id: String,
-    range: &str,
-    aggregate: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
     name: String,
 ) -> (String, Vec<InternetBandwidthRest>) {
     (
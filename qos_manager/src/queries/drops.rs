@@ -1,16 +1,17 @@
+use crate::auth::ReadOnlyUser;
 use crate::influx::*;
-use crate::queries::{InternetBandwidth, InternetBandwidthRest};
+use crate::queries::{combine_shapers, InternetBandwidth, InternetBandwidthRest};
 use anyhow::Result;
 use rocket::serde::json::Json;
 
 pub async fn site_drops_query(
     id: &str,
-    range: &str,
-    aggregate: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
 ) -> Result<Vec<InternetBandwidthRest>> {
-    let rest = InfluxQuery::new()
-        .with_range_from_url(range)
-        .with_aggregate_from_url(aggregate, AggregateFunction::Max)
+    let points = InfluxQuery::new()
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
         .with_measurement("queues")
         .with_filter(QueryFilter::Either {
             field: "_field".to_string(),
@@ -22,21 +23,17 @@ pub async fn site_drops_query(
             value: id.to_string(),
         })
         .run::<InternetBandwidth>()
-        .await?
-        .sort()
-        .0
-        .chunks(2)
-        .map(|n| InternetBandwidthRest::from(n))
-        .collect();
+        .await?;
 
-    Ok(rest)
+    Ok(combine_shapers(&points.0))
 }
 
 #[get("/query/site_drops/<id>/<range>/<aggregate>")]
 pub async fn site_drops(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
 ) -> Json<Vec<InternetBandwidthRest>> {
     Json(site_drops_query(&id, &range, &aggregate).await.unwrap())
 }
@@ -1,4 +1,5 @@
 use super::LatencySummaryRest;
+use crate::auth::ReadOnlyUser;
 use crate::influx::*;
 use anyhow::Result;
 use chrono::{DateTime, FixedOffset, Local};
@@ -22,12 +23,12 @@ impl Default for FrequencySummary {
 
 pub async fn ap_frequency_query(
     id: &str,
-    range: &str,
-    aggregate: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
 ) -> Result<Vec<LatencySummaryRest>> {
     let rest = InfluxQuery::new()
-        .with_range_from_url(range)
-        .with_aggregate_from_url(aggregate, AggregateFunction::Max)
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
         .with_measurement("frequency")
         .with_filter(QueryFilter::MatchOne {
             field: "access_point".to_string(),
@@ -49,12 +50,12 @@ pub async fn ap_frequency_query(
 
 pub async fn ap_noise_query(
     id: &str,
-    range: &str,
-    aggregate: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
 ) -> Result<Vec<LatencySummaryRest>> {
     let rest = InfluxQuery::new()
-        .with_range_from_url(range)
-        .with_aggregate_from_url(aggregate, AggregateFunction::Max)
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
         .with_measurement("noise_floor")
         .with_filter(QueryFilter::MatchOne {
             field: "access_point".to_string(),
@@ -77,12 +78,12 @@ pub async fn ap_noise_query(
 
 pub async fn signal_query(
     id: &str,
-    range: &str,
-    aggregate: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
 ) -> Result<Vec<LatencySummaryRest>> {
     let rest = InfluxQuery::new()
-        .with_range_from_url(range)
-        .with_aggregate_from_url(aggregate, AggregateFunction::Max)
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
         .with_measurement("signal")
         .with_filter(QueryFilter::MatchOne {
             field: "access_point".to_string(),
@@ -105,23 +106,30 @@ pub async fn signal_query(
 
 #[get("/query/ap_frequency/<id>/<range>/<aggregate>")]
 pub async fn ap_frequency(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
 ) -> Json<Vec<LatencySummaryRest>> {
     Json(ap_frequency_query(&id, &range, &aggregate).await.unwrap())
 }
 
 #[get("/query/ap_noise/<id>/<range>/<aggregate>")]
 pub async fn ap_noise(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
 ) -> Json<Vec<LatencySummaryRest>> {
     Json(ap_noise_query(&id, &range, &aggregate).await.unwrap())
 }
 
 #[get("/query/signal/<id>/<range>/<aggregate>")]
-pub async fn signal(id: String, range: String, aggregate: String) -> Json<Vec<LatencySummaryRest>> {
+pub async fn signal(
+    _user: ReadOnlyUser,
+    id: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
+) -> Json<Vec<LatencySummaryRest>> {
     Json(signal_query(&id, &range, &aggregate).await.unwrap())
 }
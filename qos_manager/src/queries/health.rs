@@ -0,0 +1,16 @@
+use crate::metrics::{writer_health, WriterHealth};
+use rocket::serde::{json::Json, Serialize};
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Health {
+    metrics_writer: WriterHealth,
+}
+
+/// Unauthenticated, so that monitoring systems can poll it.
+#[get("/health")]
+pub async fn health_status() -> Json<Health> {
+    Json(Health {
+        metrics_writer: writer_health(),
+    })
+}
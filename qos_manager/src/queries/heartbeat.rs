@@ -0,0 +1,11 @@
+use crate::auth::ReadOnlyUser;
+use crate::bus::{shaper_status, ShaperStatus};
+use crate::config::configuration;
+use rocket::serde::json::Json;
+
+/// The health of each shaper's `qos_daemon`: version, uptime, last rebuild
+/// and when each bus feed was last received.
+#[get("/query/shapers/status")]
+pub async fn shaper_status_list(_user: ReadOnlyUser) -> Json<Vec<ShaperStatus>> {
+    Json(shaper_status(configuration().feed_stale_secs))
+}
@@ -1,7 +1,9 @@
+use crate::auth::ReadOnlyUser;
 use crate::influx::*;
 use chrono::{DateTime, FixedOffset, Local};
 use influxdb2_structmap::FromMap;
 use rocket::serde::{json::Json, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(influxdb2_structmap_derive::FromMap, Debug)]
 pub struct InternetBandwidth {
@@ -42,24 +44,37 @@ pub struct InternetBandwidthRest {
     pub time: String,
 }
 
-impl From<&[InternetBandwidth]> for InternetBandwidthRest {
-    fn from(n: &[InternetBandwidth]) -> Self {
-        Self {
-            time: chrono::DateTime::<Local>::from(n[0].time).to_rfc3339(),
-            up: n[1].value,
-            down: n[0].value,
+/// Pairs download and upload readings by time. A site that is reported by
+/// more than one shaper (such as `root`) has a series per shaper; these are
+/// added together.
+pub fn combine_shapers(points: &[InternetBandwidth]) -> Vec<InternetBandwidthRest> {
+    let mut by_time: BTreeMap<DateTime<FixedOffset>, (f64, f64)> = BTreeMap::new();
+    for point in points.iter() {
+        let entry = by_time.entry(point.time).or_insert((0.0, 0.0));
+        if point.field.starts_with("down") {
+            entry.0 += point.value;
+        } else {
+            entry.1 += point.value;
         }
     }
+    by_time
+        .iter()
+        .map(|(time, (down, up))| InternetBandwidthRest {
+            time: chrono::DateTime::<Local>::from(*time).to_rfc3339(),
+            up: *up,
+            down: *down,
+        })
+        .collect()
 }
 
 pub async fn site_bandwidth_query(
     id: &str,
-    range: &str,
-    aggregate: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
 ) -> anyhow::Result<Vec<InternetBandwidthRest>> {
-    let rest = InfluxQuery::new()
-        .with_range_from_url(range)
-        .with_aggregate_from_url(aggregate, AggregateFunction::Max)
+    let points = InfluxQuery::new()
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Max)
         .with_measurement("queues")
         .with_filter(QueryFilter::Either {
             field: "_field".to_string(),
@@ -71,54 +86,42 @@ pub async fn site_bandwidth_query(
             value: id.to_string(),
         })
         .run::<InternetBandwidth>()
-        .await?
-        .sort()
-        .0
-        .chunks(2)
-        .map(|n| InternetBandwidthRest::from(n))
-        .collect();
-    Ok(rest)
+        .await?;
+    Ok(combine_shapers(&points.0))
 }
 
 #[get("/query/site_bandwidth/<id>/<range>/<aggregate>")]
 pub async fn site_bandwidth(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
 ) -> Json<Vec<InternetBandwidthRest>> {
     Json(site_bandwidth_query(&id, &range, &aggregate).await.unwrap())
 }
 
 #[get("/query/peak_bandwidth/<id>")]
-pub async fn peak_bandwidth(id: String) -> Json<InternetBandwidthRest> {
-    use influxdb2::{models::Query, Client};
-    let qs = format!(
-        "from(bucket: \"bracketqos\")
-    |> range(start: -7d)
-    |> filter(fn: (r) => r[\"_measurement\"] == \"queues\")
-    |> filter(fn: (r) => r[\"_field\"] == \"down_mbps\" or r[\"_field\"] == \"up_mbps\")
-    |> filter(fn: (r) => r[\"site\"] == \"{id}\")
-    |> aggregateWindow(every: 14d, fn: max, createEmpty: false)
-    |> yield(name: \"max\")
-    "
-    );
-    let query = Query::new(qs.to_string());
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let mut result: Vec<InternetBandwidth> = client
-        .query::<InternetBandwidth>(Some(query))
-        .await
-        .unwrap();
-    //println!("{:?}", result);
-    result.sort_by(|a, b| b.time.cmp(&a.time));
-    let rest = result
-        .chunks(2)
-        .map(|n| InternetBandwidthRest {
-            time: chrono::DateTime::<Local>::from(n[0].time).to_rfc3339(),
-            up: n[1].value,
-            down: n[0].value,
+pub async fn peak_bandwidth(_user: ReadOnlyUser, id: String) -> Json<InternetBandwidthRest> {
+    let result = InfluxQuery::new()
+        .with_range(&FluxRange::last(FluxDuration::days(7)))
+        .with_aggregate(&FluxDuration::days(14), AggregateFunction::Max)
+        .with_measurement("queues")
+        .with_filter(QueryFilter::Either {
+            field: "_field".to_string(),
+            value_1: "down_mbps".to_string(),
+            value_2: "up_mbps".to_string(),
         })
-        .next()
+        .with_filter(QueryFilter::tag("site", id))
+        .run::<InternetBandwidth>()
+        .await
         .unwrap();
+    // A new shaper or site has no data yet.
+    let rest = combine_shapers(&result.0)
+        .pop()
+        .unwrap_or_else(|| InternetBandwidthRest {
+            time: Local::now().to_rfc3339(),
+            up: 0.0,
+            down: 0.0,
+        });
     Json(rest)
 }
@@ -1,12 +1,13 @@
+use crate::auth::ReadOnlyUser;
 use crate::bus::{duplicate_ip_list, unmapped_client_list};
 use rocket::serde::json::Json;
 
 #[get("/query/ip_dupe")]
-pub async fn duplicate_ip() -> Json<Vec<String>> {
+pub async fn duplicate_ip(_user: ReadOnlyUser) -> Json<Vec<String>> {
     Json(duplicate_ip_list())
 }
 
 #[get("/query/unmapped")]
-pub async fn unmapped() -> Json<Vec<String>> {
+pub async fn unmapped(_user: ReadOnlyUser) -> Json<Vec<String>> {
     Json(unmapped_client_list())
 }
@@ -1,3 +1,4 @@
+use crate::auth::ReadOnlyUser;
 use crate::influx::*;
 use chrono::{DateTime, FixedOffset};
 use influxdb2_structmap::FromMap;
@@ -24,16 +25,14 @@ pub struct CpuLoadRest {
     usage: f64,
 }
 
-#[get("/query/cpu_load")]
-pub async fn last_cpu_average() -> Json<Vec<CpuLoadRest>> {
+#[get("/query/cpu_load/<shaper>")]
+pub async fn last_cpu_average(_user: ReadOnlyUser, shaper: String) -> Json<Vec<CpuLoadRest>> {
     let result = InfluxQuery::new()
-        .with_range_string("start: -5m")
-        .with_aggregate("5m", AggregateFunction::Last)
+        .with_range(&FluxRange::last(FluxDuration::minutes(5)))
+        .with_aggregate(&FluxDuration::minutes(5), AggregateFunction::Last)
         .with_measurement("cpu")
-        .with_filter(QueryFilter::MatchOne {
-            field: "_field".to_string(),
-            value: "usage".to_string(),
-        })
+        .with_filter(QueryFilter::field("usage"))
+        .with_filter(QueryFilter::tag("host", shaper))
         .with_group("cpu")
         .with_last()
         .run::<CpuLoad>()
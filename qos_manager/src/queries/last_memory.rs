@@ -1,3 +1,4 @@
+use crate::auth::ReadOnlyUser;
 use crate::influx::*;
 use chrono::{DateTime, FixedOffset};
 use influxdb2_structmap::FromMap;
@@ -24,12 +25,13 @@ pub struct CpuLoadRest {
     usage: f64,
 }
 
-#[get("/query/ram_use")]
-pub async fn last_ram_use() -> Json<Vec<CpuLoadRest>> {
+#[get("/query/ram_use/<shaper>")]
+pub async fn last_ram_use(_user: ReadOnlyUser, shaper: String) -> Json<Vec<CpuLoadRest>> {
     let result = InfluxQuery::new()
-        .with_range_string("start: -15m")
-        .with_aggregate("5m", AggregateFunction::Mean)
+        .with_range(&FluxRange::last(FluxDuration::minutes(15)))
+        .with_aggregate(&FluxDuration::minutes(5), AggregateFunction::Mean)
         .with_measurement("memory")
+        .with_filter(QueryFilter::tag("host", shaper))
         .with_group("cpu")
         .run::<CpuLoad>()
         .await
@@ -42,20 +44,18 @@ pub async fn last_ram_use() -> Json<Vec<CpuLoadRest>> {
     Json(result)
 }
 
-#[get("/query/swap_use")]
-pub async fn last_swap_use() -> Json<Vec<CpuLoadRest>> {
-    use influxdb2::{models::Query, Client};
-    let qs = "from(bucket: \"bracketqos\")
-    |> range(start: -15m)
-    |> filter(fn: (r) => r[\"_measurement\"] == \"swap\")
-    |> aggregateWindow(every: 5m, fn: mean, createEmpty: false)
-    |> last()
-    ";
-    let query = Query::new(qs.to_string());
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result: Vec<CpuLoad> = client.query::<CpuLoad>(Some(query)).await.unwrap();
-    let result = result
+#[get("/query/swap_use/<shaper>")]
+pub async fn last_swap_use(_user: ReadOnlyUser, shaper: String) -> Json<Vec<CpuLoadRest>> {
+    let result = InfluxQuery::new()
+        .with_range(&FluxRange::last(FluxDuration::minutes(15)))
+        .with_aggregate(&FluxDuration::minutes(5), AggregateFunction::Mean)
+        .with_measurement("swap")
+        .with_filter(QueryFilter::tag("host", shaper))
+        .with_last()
+        .run::<CpuLoad>()
+        .await
+        .unwrap()
+        .0
         .iter()
         .map(|c| CpuLoadRest { usage: c.value })
         .collect::<Vec<CpuLoadRest>>();
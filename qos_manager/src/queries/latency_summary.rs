@@ -1,9 +1,15 @@
+use crate::auth::ReadOnlyUser;
+use crate::bus::{latency_source_filter, DEFAULT_LATENCY_SOURCE};
+use crate::influx::*;
+use crate::queries::InternetBandwidth;
+use anyhow::Result;
 use chrono::{DateTime, FixedOffset, Local};
 use influxdb2_structmap::FromMap;
 use rocket::{
     serde::{json::Json, Serialize},
     tokio::join,
 };
+use std::collections::BTreeMap;
 
 #[derive(influxdb2_structmap_derive::FromMap, Debug)]
 pub struct LatencySummary {
@@ -29,60 +35,110 @@ pub struct LatencySummaryRest {
     pub date: String,
 }
 
-async fn calc_latency(id: &str, range: &str, aggregate: &str, mode: &str) -> Vec<LatencySummary> {
-    use influxdb2::{models::Query, Client};
-    let range = urlencoding::decode(&range).unwrap();
-    let aggregate = urlencoding::decode(&aggregate).unwrap();
-    //println!("{range}, {aggregate}");
+/// Latency distribution for a site, per aggregation window. Data written
+/// before distributions were reported only has an average, which is used for
+/// every field. Each client's percentiles are also written to its access
+/// point and sites, so for those `median` and `p95` are averages of their
+/// clients' percentiles, rather than percentiles of every sample.
+#[derive(Serialize, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct LatencyDistributionRest {
+    pub avg: f64,
+    pub min: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    pub samples: f64,
+    pub date: String,
+}
+
+/// A query for some of a site's latency fields from `source`, combined
+/// across each window with `function`.
+fn latency_query(
+    id: &str,
+    source: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
+    fields: &[&str],
+    function: AggregateFunction,
+) -> InfluxQuery {
+    InfluxQuery::new()
+        .with_range(range)
+        .with_aggregate(aggregate, function)
+        .with_measurement("latency")
+        .with_filter(QueryFilter::AnyOf {
+            field: "_field".to_string(),
+            values: fields.iter().map(|f| f.to_string()).collect(),
+        })
+        .with_filter(QueryFilter::tag("site", id))
+        .with_filter(latency_source_filter(source))
+        .with_group("_field")
+}
 
-    // Get Average
-    let filter = format!("r[\"site\"] == \"{id}\"");
-    let qs = format!(
-        "from(bucket: \"bracketqos\")
-    |> range({range})
-    |> filter(fn: (r) => r[\"_measurement\"] == \"latency\")
-    |> filter(fn: (r) => r[\"_field\"] == \"latency\")
-    |> filter(fn: (r) => {filter})
-    |> group(columns: [\"_measurement\"])
-    |> aggregateWindow(every: {aggregate}, fn: {mode}, createEmpty: false)
-    |> yield(name: \"{mode}\")
-    "
+async fn calc_latency(
+    id: &str,
+    source: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
+) -> Result<Vec<LatencyDistributionRest>> {
+    // The lowest minimum and highest maximum of the reports in each window,
+    // the total samples, and the mean of everything else.
+    let query =
+        |fields: &[&str], function| latency_query(id, source, range, aggregate, fields, function);
+    let (averaged, min, max, samples) = join!(
+        query(
+            &["latency", "latency_median", "latency_p95"],
+            AggregateFunction::Mean
+        )
+        .run::<InternetBandwidth>(),
+        query(&["latency_min"], AggregateFunction::Min).run::<InternetBandwidth>(),
+        query(&["latency_max"], AggregateFunction::Max).run::<InternetBandwidth>(),
+        query(&["latency_samples"], AggregateFunction::Sum).run::<InternetBandwidth>(),
     );
-    //println!("{qs}");
-    let query = Query::new(qs.to_string());
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let mut average: Vec<LatencySummary> =
-        client.query::<LatencySummary>(Some(query)).await.unwrap();
 
-    // Sort by time
-    average.sort_by(|a, b| a.time.cmp(&b.time));
-    average
+    let mut by_time: BTreeMap<DateTime<FixedOffset>, LatencyDistributionRest> = BTreeMap::new();
+    for point in [averaged?.0, min?.0, max?.0, samples?.0].iter().flatten() {
+        let entry = by_time.entry(point.time).or_default();
+        match point.field.as_str() {
+            "latency" => entry.avg = point.value,
+            "latency_min" => entry.min = point.value,
+            "latency_median" => entry.median = point.value,
+            "latency_p95" => entry.p95 = point.value,
+            "latency_max" => entry.max = point.value,
+            _ => entry.samples = point.value,
+        }
+    }
+    Ok(by_time
+        .into_iter()
+        .map(|(time, mut latency)| {
+            if latency.samples == 0.0 {
+                latency.min = latency.avg;
+                latency.median = latency.avg;
+                latency.p95 = latency.avg;
+                latency.max = latency.avg;
+            }
+            latency.date = chrono::DateTime::<Local>::from(time).to_rfc3339();
+            latency
+        })
+        .collect())
 }
 
-#[get("/query/latency_site/<id>/<range>/<aggregate>")]
+/// Latency for a site from one source (`pping`, `icmp` or `file`), pping
+/// if none is given.
+#[get("/query/latency_site/<id>/<range>/<aggregate>?<source>")]
 pub async fn latency_site(
+    _user: ReadOnlyUser,
     id: String,
-    range: String,
-    aggregate: String,
-) -> Json<Vec<LatencySummaryRest>> {
-    let (average, min, max) = join!(
-        calc_latency(&id, &range, &aggregate, "median"),
-        calc_latency(&id, &range, &aggregate, "min"),
-        calc_latency(&id, &range, &aggregate, "max"),
-    );
-
-    let max_index = usize::min(average.len(), usize::min(min.len(), max.len()));
-    let mut rest = Vec::new();
-    for i in 0..max_index {
-        let local_time = chrono::DateTime::<Local>::from(average[i].time);
-        rest.push(LatencySummaryRest {
-            date: local_time.to_rfc3339(),
-            avg: average[i].value,
-            min: min[i].value,
-            max: max[i].value,
-        })
+    range: FluxRange,
+    aggregate: FluxDuration,
+    source: Option<String>,
+) -> Json<Vec<LatencyDistributionRest>> {
+    let source = source.as_deref().unwrap_or(DEFAULT_LATENCY_SOURCE);
+    match calc_latency(&id, source, &range, &aggregate).await {
+        Ok(latency) => Json(latency),
+        Err(e) => {
+            println!("Unable to query latency for {id}: {e:?}");
+            Json(Vec::new())
+        }
     }
-
-    Json(rest)
 }
@@ -20,3 +20,25 @@ mod uisp;
 pub use uisp::*;
 mod frequency;
 pub use frequency::*;
+mod health;
+pub use health::*;
+mod cake;
+pub use cake::*;
+mod class_stats;
+pub use class_stats::*;
+mod settings;
+pub use settings::*;
+mod reachability;
+pub use reachability::*;
+mod outages;
+pub use outages::*;
+mod alerts;
+pub use alerts::*;
+mod heartbeat;
+pub use heartbeat::*;
+mod rebuilds;
+pub use rebuilds::*;
+mod topology;
+pub use topology::*;
+mod audit;
+pub use audit::*;
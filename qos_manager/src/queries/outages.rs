@@ -0,0 +1,9 @@
+use crate::auth::ReadOnlyUser;
+use crate::outages::{get_outages, Outage};
+use rocket::serde::json::Json;
+
+/// Ongoing outages first, then recent ones, newest first.
+#[get("/query/outages")]
+pub async fn outage_list(_user: ReadOnlyUser) -> Json<Vec<Outage>> {
+    Json(get_outages())
+}
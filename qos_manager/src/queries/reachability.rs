@@ -0,0 +1,108 @@
+use crate::auth::ReadOnlyUser;
+use crate::bus::{current_reachability, get_tree_ancestors, get_tree_node_by_id};
+use crate::influx::*;
+use crate::queries::InternetBandwidth;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local};
+use rocket::serde::{json::Json, Serialize};
+use shared_rest::NodeKind;
+use std::collections::BTreeMap;
+
+/// Availability of a client, or of the clients beneath an access point or
+/// site, per aggregation window.
+#[derive(Serialize, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct ReachabilityRest {
+    pub time: String,
+    /// Percentage of probes (or clients) that answered.
+    pub availability: f64,
+    /// Percentage of pings lost.
+    pub loss: f64,
+    pub rtt: Option<f64>,
+}
+
+async fn reachability_query(
+    id: &str,
+    range: &FluxRange,
+    aggregate: &FluxDuration,
+) -> Result<Vec<ReachabilityRest>> {
+    let points = InfluxQuery::new()
+        .with_range(range)
+        .with_aggregate(aggregate, AggregateFunction::Mean)
+        .with_measurement("reachability")
+        .with_filter(QueryFilter::AnyOf {
+            field: "_field".to_string(),
+            values: vec!["up".to_string(), "loss".to_string(), "rtt".to_string()],
+        })
+        .with_filter(QueryFilter::tag("site", id))
+        .with_group("_field")
+        .run::<InternetBandwidth>()
+        .await?;
+
+    let mut by_time: BTreeMap<DateTime<FixedOffset>, ReachabilityRest> = BTreeMap::new();
+    for point in points.0.iter() {
+        let entry = by_time.entry(point.time).or_default();
+        match point.field.as_str() {
+            "up" => entry.availability = point.value * 100.0,
+            "loss" => entry.loss = point.value * 100.0,
+            _ => entry.rtt = Some(point.value),
+        }
+    }
+    Ok(by_time
+        .into_iter()
+        .map(|(time, mut stats)| {
+            stats.time = chrono::DateTime::<Local>::from(time).to_rfc3339();
+            stats
+        })
+        .collect())
+}
+
+#[get("/query/reachability/<id>/<range>/<aggregate>")]
+pub async fn site_reachability(
+    _user: ReadOnlyUser,
+    id: String,
+    range: FluxRange,
+    aggregate: FluxDuration,
+) -> Json<Vec<ReachabilityRest>> {
+    Json(reachability_query(&id, &range, &aggregate).await.unwrap())
+}
+
+/// A client queue that didn't answer its most recent probes.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DownClientRest {
+    pub id: String,
+    pub name: String,
+    pub level_type: Option<NodeKind>,
+    pub parent_name: Option<String>,
+    pub shaper_id: String,
+    /// When the client stopped answering (RFC 3339).
+    pub since: String,
+}
+
+#[get("/query/reachability_down")]
+pub async fn down_clients(_user: ReadOnlyUser) -> Json<Vec<DownClientRest>> {
+    let mut down: Vec<(DateTime<Local>, DownClientRest)> = current_reachability()
+        .into_iter()
+        .filter(|(_, state)| !state.up)
+        .map(|(id, state)| {
+            let node = get_tree_node_by_id(&id);
+            let parent_name = get_tree_ancestors(&id)
+                .and_then(|ancestors| ancestors.get(1).map(|p| p.name.clone()));
+            (
+                state.since,
+                DownClientRest {
+                    name: node.as_ref().map(|n| n.name.clone()).unwrap_or(id.clone()),
+                    level_type: node.map(|n| n.level_type),
+                    parent_name,
+                    id,
+                    shaper_id: state.shaper_id,
+                    since: state.since.to_rfc3339(),
+                },
+            )
+        })
+        .collect();
+    // Longest outages first.
+    down.sort_by_key(|(since, _)| *since);
+    Json(down.into_iter().map(|(_, client)| client).collect())
+}
@@ -0,0 +1,9 @@
+use crate::auth::ReadOnlyUser;
+use crate::bus::{get_rebuilds, RebuildRecord};
+use rocket::serde::json::Json;
+
+/// Queue rebuilds reported by every shaper, newest first.
+#[get("/query/rebuilds")]
+pub async fn rebuild_list(_user: ReadOnlyUser) -> Json<Vec<RebuildRecord>> {
+    Json(get_rebuilds())
+}
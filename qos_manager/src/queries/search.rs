@@ -1,16 +1,18 @@
+use crate::auth::ReadOnlyUser;
 use crate::bus::get_queue_tree;
 use rocket::serde::{json::Json, Serialize};
+use shared_rest::NodeKind;
 
 #[derive(Serialize, Clone, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct SearchResult {
     pub name: String,
     pub id: String,
-    pub rtype: String,
+    pub rtype: NodeKind,
 }
 
 #[post("/query/search", data = "<term>")]
-pub async fn post_search(term: String) -> Json<Vec<SearchResult>> {
+pub async fn post_search(_user: ReadOnlyUser, term: String) -> Json<Vec<SearchResult>> {
     let term = term.to_uppercase();
 
     let tree = get_queue_tree();
@@ -23,7 +25,7 @@ pub async fn post_search(term: String) -> Json<Vec<SearchResult>> {
         .map(|t| SearchResult {
             name: t.name.clone(),
             id: t.id.clone(),
-            rtype: t.level_type.clone(),
+            rtype: t.level_type,
         })
         .collect();
 
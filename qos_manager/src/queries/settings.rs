@@ -0,0 +1,17 @@
+use crate::auth::ReadOnlyUser;
+use crate::config::configuration;
+use rocket::serde::{json::Json, Serialize};
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GraphSettings {
+    /// The finest interval (seconds) at which shapers report statistics.
+    stats_resolution_secs: u64,
+}
+
+#[get("/query/graph_settings")]
+pub async fn graph_settings(_user: ReadOnlyUser) -> Json<GraphSettings> {
+    Json(GraphSettings {
+        stats_resolution_secs: configuration().stats_resolution_secs,
+    })
+}
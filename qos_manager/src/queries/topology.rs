@@ -0,0 +1,29 @@
+use crate::auth::ReadOnlyUser;
+use crate::topology::{
+    node_history, tree_version, tree_versions, NodeHistoryEntry, TreeVersion, TreeVersionSummary,
+};
+use rocket::serde::json::Json;
+
+/// Every stored version of the shapers' trees, newest first.
+#[get("/query/topology/history")]
+pub async fn topology_history(_user: ReadOnlyUser) -> Json<Vec<TreeVersionSummary>> {
+    Json(tree_versions())
+}
+
+/// What changed in one version.
+#[get("/query/topology/version/<version>")]
+pub async fn topology_version(_user: ReadOnlyUser, version: u64) -> Option<Json<TreeVersion>> {
+    tree_version(version).map(Json)
+}
+
+/// Every change to one node, newest first.
+#[get("/query/topology/node/<id>")]
+pub async fn node_topology_history(_user: ReadOnlyUser, id: String) -> Json<Vec<NodeHistoryEntry>> {
+    Json(node_history(&id, false))
+}
+
+/// A client's speed and plan changes, newest first.
+#[get("/query/topology/plans/<id>")]
+pub async fn plan_history(_user: ReadOnlyUser, id: String) -> Json<Vec<NodeHistoryEntry>> {
+    Json(node_history(&id, true))
+}
@@ -1,41 +1,53 @@
+use crate::auth::ReadOnlyUser;
 use rocket::serde::json::Json;
 use shared_rest::QueueTreeEntry;
 
-use crate::bus::{get_queue_tree, get_tree_children, get_tree_node_by_id, get_tree_node_by_index};
+use crate::bus::{
+    get_queue_tree, get_shaper_tree, get_tree_ancestors, get_tree_children, get_tree_node_by_id,
+    get_tree_node_by_index, shaper_list,
+};
 
 #[get("/query/all_tree")]
-pub async fn all_tree() -> Json<Vec<QueueTreeEntry>> {
+pub async fn all_tree(_user: ReadOnlyUser) -> Json<Vec<QueueTreeEntry>> {
     Json(get_queue_tree())
 }
 
+/// Lists the shapers reporting to this manager.
+#[get("/query/shapers")]
+pub async fn shapers(_user: ReadOnlyUser) -> Json<Vec<String>> {
+    Json(shaper_list())
+}
+
+/// One shaper's queue tree, as it was sent (rather than merged).
+#[get("/query/shaper_tree/<shaper_id>")]
+pub async fn shaper_tree(
+    _user: ReadOnlyUser,
+    shaper_id: String,
+) -> Option<Json<Vec<QueueTreeEntry>>> {
+    get_shaper_tree(&shaper_id).map(Json)
+}
+
 #[get("/query/node/<id>")]
-pub async fn node_by_id(id: String) -> Json<QueueTreeEntry> {
-    Json(get_tree_node_by_id(&id))
+pub async fn node_by_id(_user: ReadOnlyUser, id: String) -> Option<Json<QueueTreeEntry>> {
+    get_tree_node_by_id(&id).map(Json)
 }
 
 #[get("/query/node_index/<index>")]
-pub async fn node_by_index(index: usize) -> Json<QueueTreeEntry> {
-    Json(get_tree_node_by_index(index))
+pub async fn node_by_index(_user: ReadOnlyUser, index: usize) -> Option<Json<QueueTreeEntry>> {
+    get_tree_node_by_index(index).map(Json)
 }
 
 #[get("/query/children/<id>")]
-pub async fn node_children(id: String) -> Json<Vec<QueueTreeEntry>> {
-    let mut children = get_tree_children(&id);
+pub async fn node_children(_user: ReadOnlyUser, id: String) -> Option<Json<Vec<QueueTreeEntry>>> {
+    let mut children = get_tree_children(&id)?;
     children.sort_by(|a, b| a.name.cmp(&b.name));
-    Json(children)
+    Some(Json(children))
 }
 
 #[get("/query/site_crumbs/<id>")]
-pub async fn site_breadcrumbs(id: String) -> Json<Vec<QueueTreeEntry>> {
-    let mut result = Vec::new();
-
-    let mut current_node = get_tree_node_by_id(&id);
-    result.push(current_node.clone());
-
-    while let Some(parent) = current_node.parent {
-        current_node = get_tree_node_by_index(parent);
-        result.push(current_node.clone());
-    }
-
-    Json(result)
+pub async fn site_breadcrumbs(
+    _user: ReadOnlyUser,
+    id: String,
+) -> Option<Json<Vec<QueueTreeEntry>>> {
+    get_tree_ancestors(&id).map(Json)
 }
@@ -1,13 +1,14 @@
+use crate::auth::ReadOnlyUser;
+use crate::metrics::{write_points, MetricPoint};
 use crate::{bus::get_queue_tree, config::configuration};
 use anyhow::Result;
 use config::QosConfig;
-use influxdb2::models::DataPoint;
-use influxdb2::Client;
 use lazy_static::*;
 use parking_lot::RwLock;
+use rocket::serde::json::Json;
 use rocket::serde::Serialize;
 use rocket::tokio::join;
-use rocket::{futures::stream, serde::json::Json};
+use shared_rest::NodeKind;
 use std::time::Duration;
 use uisp_support::crm_types::{ClientServicePlan, ServicePlan};
 use uisp_support::{load_all_devices_with_interfaces, load_all_sites_with_crm, Device, Site};
@@ -80,37 +81,31 @@ pub async fn poll_ap_frequencies() {
         let devices = DEVICES.read();
         for ap in get_queue_tree()
             .iter()
-            .filter(|t| t.level_type == "ap")
+            .filter(|t| t.level_type == NodeKind::AccessPoint)
             .map(|t| t.id.clone())
         {
             for device in devices.iter().filter(|d| d.get_id() == ap) {
                 if let Some(overview) = &device.overview {
                     if let Some(frequency) = &overview.frequency {
                         tmp.push(
-                            DataPoint::builder("frequency")
+                            MetricPoint::new("frequency")
                                 .tag("access_point", &device.get_id())
-                                .field("frequency", *frequency)
-                                .build()
-                                .unwrap(),
+                                .field("frequency", *frequency),
                         );
                     }
                 }
                 if let Some(noise_floor) = device.get_noise_floor() {
                     tmp.push(
-                        DataPoint::builder("noise_floor")
+                        MetricPoint::new("noise_floor")
                             .tag("access_point", &device.get_id())
-                            .field("noise_floor", noise_floor as f64)
-                            .build()
-                            .unwrap(),
+                            .field("noise_floor", noise_floor as f64),
                     );
                 }
             }
         }
     }
 
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let _ = client.write("bracketqos", stream::iter(tmp)).await;
+    write_points(tmp);
 }
 
 pub async fn poll_signals() {
@@ -121,20 +116,16 @@ pub async fn poll_signals() {
             if let Some(overview) = &device.overview {
                 if let Some(signal) = &overview.signal {
                     tmp.push(
-                        DataPoint::builder("signal")
+                        MetricPoint::new("signal")
                             .tag("access_point", &device.get_id())
-                            .field("signal", *signal as f64)
-                            .build()
-                            .unwrap(),
+                            .field("signal", *signal as f64),
                     )
                 }
             }
         }
     }
 
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let _ = client.write("bracketqos", stream::iter(tmp)).await;
+    write_points(tmp);
 }
 
 fn find_interface_speed_actual(device_id: &str) -> Vec<String> {
@@ -161,14 +152,17 @@ fn find_interface_speed_actual(device_id: &str) -> Vec<String> {
 }
 
 #[get("/query/device_interface_speed/<device_id>")]
-pub async fn find_interface_speed(device_id: String) -> Json<Vec<String>> {
+pub async fn find_interface_speed(_user: ReadOnlyUser, device_id: String) -> Json<Vec<String>> {
     Json(find_interface_speed_actual(&device_id))
 }
 
 #[get("/query/10mbit_ap")]
-pub async fn ap_at_10() -> Json<Vec<(String, String, String)>> {
+pub async fn ap_at_10(_user: ReadOnlyUser) -> Json<Vec<(String, String, String)>> {
     let mut result = Vec::new();
-    for t in get_queue_tree().iter().filter(|t| t.level_type == "ap") {
+    for t in get_queue_tree()
+        .iter()
+        .filter(|t| t.level_type == NodeKind::AccessPoint)
+    {
         find_interface_speed_actual(&t.id)
             .iter()
             .filter(|s| *s == "10-full" || *s == "10-half")
@@ -181,11 +175,11 @@ pub async fn ap_at_10() -> Json<Vec<(String, String, String)>> {
 }
 
 #[get("/query/10mbit_device")]
-pub async fn device_at_10() -> Json<Vec<(String, String, String)>> {
+pub async fn device_at_10(_user: ReadOnlyUser) -> Json<Vec<(String, String, String)>> {
     let devices = DEVICES.read();
     let access_points: std::collections::HashSet<String> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "ap")
+        .filter(|t| t.level_type == NodeKind::AccessPoint)
         .map(|t| t.id.clone())
         .collect();
     let mut result = Vec::new();
@@ -312,7 +306,7 @@ async fn access_point_info_actual(id: &str) -> AccessPointInfo {
 }
 
 #[get("/query/access_point_info/<id>")]
-pub async fn access_point_info(id: String) -> Json<AccessPointInfo> {
+pub async fn access_point_info(_user: ReadOnlyUser, id: String) -> Json<AccessPointInfo> {
     Json(access_point_info_actual(&id).await)
 }
 
@@ -333,7 +327,7 @@ fn find_site_devices(id: &str) -> Vec<String> {
 }
 
 #[get("/query/site_device_list/<id>")]
-pub async fn site_device_list(id: String) -> Json<Vec<AccessPointInfo>> {
+pub async fn site_device_list(_user: ReadOnlyUser, id: String) -> Json<Vec<AccessPointInfo>> {
     let mut result = Vec::new();
     for site_id in find_site_devices(&id).iter() {
         let device = access_point_info_actual(&site_id).await;
@@ -343,7 +337,7 @@ pub async fn site_device_list(id: String) -> Json<Vec<AccessPointInfo>> {
 }
 
 #[get("/query/site_suspended/<id>")]
-pub async fn site_suspended(id: String) -> Json<bool> {
+pub async fn site_suspended(_user: ReadOnlyUser, id: String) -> Json<bool> {
     if let Some(site) = SITES.read().iter().find(|s| s.id == id) {
         if let Some(site_id) = &site.identification {
             return Json(site_id.suspended);
@@ -1,11 +1,12 @@
+use crate::auth::ReadOnlyUser;
 use crate::{
     bus::get_queue_tree,
-    queries::{InternetBandwidth, InternetBandwidthRest},
+    influx::{AggregateFunction, FluxDuration, FluxRange, InfluxQuery, QueryFilter},
+    queries::{combine_shapers, InternetBandwidth},
 };
-use chrono::Local;
 use rocket::futures::future::join_all;
 use rocket::serde::{json::Json, Serialize};
-use shared_rest::QueueTreeEntry;
+use shared_rest::{NodeKind, QueueTreeEntry};
 
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
@@ -19,11 +20,11 @@ pub struct SiteCongestion {
 }
 
 #[get("/reports/site_congestion")]
-pub async fn site_congestion() -> Json<Vec<SiteCongestion>> {
+pub async fn site_congestion(_user: ReadOnlyUser) -> Json<Vec<SiteCongestion>> {
     let mut futures = Vec::new();
     let sites: Vec<QueueTreeEntry> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "tower")
+        .filter(|t| t.level_type == NodeKind::Tower)
         .cloned()
         .collect();
 
@@ -56,11 +57,11 @@ pub async fn site_congestion() -> Json<Vec<SiteCongestion>> {
 }
 
 #[get("/reports/ap_congestion")]
-pub async fn ap_congestion() -> Json<Vec<SiteCongestion>> {
+pub async fn ap_congestion(_user: ReadOnlyUser) -> Json<Vec<SiteCongestion>> {
     let mut futures = Vec::new();
     let sites: Vec<QueueTreeEntry> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "ap")
+        .filter(|t| t.level_type.is_ap_queue())
         .cloned()
         .collect();
 
@@ -93,11 +94,11 @@ pub async fn ap_congestion() -> Json<Vec<SiteCongestion>> {
 }
 
 #[get("/reports/client_congestion")]
-pub async fn client_congestion() -> Json<Vec<SiteCongestion>> {
+pub async fn client_congestion(_user: ReadOnlyUser) -> Json<Vec<SiteCongestion>> {
     let mut futures = Vec::new();
     let sites: Vec<QueueTreeEntry> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "client")
+        .filter(|t| t.level_type.is_client_queue())
         .cloned()
         .collect();
 
@@ -130,35 +131,26 @@ pub async fn client_congestion() -> Json<Vec<SiteCongestion>> {
 }
 
 async fn peak_bandwidth(id: String) -> (u32, u32) {
-    use influxdb2::{models::Query, Client};
-    let qs = format!(
-        "from(bucket: \"bracketqos\")
-    |> range(start: -1d)
-    |> filter(fn: (r) => r[\"_measurement\"] == \"queues\")
-    |> filter(fn: (r) => r[\"_field\"] == \"down_mbps\" or r[\"_field\"] == \"up_mbps\")
-    |> filter(fn: (r) => r[\"site\"] == \"{id}\")
-    |> aggregateWindow(every: 2d, fn: max, createEmpty: false)
-    |> yield(name: \"max\")
-    "
-    );
-    let query = Query::new(qs.to_string());
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let mut result: Vec<InternetBandwidth> = client
-        .query::<InternetBandwidth>(Some(query))
+    peak_bandwidth_over(id, FluxDuration::days(1)).await
+}
+
+/// Peak download and upload (Mbps) of a node over the last `range`, which
+/// should be under two days.
+pub async fn peak_bandwidth_over(id: String, range: FluxDuration) -> (u32, u32) {
+    let result = InfluxQuery::new()
+        .with_range(&FluxRange::last(range))
+        .with_aggregate(&FluxDuration::days(2), AggregateFunction::Max)
+        .with_measurement("queues")
+        .with_filter(QueryFilter::Either {
+            field: "_field".to_string(),
+            value_1: "down_mbps".to_string(),
+            value_2: "up_mbps".to_string(),
+        })
+        .with_filter(QueryFilter::tag("site", id))
+        .run::<InternetBandwidth>()
         .await
         .unwrap();
-    //println!("{:?}", result);
-    result.sort_by(|a, b| b.time.cmp(&a.time));
-    if let Some(rest) = result
-        .chunks(2)
-        .map(|n| InternetBandwidthRest {
-            time: chrono::DateTime::<Local>::from(n[0].time).to_rfc3339(),
-            up: n[1].value,
-            down: n[0].value,
-        })
-        .next()
-    {
+    if let Some(rest) = combine_shapers(&result.0).pop() {
         (rest.down as u32, rest.up as u32)
     } else {
         (0, 0)
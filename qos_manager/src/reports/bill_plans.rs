@@ -1,6 +1,8 @@
-use crate::bus::{get_queue_tree, get_tree_node_by_index};
+use crate::auth::ReadOnlyUser;
+use crate::bus::{get_queue_tree, get_tree_ancestors};
 use crate::queries::{CLIENTS, CLIENT_PLANS, SERVICE_PLANS, SITES};
 use rocket::serde::{json::Json, Serialize};
+use shared_rest::NodeKind;
 
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
@@ -21,12 +23,11 @@ pub struct BillingInfo {
 }
 
 #[get("/reports/billing_plans")]
-pub async fn billing_plans() -> Json<Vec<BillingInfo>> {
+pub async fn billing_plans(_user: ReadOnlyUser) -> Json<Vec<BillingInfo>> {
     let mut result = Vec::new();
     get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "client")
-        .filter(|c| !c.name.contains(" Infrastructure"))
+        .filter(|t| t.level_type == NodeKind::Client)
         .for_each(|c| {
             let mut plan_name = String::new();
             let mut suspended = String::new();
@@ -145,21 +146,29 @@ pub async fn billing_plans() -> Json<Vec<BillingInfo>> {
                             })
                     });
             }
-            let parent = get_tree_node_by_index(c.parent.unwrap_or(0));
-            let site = get_tree_node_by_index(parent.parent.unwrap_or(0));
+            // The client's access point, and the site above that.
+            let ancestors = get_tree_ancestors(&c.id).unwrap_or_default();
+            let (parent_id, parent_name) = ancestors
+                .get(1)
+                .map(|p| (p.id.clone(), p.name.clone()))
+                .unwrap_or_default();
+            let (site_id, site_name) = ancestors
+                .get(2)
+                .map(|s| (s.id.clone(), s.name.clone()))
+                .unwrap_or_default();
             result.push(BillingInfo {
                 id: c.id.clone(),
                 name: c.name.clone(),
                 down: c.down_mbps,
                 up: c.up_mbps,
-                parent_id: parent.id.clone(),
-                parent_name: parent.name.clone(),
+                parent_id,
+                parent_name,
                 crm_site: plan_name,
                 suspended,
                 price,
                 reseller,
-                site_id: site.id.clone(),
-                site_name: site.name.clone(),
+                site_id,
+                site_name,
                 outstanding,
             });
         });
@@ -1,16 +1,17 @@
 use super::peak_latency;
+use crate::auth::ReadOnlyUser;
 use crate::bus::get_queue_tree;
-use anyhow::{Error, Result};
+use crate::config::configuration;
+use crate::store::{add_nightly_report, recent_nightly_reports};
+use anyhow::Result;
 use rocket::{
     serde::{json::Json, Deserialize, Serialize},
     tokio::{join, time::sleep},
 };
-use ron::{
-    de::from_reader,
-    ser::{to_string_pretty, PrettyConfig},
-};
-use std::{fs::File, path::Path, time::Duration};
+use shared_rest::NodeKind;
+use std::time::Duration;
 
+/// The contents of `nightly.ron`, as written by older managers.
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct NightlyReport {
@@ -35,54 +36,30 @@ pub async fn nightly_report_runner() {
     loop {
         let (ap, site) = join!(ap_report(), site_report());
 
-        let mut report = if let Ok(report) = load_nightly() {
-            report
-        } else {
-            NightlyReport {
-                reports: Vec::new(),
-            }
-        };
-
         if let Ok(ap) = ap {
             if let Ok(site) = site {
                 let now = chrono::offset::Local::now().to_rfc3339();
-                report.reports.push((now, ap, site));
+                if let Err(e) = add_nightly_report(&now, &ap, &site) {
+                    println!("Unable to store the nightly report: {e:?}");
+                }
             }
         }
 
-        report.reports.sort_by(|a, b| a.0.cmp(&b.0));
-        while report.reports.len() > 30 {
-            report.reports.remove(0);
-        }
-        let _ = save_nightly(report);
-
-        sleep(Duration::from_secs(86400)).await;
+        sleep(Duration::from_secs(configuration().nightly_report_secs)).await;
     }
 }
 
-const NIGHTLY_FILE: &str = "nightly.ron";
-
-fn load_nightly() -> Result<NightlyReport> {
-    let path = Path::new(NIGHTLY_FILE);
-    if !path.exists() {
-        return Err(Error::msg("Please setup {CONFIG_FILENAME}"));
-    }
-    let f = File::open(NIGHTLY_FILE).unwrap();
-    let cfg: NightlyReport = from_reader(f)?;
-    Ok(cfg)
-}
-
-fn save_nightly(nightly: NightlyReport) -> Result<()> {
-    let header_ron = to_string_pretty(&nightly, PrettyConfig::new())?;
-    std::fs::write(NIGHTLY_FILE, header_ron)?;
-    Ok(())
-}
+/// The number of nightly runs shown when no limit is given.
+const NIGHTLY_DISPLAY_RUNS: usize = 30;
 
 async fn ap_report() -> Result<ApReport> {
     let mut report = ApReport {
         access_points: Vec::new(),
     };
-    for t in get_queue_tree().iter().filter(|t| t.level_type == "ap") {
+    for t in get_queue_tree()
+        .iter()
+        .filter(|t| t.level_type.is_ap_queue())
+    {
         let median_latency = peak_latency(t.id.clone()).await;
         report.access_points.push((t.name.clone(), median_latency));
     }
@@ -91,7 +68,10 @@ async fn ap_report() -> Result<ApReport> {
 
 async fn site_report() -> Result<SiteReport> {
     let mut report = SiteReport { sites: Vec::new() };
-    for t in get_queue_tree().iter().filter(|t| t.level_type == "tower") {
+    for t in get_queue_tree()
+        .iter()
+        .filter(|t| t.level_type == NodeKind::Tower)
+    {
         let median_latency = peak_latency(t.id.clone()).await;
         report.sites.push((t.name.clone(), median_latency));
     }
@@ -113,14 +93,16 @@ pub struct NightlyCountReport {
     pub access_points: Vec<(String, NightlyCount)>,
 }
 
-#[get("/reports/nightly")]
-pub fn nightly_json() -> Json<NightlyCountReport> {
-    let n = load_nightly().unwrap();
+/// Counts of good, medium and bad sites and access points for the most
+/// recent `limit` nightly runs (30 by default).
+#[get("/reports/nightly?<limit>")]
+pub fn nightly_json(_user: ReadOnlyUser, limit: Option<usize>) -> Json<NightlyCountReport> {
+    let reports = recent_nightly_reports(limit.unwrap_or(NIGHTLY_DISPLAY_RUNS)).unwrap_or_default();
     let mut result = NightlyCountReport {
         sites: Vec::new(),
         access_points: Vec::new(),
     };
-    for (date, ap, site) in n.reports.iter() {
+    for (date, ap, site) in reports.iter() {
         let mut ap_count = NightlyCount {
             good: 0,
             medium: 0,
@@ -1,23 +1,17 @@
-use crate::bus::get_queue_tree;
+use crate::auth::ReadOnlyUser;
+use crate::bus::{get_subtree_clients, get_tree_children, get_tree_node_by_index};
 use rocket::serde::{json::Json, Serialize};
-use shared_rest::QueueTreeEntry;
+use shared_rest::{NodeKind, QueueTreeEntry};
+use std::cmp::Ordering;
 
-fn sum_bandwidth(tree: &[QueueTreeEntry], node: usize) -> (u32, u32) {
-    let (mut down, mut up) = (0, 0);
-    if tree[node].level_type == "client" && !tree[node].name.ends_with(" Infrastructure") {
-        //println!("{}: {}/{}", tree[node].name, tree[node].down_mbps, tree[node].up_mbps);
-        down = tree[node].down_mbps;
-        up = tree[node].up_mbps;
-    }
-    tree.iter()
-        .enumerate()
-        .filter(|(_, t)| t.parent.unwrap_or(usize::MAX) == node)
-        .for_each(|(node_n, _t)| {
-            let (cdown, cup) = sum_bandwidth(tree, node_n);
-            down += cdown;
-            up += cup;
-        });
-    (down, up)
+/// The bandwidth sold to client sites at or beneath a node. Infrastructure
+/// isn't sold, so it isn't counted.
+fn sum_bandwidth(id: &str) -> (u32, u32) {
+    get_subtree_clients(id)
+        .unwrap_or_default()
+        .iter()
+        .filter(|c| c.level_type == NodeKind::Client)
+        .fold((0, 0), |(down, up), c| (down + c.down_mbps, up + c.up_mbps))
 }
 
 #[derive(Debug, Serialize)]
@@ -31,32 +25,32 @@ pub struct OversellNode {
 }
 
 impl OversellNode {
-    pub fn from_tree(tree: &[QueueTreeEntry], node: usize) -> Self {
+    pub fn from_tree(node: &QueueTreeEntry) -> Self {
         let mut result = Self {
-            name: tree[node].name.clone(),
-            id: tree[node].id.clone(),
-            available: (tree[node].down_mbps, tree[node].up_mbps),
-            sold: sum_bandwidth(tree, node),
+            name: node.name.clone(),
+            id: node.id.clone(),
+            available: (node.down_mbps, node.up_mbps),
+            sold: sum_bandwidth(&node.id),
             children: Vec::new(),
         };
-        for (i, _child) in tree
+        for child in get_tree_children(&node.id)
+            .unwrap_or_default()
             .iter()
-            .enumerate()
-            .filter(|(_i, c)| c.level_type != "client" && c.parent.unwrap_or(usize::MAX) == node)
+            .filter(|c| !c.level_type.is_client_queue())
         {
-            result.children.push(OversellNode::from_tree(tree, i));
+            result.children.push(OversellNode::from_tree(child));
         }
         result.children.sort_by(|a, b| {
             let a_ratio = a.sold.0 as f32 / a.available.0 as f32;
             let b_ratio = b.sold.0 as f32 / b.available.0 as f32;
-            b_ratio.partial_cmp(&a_ratio).unwrap()
+            b_ratio.partial_cmp(&a_ratio).unwrap_or(Ordering::Equal)
         });
         result
     }
 }
 
 #[get("/reports/oversell")]
-pub fn oversell_report() -> Json<OversellNode> {
-    let tree = get_queue_tree();
-    Json(OversellNode::from_tree(&tree, 0))
+pub fn oversell_report(_user: ReadOnlyUser) -> Option<Json<OversellNode>> {
+    let root = get_tree_node_by_index(0)?;
+    Some(Json(OversellNode::from_tree(&root)))
 }
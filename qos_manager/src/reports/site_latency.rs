@@ -1,10 +1,12 @@
+use crate::auth::ReadOnlyUser;
 use crate::{
-    bus::{get_queue_tree, get_tree_children, get_tree_node_by_id},
+    bus::{get_queue_tree, get_subtree_clients, latency_source_filter, DEFAULT_LATENCY_SOURCE},
+    influx::{AggregateFunction, FluxDuration, FluxRange, InfluxQuery, QueryFilter},
     queries::LatencySummary,
 };
 use rocket::serde::{json::Json, Serialize};
 use rocket::{futures::future::join_all, tokio::join};
-use shared_rest::QueueTreeEntry;
+use shared_rest::{NodeKind, QueueTreeEntry};
 
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
@@ -16,12 +18,12 @@ pub struct SiteLatency {
 }
 
 #[get("/reports/site_latency")]
-pub async fn site_tcp_latency() -> Json<Vec<SiteLatency>> {
+pub async fn site_tcp_latency(_user: ReadOnlyUser) -> Json<Vec<SiteLatency>> {
     let mut futures_median = Vec::new();
     let mut futures_peak = Vec::new();
     let sites: Vec<QueueTreeEntry> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "tower")
+        .filter(|t| t.level_type == NodeKind::Tower)
         .cloned()
         .collect();
 
@@ -56,12 +58,12 @@ pub async fn site_tcp_latency() -> Json<Vec<SiteLatency>> {
 }
 
 #[get("/reports/ap_latency")]
-pub async fn ap_tcp_latency() -> Json<Vec<SiteLatency>> {
+pub async fn ap_tcp_latency(_user: ReadOnlyUser) -> Json<Vec<SiteLatency>> {
     let mut futures_median = Vec::new();
     let mut futures_peak = Vec::new();
     let sites: Vec<QueueTreeEntry> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "ap")
+        .filter(|t| t.level_type.is_ap_queue())
         .cloned()
         .collect();
 
@@ -96,12 +98,12 @@ pub async fn ap_tcp_latency() -> Json<Vec<SiteLatency>> {
 }
 
 #[get("/reports/client_latency")]
-pub async fn client_tcp_latency() -> Json<Vec<SiteLatency>> {
+pub async fn client_tcp_latency(_user: ReadOnlyUser) -> Json<Vec<SiteLatency>> {
     let mut futures_median = Vec::new();
     let mut futures_peak = Vec::new();
     let sites: Vec<QueueTreeEntry> = get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "client")
+        .filter(|t| t.level_type.is_client_queue())
         .cloned()
         .collect();
 
@@ -136,49 +138,58 @@ pub async fn client_tcp_latency() -> Json<Vec<SiteLatency>> {
 }
 
 pub async fn peak_latency(id: String) -> f64 {
-    use influxdb2::{models::Query, Client};
-    let filter = build_site_filter(&id);
-    let qs = format!(
-        "from(bucket: \"bracketqos\")
-    |> range(start: -1d)
-    |> filter(fn: (r) => r[\"_measurement\"] == \"latency\")
-    |> filter(fn: (r) => r[\"_field\"] == \"latency\")
-    |> filter(fn: (r) => {filter})
-    |> aggregateWindow(every: 1d, fn: max, createEmpty: false)
-    |> yield(name: \"max\")
-    "
-    );
-    let query = Query::new(qs.to_string());
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result: Vec<LatencySummary> = client.query::<LatencySummary>(Some(query)).await.unwrap();
-
-    if !result.is_empty() {
-        result[0].value
-    } else {
-        0.0
-    }
+    site_latency(
+        &id,
+        FluxDuration::days(1),
+        FluxDuration::days(1),
+        AggregateFunction::Max,
+    )
+    .await
 }
 
 pub async fn median_latency(id: String) -> f64 {
-    use influxdb2::{models::Query, Client};
-    let filter = build_site_filter(&id);
-    let qs = format!(
-        "from(bucket: \"bracketqos\")
-    |> range(start: -1d)
-    |> filter(fn: (r) => r[\"_measurement\"] == \"latency\")
-    |> filter(fn: (r) => r[\"_field\"] == \"latency\")
-    |> filter(fn: (r) => {filter})
-    |> aggregateWindow(every: 2d, fn: median, createEmpty: false)
-    |> yield(name: \"median\")
-    "
-    );
-    let query = Query::new(qs.to_string());
-    let cfg = crate::configuration();
-    let client = Client::new(cfg.influx_url, cfg.influx_org, cfg.influx_token);
-    let result: Vec<LatencySummary> = client
-        .query::<LatencySummary>(Some(query))
+    site_latency(
+        &id,
+        FluxDuration::days(1),
+        FluxDuration::days(2),
+        AggregateFunction::Median,
+    )
+    .await
+}
+
+/// Aggregates the latency of a node and the client queues beneath it over
+/// the last `range`, which should be under two days.
+pub async fn latency_over(id: &str, range: FluxDuration, function: AggregateFunction) -> f64 {
+    site_latency(id, range, FluxDuration::days(2), function).await
+}
+
+/// Aggregates the latency of a node and the client queues beneath it over
+/// the last `range`, using pping's samples. Returns 0 if the node isn't in
+/// the tree, or has no latency records.
+async fn site_latency(
+    id: &str,
+    range: FluxDuration,
+    every: FluxDuration,
+    function: AggregateFunction,
+) -> f64 {
+    let sites = if let Some(sites) = site_and_clients(id) {
+        sites
+    } else {
+        return 0.0;
+    };
+    let result: Vec<LatencySummary> = InfluxQuery::new()
+        .with_range(&FluxRange::last(range))
+        .with_aggregate(&every, function)
+        .with_measurement("latency")
+        .with_filter(QueryFilter::field("latency"))
+        .with_filter(latency_source_filter(DEFAULT_LATENCY_SOURCE))
+        .with_filter(QueryFilter::AnyOf {
+            field: "site".to_string(),
+            values: sites,
+        })
+        .run::<LatencySummary>()
         .await
+        .map(|r| r.0)
         .unwrap_or(Vec::new());
 
     if !result.is_empty() {
@@ -188,24 +199,12 @@ pub async fn median_latency(id: String) -> f64 {
     }
 }
 
-fn build_site_filter(id: &str) -> String {
-    let node = get_tree_node_by_id(id);
-    if node.level_type == "client" {
-        format!("r[\"site\"] == \"{id}\"")
-    } else {
-        // FIXME
-        let mut query = format!("r[\"site\"] == \"{id}\"");
-        recurse_kids(id, &mut query);
-        //println!("{query}");
-        query
-    }
-}
-
-fn recurse_kids(id: &str, query: &mut String) {
-    for n in get_tree_children(id).iter() {
-        if n.level_type == "client" {
-            *query = format!("{query} or r[\"site\"] == \"{}\"", n.id);
-        }
-        recurse_kids(&n.id, query);
+/// The IDs of a node and the client queues beneath it, or `None` if the node
+/// isn't in the tree.
+fn site_and_clients(id: &str) -> Option<Vec<String>> {
+    let mut sites = vec![id.to_string()];
+    for client in get_subtree_clients(id)?.iter().filter(|c| c.id != id) {
+        sites.push(client.id.clone());
     }
+    Some(sites)
 }
@@ -1,5 +1,7 @@
+use crate::auth::ReadOnlyUser;
 use crate::bus::get_queue_tree;
 use rocket::serde::{json::Json, Serialize};
+use shared_rest::NodeKind;
 
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
@@ -11,11 +13,11 @@ pub struct SpeedPlan {
 }
 
 #[get("/reports/plan_5m")]
-pub async fn plan_5m() -> Json<Vec<SpeedPlan>> {
+pub async fn plan_5m(_user: ReadOnlyUser) -> Json<Vec<SpeedPlan>> {
     let mut result = Vec::new();
     get_queue_tree()
         .iter()
-        .filter(|t| t.level_type == "client" && t.down_mbps == 5)
+        .filter(|t| t.level_type == NodeKind::Client && t.down_mbps == 5)
         .for_each(|c| {
             result.push(SpeedPlan {
                 id: c.id.clone(),
@@ -1,8 +1,16 @@
+use crate::auth::ReadOnlyUser;
 use crate::bus::UNMAPPED_IP;
+use crate::store::{sightings, Sighting, SightingKind};
 use rocket::serde::json::Json;
 
 #[get("/reports/unknown_ip")]
-pub async fn unknown_ip_addresses() -> Json<Vec<String>> {
+pub async fn unknown_ip_addresses(_user: ReadOnlyUser) -> Json<Vec<String>> {
     Json(UNMAPPED_IP.read().iter().map(|(k, _v)| k.clone()).collect())
 }
 
+/// Every unknown IP ever reported, with when each shaper first and last saw
+/// it. Most recently seen first.
+#[get("/reports/unknown_ip_history")]
+pub async fn unknown_ip_history(_user: ReadOnlyUser) -> Json<Vec<Sighting>> {
+    Json(sightings(SightingKind::UnknownIp, None).unwrap_or_default())
+}
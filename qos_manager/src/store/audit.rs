@@ -0,0 +1,49 @@
+use super::with_db;
+use anyhow::Result;
+use chrono::Local;
+use rocket::serde::Serialize;
+use rusqlite::params;
+
+/// A change made through the manager.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct AuditEntry {
+    /// RFC 3339.
+    pub time: String,
+    pub username: String,
+    pub action: String,
+    pub detail: String,
+}
+
+/// Records a change made by a manager user. Failures are printed, since
+/// they shouldn't stop the change itself.
+pub fn audit(username: &str, action: &str, detail: &str) {
+    let result = with_db(|conn| {
+        conn.execute(
+            "INSERT INTO audit_log (time, username, action, detail) VALUES (?1, ?2, ?3, ?4)",
+            params![Local::now().to_rfc3339(), username, action, detail],
+        )?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        println!("Unable to write to the audit log: {e:?}");
+    }
+}
+
+/// The most recent `limit` audit log entries, newest first.
+pub fn audit_log(limit: usize) -> Result<Vec<AuditEntry>> {
+    with_db(|conn| {
+        let mut query = conn.prepare(
+            "SELECT time, username, action, detail FROM audit_log ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = query.query_map([limit as i64], |row| {
+            Ok(AuditEntry {
+                time: row.get(0)?,
+                username: row.get(1)?,
+                action: row.get(2)?,
+                detail: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    })
+}
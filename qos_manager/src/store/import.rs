@@ -0,0 +1,77 @@
+use super::{insert_nightly_report, replace_limits, replace_tree};
+use crate::bus::parse_tree_file;
+use crate::reports::NightlyReport;
+use anyhow::Result;
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension};
+use shared_rest::ShaperTreeConfig;
+use std::path::Path;
+
+/// Imports the RON files written by older managers, each at most once. The
+/// files are left where they are, so a downgrade still finds them.
+pub fn import_ron_files(conn: &mut Connection) -> Result<()> {
+    import_once(conn, "shaper.ron", |conn, data| {
+        let limits: ShaperTreeConfig = ron::from_str(data)?;
+        let tx = conn.transaction()?;
+        replace_limits(&tx, &limits)?;
+        tx.commit()?;
+        Ok(())
+    })?;
+    import_once(conn, "tree.ron", |conn, data| {
+        let tx = conn.transaction()?;
+        for (shaper_id, tree) in parse_tree_file(data)?.iter() {
+            replace_tree(&tx, shaper_id, tree)?;
+        }
+        tx.commit()?;
+        Ok(())
+    })?;
+    import_once(conn, "nightly.ron", |conn, data| {
+        let nightly: NightlyReport = ron::from_str(data)?;
+        let tx = conn.transaction()?;
+        for (date, ap, site) in nightly.reports.iter() {
+            insert_nightly_report(&tx, date, ap, site)?;
+        }
+        tx.commit()?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn import_once(
+    conn: &mut Connection,
+    filename: &str,
+    import: impl FnOnce(&mut Connection, &str) -> Result<()>,
+) -> Result<()> {
+    if !Path::new(filename).exists() {
+        return Ok(());
+    }
+    let imported: Option<String> = conn
+        .query_row(
+            "SELECT imported FROM imports WHERE filename = ?1",
+            [filename],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if imported.is_some() {
+        return Ok(());
+    }
+    // A file that can't be read is reported and tried again next time,
+    // rather than stopping the manager.
+    let data = match std::fs::read_to_string(filename) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Unable to read {filename}: {e:?}");
+            return Ok(());
+        }
+    };
+    if let Err(e) = import(conn, &data) {
+        println!("Unable to import {filename}: {e:?}");
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO imports (filename, imported) VALUES (?1, ?2)",
+        params![filename, Local::now().to_rfc3339()],
+    )?;
+    println!("Imported {filename} into the state database");
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use super::with_db;
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use shared_rest::{ApLimit, ShaperTreeConfig, SiteLimit};
+
+/// The site and access point speed limits set in the manager.
+pub fn load_limits() -> Result<ShaperTreeConfig> {
+    with_db(|conn| {
+        let mut config = ShaperTreeConfig::new();
+        let mut query = conn.prepare("SELECT id, download, upload FROM site_limits ORDER BY id")?;
+        for row in query.query_map([], |row| {
+            Ok(SiteLimit {
+                id: row.get(0)?,
+                download: row.get(1)?,
+                upload: row.get(2)?,
+            })
+        })? {
+            config.sites.push(row?);
+        }
+        let mut query = conn.prepare("SELECT id, download, upload FROM ap_limits ORDER BY id")?;
+        for row in query.query_map([], |row| {
+            Ok(ApLimit {
+                id: row.get(0)?,
+                download: row.get(1)?,
+                upload: row.get(2)?,
+            })
+        })? {
+            config.access_points.push(row?);
+        }
+        Ok(config)
+    })
+}
+
+/// Replaces the stored speed limits.
+pub fn save_limits(config: &ShaperTreeConfig) -> Result<()> {
+    with_db(|conn| {
+        let tx = conn.transaction()?;
+        replace_limits(&tx, config)?;
+        tx.commit()?;
+        Ok(())
+    })
+}
+
+pub(super) fn replace_limits(conn: &Connection, config: &ShaperTreeConfig) -> Result<()> {
+    conn.execute("DELETE FROM site_limits", [])?;
+    conn.execute("DELETE FROM ap_limits", [])?;
+    for site in config.sites.iter() {
+        conn.execute(
+            "INSERT OR REPLACE INTO site_limits (id, download, upload) VALUES (?1, ?2, ?3)",
+            params![site.id, site.download, site.upload],
+        )?;
+    }
+    for ap in config.access_points.iter() {
+        conn.execute(
+            "INSERT OR REPLACE INTO ap_limits (id, download, upload) VALUES (?1, ?2, ?3)",
+            params![ap.id, ap.download, ap.upload],
+        )?;
+    }
+    Ok(())
+}
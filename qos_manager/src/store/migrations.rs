@@ -0,0 +1,71 @@
+use anyhow::{Error, Result};
+use rusqlite::Connection;
+
+/// Each schema version's changes, oldest first. Append to this list; never
+/// edit a migration that has been released.
+const MIGRATIONS: &[&str] = &[
+    // v1: the initial schema.
+    "CREATE TABLE site_limits (
+        id TEXT PRIMARY KEY,
+        download INTEGER NOT NULL,
+        upload INTEGER NOT NULL
+    );
+    CREATE TABLE ap_limits (
+        id TEXT PRIMARY KEY,
+        download INTEGER NOT NULL,
+        upload INTEGER NOT NULL
+    );
+    CREATE TABLE shaper_trees (
+        shaper_id TEXT PRIMARY KEY,
+        received TEXT NOT NULL,
+        tree TEXT NOT NULL
+    );
+    CREATE TABLE nightly_reports (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        date TEXT NOT NULL,
+        access_points TEXT NOT NULL,
+        sites TEXT NOT NULL
+    );
+    CREATE INDEX nightly_reports_date ON nightly_reports (date);
+    CREATE TABLE sightings (
+        kind TEXT NOT NULL,
+        value TEXT NOT NULL,
+        shaper_id TEXT NOT NULL,
+        first_seen TEXT NOT NULL,
+        last_seen TEXT NOT NULL,
+        active INTEGER NOT NULL,
+        PRIMARY KEY (kind, value, shaper_id)
+    );
+    CREATE TABLE audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        time TEXT NOT NULL,
+        username TEXT NOT NULL,
+        action TEXT NOT NULL,
+        detail TEXT NOT NULL
+    );
+    CREATE TABLE imports (
+        filename TEXT PRIMARY KEY,
+        imported TEXT NOT NULL
+    );",
+];
+
+/// The schema version this build expects.
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Applies any migrations the database hasn't had yet, each in its own
+/// transaction. Returns the schema versions before and after.
+pub fn migrate(conn: &mut Connection) -> Result<(usize, usize)> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::msg(format!(
+            "The state database has schema v{version}, but this manager only knows up to v{SCHEMA_VERSION}. Upgrade qos_manager."
+        )));
+    }
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+        tx.commit()?;
+    }
+    Ok((version, SCHEMA_VERSION))
+}
@@ -0,0 +1,58 @@
+//! The manager's SQLite database (`state_db_path`), which holds speed limits,
+//! the shapers' queue trees, nightly report history, sightings of unknown IPs,
+//! duplicate IPs and unmapped clients, and an audit log. Each kind of state has
+//! its own repository module here; the rest of the manager keeps its in-memory
+//! copies and writes through. Outages, rebuilds, topology history, alert rules
+//! and users are still kept in their own RON files.
+//!
+//! The schema is versioned (`PRAGMA user_version`) and migrated on startup.
+//! The RON files that older managers wrote are imported once.
+
+mod migrations;
+use migrations::*;
+mod import;
+use import::*;
+mod limits;
+pub use limits::*;
+mod trees;
+pub use trees::*;
+mod nightly;
+pub use nightly::*;
+mod sightings;
+pub use sightings::*;
+mod audit;
+pub use audit::*;
+mod restore;
+pub use restore::*;
+
+use anyhow::{Error, Result};
+use lazy_static::*;
+use parking_lot::Mutex;
+use rusqlite::Connection;
+
+lazy_static! {
+    static ref DB: Mutex<Option<Connection>> = Mutex::new(None);
+}
+
+/// Opens (creating if need be) the state database, brings its schema up to
+/// date and imports any RON files that haven't been imported yet.
+pub fn open_store(path: &str) -> Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
+    let (from, to) = migrate(&mut conn)?;
+    if from != to {
+        println!("Migrated {path} from schema v{from} to v{to}");
+    }
+    import_ron_files(&mut conn)?;
+    *DB.lock() = Some(conn);
+    Ok(())
+}
+
+/// Runs `f` with the database connection.
+fn with_db<T>(f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+    let mut lock = DB.lock();
+    match lock.as_mut() {
+        Some(conn) => f(conn),
+        None => Err(Error::msg("The state database isn't open")),
+    }
+}
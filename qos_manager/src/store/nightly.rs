@@ -0,0 +1,68 @@
+use super::with_db;
+use crate::reports::{ApReport, SiteReport};
+use anyhow::Result;
+use rocket::serde::json;
+use rusqlite::{params, Connection};
+
+/// Adds a nightly report run to the history. Nothing is discarded.
+pub fn add_nightly_report(date: &str, ap: &ApReport, site: &SiteReport) -> Result<()> {
+    with_db(|conn| insert_nightly_report(conn, date, ap, site))
+}
+
+pub(super) fn insert_nightly_report(
+    conn: &Connection,
+    date: &str,
+    ap: &ApReport,
+    site: &SiteReport,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO nightly_reports (date, access_points, sites) VALUES (?1, ?2, ?3)",
+        params![date, json::to_string(ap)?, json::to_string(site)?],
+    )?;
+    Ok(())
+}
+
+/// The most recent `limit` nightly report runs, oldest first.
+pub fn recent_nightly_reports(limit: usize) -> Result<Vec<(String, ApReport, SiteReport)>> {
+    with_db(|conn| select_nightly_reports(conn, limit as i64))
+}
+
+/// Every nightly report run, oldest first.
+pub fn all_nightly_reports() -> Result<Vec<(String, ApReport, SiteReport)>> {
+    // SQLite treats a negative limit as no limit.
+    with_db(|conn| select_nightly_reports(conn, -1))
+}
+
+fn select_nightly_reports(
+    conn: &Connection,
+    limit: i64,
+) -> Result<Vec<(String, ApReport, SiteReport)>> {
+    let mut query = conn.prepare(
+        "SELECT date, access_points, sites FROM nightly_reports ORDER BY date DESC LIMIT ?1",
+    )?;
+    let mut reports = Vec::new();
+    for row in query.query_map([limit], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })? {
+        let (date, ap, site) = row?;
+        reports.push((date, json::from_str(&ap)?, json::from_str(&site)?));
+    }
+    reports.reverse();
+    Ok(reports)
+}
+
+/// Replaces the whole nightly report history.
+pub(super) fn replace_nightly_reports(
+    conn: &Connection,
+    reports: &[(String, ApReport, SiteReport)],
+) -> Result<()> {
+    conn.execute("DELETE FROM nightly_reports", [])?;
+    for (date, ap, site) in reports.iter() {
+        insert_nightly_report(conn, date, ap, site)?;
+    }
+    Ok(())
+}
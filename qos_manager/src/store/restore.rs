@@ -0,0 +1,22 @@
+use super::{replace_limits, replace_nightly_reports, replace_trees, with_db};
+use crate::reports::{ApReport, SiteReport};
+use anyhow::Result;
+use shared_rest::{QueueTreeEntry, ShaperTreeConfig};
+use std::collections::BTreeMap;
+
+/// Replaces the speed limits, trees and nightly history in one transaction,
+/// so that a failed restore leaves the database as it was.
+pub fn restore_state(
+    limits: &ShaperTreeConfig,
+    trees: &BTreeMap<String, Vec<QueueTreeEntry>>,
+    nightly: &[(String, ApReport, SiteReport)],
+) -> Result<()> {
+    with_db(|conn| {
+        let tx = conn.transaction()?;
+        replace_limits(&tx, limits)?;
+        replace_trees(&tx, trees)?;
+        replace_nightly_reports(&tx, nightly)?;
+        tx.commit()?;
+        Ok(())
+    })
+}
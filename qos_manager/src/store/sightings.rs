@@ -0,0 +1,92 @@
+use super::with_db;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rocket::serde::Serialize;
+use rusqlite::params;
+
+/// Kinds of thing that shapers report seeing.
+#[derive(Clone, Copy)]
+pub enum SightingKind {
+    /// An address passing traffic that isn't mapped to a queue.
+    UnknownIp,
+    /// An address found on more than one client in UISP.
+    DuplicateIp,
+    /// A UISP client that couldn't be placed in the tree.
+    UnmappedClient,
+}
+
+impl SightingKind {
+    fn name(&self) -> &'static str {
+        match self {
+            SightingKind::UnknownIp => "unknown_ip",
+            SightingKind::DuplicateIp => "duplicate_ip",
+            SightingKind::UnmappedClient => "unmapped_client",
+        }
+    }
+}
+
+/// When something was first and last reported by a shaper.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Sighting {
+    pub value: String,
+    pub shaper_id: String,
+    /// RFC 3339.
+    pub first_seen: String,
+    pub last_seen: String,
+    /// Still in the shaper's latest report.
+    pub active: bool,
+}
+
+/// Notes that a shaper reported seeing `values`. With `complete`, the
+/// report is the shaper's whole list, and anything not in it is marked
+/// inactive.
+pub fn record_sightings(
+    kind: SightingKind,
+    shaper_id: &str,
+    values: &[String],
+    complete: bool,
+) -> Result<()> {
+    let now = Local::now().to_rfc3339();
+    with_db(|conn| {
+        let tx = conn.transaction()?;
+        if complete {
+            tx.execute(
+                "UPDATE sightings SET active = 0 WHERE kind = ?1 AND shaper_id = ?2",
+                params![kind.name(), shaper_id],
+            )?;
+        }
+        for value in values.iter() {
+            tx.execute(
+                "INSERT INTO sightings (kind, value, shaper_id, first_seen, last_seen, active)
+                 VALUES (?1, ?2, ?3, ?4, ?4, 1)
+                 ON CONFLICT (kind, value, shaper_id)
+                 DO UPDATE SET last_seen = ?4, active = 1",
+                params![kind.name(), value, shaper_id, now],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    })
+}
+
+/// Everything of a kind seen since `since` (or ever), most recent first.
+pub fn sightings(kind: SightingKind, since: Option<DateTime<Local>>) -> Result<Vec<Sighting>> {
+    let since = since.map(|s| s.to_rfc3339()).unwrap_or_default();
+    with_db(|conn| {
+        let mut query = conn.prepare(
+            "SELECT value, shaper_id, first_seen, last_seen, active FROM sightings
+             WHERE kind = ?1 AND last_seen >= ?2 ORDER BY last_seen DESC",
+        )?;
+        let rows = query.query_map(params![kind.name(), since], |row| {
+            Ok(Sighting {
+                value: row.get(0)?,
+                shaper_id: row.get(1)?,
+                first_seen: row.get(2)?,
+                last_seen: row.get(3)?,
+                active: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    })
+}
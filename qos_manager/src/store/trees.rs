@@ -0,0 +1,55 @@
+use super::with_db;
+use anyhow::Result;
+use chrono::Local;
+use rocket::serde::json;
+use rusqlite::{params, Connection};
+use shared_rest::QueueTreeEntry;
+use std::collections::BTreeMap;
+
+/// The most recent queue tree from each shaper.
+pub fn load_trees() -> Result<BTreeMap<String, Vec<QueueTreeEntry>>> {
+    with_db(|conn| {
+        let mut query = conn.prepare("SELECT shaper_id, tree FROM shaper_trees")?;
+        let mut trees = BTreeMap::new();
+        for row in query.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })? {
+            let (shaper_id, tree) = row?;
+            trees.insert(shaper_id, json::from_str(&tree)?);
+        }
+        Ok(trees)
+    })
+}
+
+/// Stores a shaper's latest queue tree, replacing the one before.
+pub fn save_tree(shaper_id: &str, tree: &[QueueTreeEntry]) -> Result<()> {
+    with_db(|conn| replace_tree(conn, shaper_id, tree))
+}
+
+/// Replaces every stored tree.
+pub(super) fn replace_trees(
+    conn: &Connection,
+    trees: &BTreeMap<String, Vec<QueueTreeEntry>>,
+) -> Result<()> {
+    conn.execute("DELETE FROM shaper_trees", [])?;
+    for (shaper_id, tree) in trees.iter() {
+        replace_tree(conn, shaper_id, tree)?;
+    }
+    Ok(())
+}
+
+pub(super) fn replace_tree(
+    conn: &Connection,
+    shaper_id: &str,
+    tree: &[QueueTreeEntry],
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO shaper_trees (shaper_id, received, tree) VALUES (?1, ?2, ?3)",
+        params![
+            shaper_id,
+            Local::now().to_rfc3339(),
+            json::to_string(&tree)?
+        ],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use rocket::serde::{Deserialize, Serialize};
+use shared_rest::{NodeKind, QueueTreeEntry};
+use std::collections::{BTreeSet, HashMap};
+
+/// How a node changed between two versions of a shaper's tree.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub enum ChangeKind {
+    Added {
+        parent_id: Option<String>,
+        parent_name: Option<String>,
+    },
+    Removed {
+        parent_id: Option<String>,
+        parent_name: Option<String>,
+    },
+    /// Moved to a different parent (e.g. a client moving APs).
+    Reparented {
+        from_id: Option<String>,
+        from_name: Option<String>,
+        to_id: Option<String>,
+        to_name: Option<String>,
+    },
+    /// The speed limits or the plan (shaping profile) changed.
+    SpeedChanged {
+        old_down_mbps: u32,
+        old_up_mbps: u32,
+        new_down_mbps: u32,
+        new_up_mbps: u32,
+        old_plan: Option<String>,
+        new_plan: Option<String>,
+        /// The manager user who set a new limit for the node, if one did.
+        changed_by: Option<String>,
+    },
+    IpChanged {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    Renamed {
+        old_name: String,
+    },
+}
+
+/// One change to one node.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NodeChange {
+    pub node_id: String,
+    pub node_name: String,
+    pub level_type: NodeKind,
+    pub change: ChangeKind,
+}
+
+fn parent_of(tree: &[QueueTreeEntry], node: &QueueTreeEntry) -> (Option<String>, Option<String>) {
+    match node.parent.and_then(|p| tree.get(p)) {
+        Some(parent) => (Some(parent.id.clone()), Some(parent.name.clone())),
+        None => (None, None),
+    }
+}
+
+/// Lists the changes between two versions of a shaper's tree, matching nodes
+/// by ID. Nodes are listed in tree order; removed nodes come last.
+pub fn diff_trees(previous: &[QueueTreeEntry], current: &[QueueTreeEntry]) -> Vec<NodeChange> {
+    let previous_nodes: HashMap<&str, &QueueTreeEntry> =
+        previous.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut changes = Vec::new();
+    let mut push = |node: &QueueTreeEntry, change: ChangeKind| {
+        changes.push(NodeChange {
+            node_id: node.id.clone(),
+            node_name: node.name.clone(),
+            level_type: node.level_type,
+            change,
+        })
+    };
+
+    for node in current.iter() {
+        let old = match previous_nodes.get(node.id.as_str()) {
+            Some(old) => *old,
+            None => {
+                let (parent_id, parent_name) = parent_of(current, node);
+                push(
+                    node,
+                    ChangeKind::Added {
+                        parent_id,
+                        parent_name,
+                    },
+                );
+                continue;
+            }
+        };
+        if old.name != node.name {
+            push(
+                node,
+                ChangeKind::Renamed {
+                    old_name: old.name.clone(),
+                },
+            );
+        }
+        let (from_id, from_name) = parent_of(previous, old);
+        let (to_id, to_name) = parent_of(current, node);
+        if from_id != to_id {
+            push(
+                node,
+                ChangeKind::Reparented {
+                    from_id,
+                    from_name,
+                    to_id,
+                    to_name,
+                },
+            );
+        }
+        if old.down_mbps != node.down_mbps
+            || old.up_mbps != node.up_mbps
+            || old.metadata.shaping_profile != node.metadata.shaping_profile
+        {
+            push(
+                node,
+                ChangeKind::SpeedChanged {
+                    old_down_mbps: old.down_mbps,
+                    old_up_mbps: old.up_mbps,
+                    new_down_mbps: node.down_mbps,
+                    new_up_mbps: node.up_mbps,
+                    old_plan: old.metadata.shaping_profile.clone(),
+                    new_plan: node.metadata.shaping_profile.clone(),
+                    changed_by: None,
+                },
+            );
+        }
+        if old.ip_addresses != node.ip_addresses {
+            let old_ips: BTreeSet<&String> = old.ip_addresses.iter().collect();
+            let new_ips: BTreeSet<&String> = node.ip_addresses.iter().collect();
+            push(
+                node,
+                ChangeKind::IpChanged {
+                    added: new_ips
+                        .difference(&old_ips)
+                        .map(|ip| ip.to_string())
+                        .collect(),
+                    removed: old_ips
+                        .difference(&new_ips)
+                        .map(|ip| ip.to_string())
+                        .collect(),
+                },
+            );
+        }
+    }
+
+    let current_ids: BTreeSet<&str> = current.iter().map(|n| n.id.as_str()).collect();
+    for old in previous
+        .iter()
+        .filter(|n| !current_ids.contains(n.id.as_str()))
+    {
+        let (parent_id, parent_name) = parent_of(previous, old);
+        push(
+            old,
+            ChangeKind::Removed {
+                parent_id,
+                parent_name,
+            },
+        );
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff_trees, ChangeKind};
+    use shared_rest::{NodeKind, NodeMetadata, QueueTreeEntry};
+
+    fn node(id: &str, level_type: NodeKind, parent: Option<usize>, mbps: u32) -> QueueTreeEntry {
+        QueueTreeEntry {
+            name: id.to_uppercase(),
+            id: id.to_string(),
+            level_type,
+            parent,
+            down_mbps: mbps,
+            up_mbps: mbps,
+            ip_addresses: Default::default(),
+            metadata: NodeMetadata::default(),
+        }
+    }
+
+    /// A root with two access points, and clients `c1` and `c2` on the
+    /// first and `c3` on the second.
+    fn previous() -> Vec<QueueTreeEntry> {
+        let mut tree = vec![
+            node("root", NodeKind::Root, None, 1000),
+            node("ap1", NodeKind::AccessPoint, Some(0), 500),
+            node("ap2", NodeKind::AccessPoint, Some(0), 500),
+            node("c1", NodeKind::Client, Some(1), 10),
+            node("c2", NodeKind::Client, Some(1), 10),
+            node("c3", NodeKind::Client, Some(2), 10),
+        ];
+        tree[4].ip_addresses.insert("100.64.0.2".to_string());
+        tree
+    }
+
+    #[test]
+    fn identical_trees_have_no_changes() {
+        assert!(diff_trees(&previous(), &previous()).is_empty());
+    }
+
+    #[test]
+    fn added_removed_moved_and_changed_nodes() {
+        let mut current = previous();
+        // c1 moves to ap2, c2 gets a faster plan and a new address, c3
+        // leaves and c4 joins ap1.
+        current[3].parent = Some(2);
+        current[4].down_mbps = 25;
+        current[4].up_mbps = 5;
+        current[4].metadata.shaping_profile = Some("Gold".to_string());
+        current[4].ip_addresses.clear();
+        current[4].ip_addresses.insert("100.64.0.20".to_string());
+        current[5] = node("c4", NodeKind::Client, Some(1), 10);
+
+        let changes = diff_trees(&previous(), &current);
+        let summary: Vec<(&str, &ChangeKind)> = changes
+            .iter()
+            .map(|c| (c.node_id.as_str(), &c.change))
+            .collect();
+        assert_eq!(summary.len(), 5, "{changes:?}");
+
+        assert!(matches!(
+            summary[0],
+            ("c1", ChangeKind::Reparented { from_id: Some(from), to_id: Some(to), .. })
+                if from == "ap1" && to == "ap2"
+        ));
+        assert!(matches!(
+            summary[1],
+            (
+                "c2",
+                ChangeKind::SpeedChanged {
+                    old_down_mbps: 10,
+                    old_up_mbps: 10,
+                    new_down_mbps: 25,
+                    new_up_mbps: 5,
+                    old_plan: None,
+                    new_plan: Some(plan),
+                    changed_by: None,
+                }
+            ) if plan == "Gold"
+        ));
+        assert!(matches!(
+            summary[2],
+            ("c2", ChangeKind::IpChanged { added, removed })
+                if added == &["100.64.0.20"] && removed == &["100.64.0.2"]
+        ));
+        assert!(matches!(
+            summary[3],
+            ("c4", ChangeKind::Added { parent_id: Some(parent), .. }) if parent == "ap1"
+        ));
+        assert!(matches!(
+            summary[4],
+            ("c3", ChangeKind::Removed { parent_id: Some(parent), .. }) if parent == "ap2"
+        ));
+    }
+
+    #[test]
+    fn renamed_nodes_are_reported() {
+        let mut current = previous();
+        current[1].name = "North AP".to_string();
+        let changes = diff_trees(&previous(), &current);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].node_name, "North AP");
+        assert!(matches!(
+            &changes[0].change,
+            ChangeKind::Renamed { old_name } if old_name == "AP1"
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+use super::{diff_trees, ChangeKind, NodeChange};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use lazy_static::*;
+use parking_lot::RwLock;
+use rocket::serde::{Deserialize, Serialize};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use shared_rest::QueueTreeEntry;
+
+const HISTORY_FILENAME: &str = "topology_history.ron";
+
+/// Versions older than this are forgotten.
+const HISTORY_RETENTION_DAYS: i64 = 365;
+
+/// Limit changes older than this aren't matched to speed changes.
+const LIMIT_EDIT_RETENTION_DAYS: i64 = 7;
+
+/// A tree received from a shaper that differed from the one before it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct TreeVersion {
+    pub version: u64,
+    pub shaper_id: String,
+    /// When the tree was received (RFC 3339).
+    pub received: String,
+    pub node_count: usize,
+    pub changes: Vec<NodeChange>,
+}
+
+/// A version without its changes, for listing.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct TreeVersionSummary {
+    pub version: u64,
+    pub shaper_id: String,
+    pub received: String,
+    pub node_count: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub reparented: usize,
+    pub speed_changed: usize,
+    pub ip_changed: usize,
+    pub renamed: usize,
+}
+
+/// A change to one node, and the version it arrived in.
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct NodeHistoryEntry {
+    pub version: u64,
+    pub shaper_id: String,
+    pub received: String,
+    pub change: ChangeKind,
+}
+
+/// A speed limit set through the manager, waiting to be matched with the
+/// tree change it causes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+struct LimitEdit {
+    id: String,
+    username: String,
+    time: String,
+    down_mbps: u32,
+    up_mbps: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "rocket::serde")]
+struct TopologyHistory {
+    next_version: u64,
+    versions: Vec<TreeVersion>,
+    #[serde(default)]
+    limit_edits: Vec<LimitEdit>,
+}
+
+lazy_static! {
+    static ref HISTORY: RwLock<TopologyHistory> = RwLock::new(TopologyHistory::default());
+}
+
+/// Loads `topology_history.ron`, if there is one.
+pub fn load_topology_history() -> Result<()> {
+    let data = std::fs::read_to_string(HISTORY_FILENAME)?;
+    let history: TopologyHistory = ron::from_str(&data)?;
+    *HISTORY.write() = history;
+    Ok(())
+}
+
+fn save_history(history: &TopologyHistory) -> Result<()> {
+    let ron = to_string_pretty(history, PrettyConfig::new())?;
+    std::fs::write(HISTORY_FILENAME, ron)?;
+    Ok(())
+}
+
+fn is_recent(time: &str, days: i64) -> bool {
+    let forget_before = Local::now() - chrono::Duration::days(days);
+    DateTime::parse_from_rfc3339(time)
+        .map(|t| t > forget_before)
+        .unwrap_or(false)
+}
+
+/// Notes a speed limit set by a manager user, so that the resulting change
+/// can be attributed to them.
+pub fn record_limit_edit(id: &str, username: &str, down_mbps: u32, up_mbps: u32) {
+    let mut history = HISTORY.write();
+    history.limit_edits.retain(|e| e.id != id);
+    history.limit_edits.push(LimitEdit {
+        id: id.to_string(),
+        username: username.to_string(),
+        time: Local::now().to_rfc3339(),
+        down_mbps,
+        up_mbps,
+    });
+    if let Err(e) = save_history(&history) {
+        println!("Unable to save {HISTORY_FILENAME}: {e:?}");
+    }
+}
+
+/// Compares a newly received tree with the shaper's previous tree, and
+/// stores the differences as a new version. The first tree from a shaper is
+/// stored with every node added.
+pub fn record_tree(
+    shaper_id: &str,
+    previous: Option<&[QueueTreeEntry]>,
+    current: &[QueueTreeEntry],
+) {
+    let mut changes = diff_trees(previous.unwrap_or_default(), current);
+    if changes.is_empty() {
+        return;
+    }
+
+    let mut history = HISTORY.write();
+    history
+        .limit_edits
+        .retain(|e| is_recent(&e.time, LIMIT_EDIT_RETENTION_DAYS));
+    for change in changes.iter_mut() {
+        if let ChangeKind::SpeedChanged {
+            new_down_mbps,
+            new_up_mbps,
+            changed_by,
+            ..
+        } = &mut change.change
+        {
+            // Limits are capped by the node's parents, so the new speed may
+            // be lower than the limit that was set.
+            if let Some(i) = history.limit_edits.iter().position(|e| {
+                e.id == change.node_id && *new_down_mbps <= e.down_mbps && *new_up_mbps <= e.up_mbps
+            }) {
+                *changed_by = Some(history.limit_edits.remove(i).username);
+            }
+        }
+    }
+
+    let version = history.next_version;
+    history.next_version += 1;
+    history.versions.push(TreeVersion {
+        version,
+        shaper_id: shaper_id.to_string(),
+        received: Local::now().to_rfc3339(),
+        node_count: current.len(),
+        changes,
+    });
+    history
+        .versions
+        .retain(|v| is_recent(&v.received, HISTORY_RETENTION_DAYS));
+    if let Err(e) = save_history(&history) {
+        println!("Unable to save {HISTORY_FILENAME}: {e:?}");
+    }
+}
+
+/// Every stored version, newest first.
+pub fn tree_versions() -> Vec<TreeVersionSummary> {
+    let history = HISTORY.read();
+    history
+        .versions
+        .iter()
+        .rev()
+        .map(|v| {
+            let count =
+                |f: fn(&ChangeKind) -> bool| v.changes.iter().filter(|c| f(&c.change)).count();
+            TreeVersionSummary {
+                version: v.version,
+                shaper_id: v.shaper_id.clone(),
+                received: v.received.clone(),
+                node_count: v.node_count,
+                added: count(|c| matches!(c, ChangeKind::Added { .. })),
+                removed: count(|c| matches!(c, ChangeKind::Removed { .. })),
+                reparented: count(|c| matches!(c, ChangeKind::Reparented { .. })),
+                speed_changed: count(|c| matches!(c, ChangeKind::SpeedChanged { .. })),
+                ip_changed: count(|c| matches!(c, ChangeKind::IpChanged { .. })),
+                renamed: count(|c| matches!(c, ChangeKind::Renamed { .. })),
+            }
+        })
+        .collect()
+}
+
+/// One version, with its changes.
+pub fn tree_version(version: u64) -> Option<TreeVersion> {
+    HISTORY
+        .read()
+        .versions
+        .iter()
+        .find(|v| v.version == version)
+        .cloned()
+}
+
+/// Every change to one node, newest first. With `plans_only`, just the
+/// speed and plan changes.
+pub fn node_history(id: &str, plans_only: bool) -> Vec<NodeHistoryEntry> {
+    let history = HISTORY.read();
+    let mut result = Vec::new();
+    for v in history.versions.iter().rev() {
+        for change in v.changes.iter().filter(|c| c.node_id == id) {
+            if plans_only && !matches!(change.change, ChangeKind::SpeedChanged { .. }) {
+                continue;
+            }
+            result.push(NodeHistoryEntry {
+                version: v.version,
+                shaper_id: v.shaper_id.clone(),
+                received: v.received.clone(),
+                change: change.change.clone(),
+            });
+        }
+    }
+    result
+}
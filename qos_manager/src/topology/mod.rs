@@ -0,0 +1,9 @@
+//! Keeps a history of queue tree changes: each tree a shaper sends is
+//! compared with the one before it, and the differences (nodes added,
+//! removed, moved, re-limited or re-addressed) are stored as a new version
+//! in `topology_history.ron`.
+
+mod diff;
+pub use diff::*;
+mod history;
+pub use history::*;
@@ -1,5 +1,51 @@
 var masterGraphList = [];
 
+// The finest interval (seconds) at which shapers report statistics. If it's
+// under a minute, short time ranges are graphed with finer windows.
+var statsResolution = 60;
+$.get("/query/graph_settings", (settings) => {
+    if (settings.stats_resolution_secs != statsResolution) {
+        statsResolution = settings.stats_resolution_secs;
+        masterGraphList.forEach((graph) => {
+            graph.setPeriod(graph.periodName);
+            graph.render();
+        });
+    }
+});
+
+// Queue rebuilds, drawn as markers on bandwidth and latency graphs.
+var rebuildEvents = [];
+$.get("/query/rebuilds", (rebuilds) => {
+    rebuildEvents = rebuilds;
+    masterGraphList.forEach((graph) => {
+        if (graph.showRebuilds) {
+            try {
+                Plotly.relayout(graph.graph_div, { shapes: rebuildMarkers() });
+            } catch (e) {
+                // Not plotted yet; it will pick the markers up when it is.
+            }
+        }
+    });
+});
+
+function rebuildMarkers() {
+    let shapes = [];
+    for (let i = 0; i < rebuildEvents.length; i++) {
+        let when = toLocalTime(rebuildEvents[i].started);
+        shapes.push({
+            type: 'line',
+            xref: 'x',
+            yref: 'paper',
+            x0: when,
+            x1: when,
+            y0: 0,
+            y1: 1,
+            line: { color: rebuildEvents[i].error == null ? 'orange' : 'red', width: 1, dash: 'dot' },
+        });
+    }
+    return shapes;
+}
+
 function toLocalTime(d) {
     let tzOffset = new Date().getTimezoneOffset() / 60;
     let date = new Date(d);
@@ -73,6 +119,7 @@ class TimeGraph {
     }
 
     setPeriod(period) {
+        this.periodName = period;
         switch (period) {
             case "5m": { this.range = "start: -5m"; this.period = "1m"; break; }
             case "15m": { this.range = "start: -15m"; this.period = "1m"; break; }
@@ -88,6 +135,12 @@ class TimeGraph {
             case "1y": { this.range = "start: -365d"; this.period = "24h"; break; }
             default: { this.range = "start: -1h"; this.period = "1m"; }
         }
+        let fineRanges = { "5m": 300, "15m": 900, "30m": 1800, "1h": 3600, "2h": 7200 };
+        if (statsResolution < 60 && period in fineRanges) {
+            // Aim for about 180 points, but no finer than the data.
+            let seconds = Math.max(statsResolution, Math.ceil(fineRanges[period] / 180));
+            this.period = seconds + "s";
+        }
     }
 
     render() {
@@ -97,10 +150,15 @@ class TimeGraph {
             let tzOffset = new Date().getTimezoneOffset() / 60;
             //start.setHours(start.getHours() - tzOffset);
             //end.setHours(end.getHours() - tzOffset);
+            if (statsResolution < 60) {
+                let seconds = Math.ceil((end - start) / 1000 / 180);
+                this.period = Math.min(60, Math.max(statsResolution, seconds)) + "s";
+            } else {
+                this.period = "1m";
+            }
             start = start.toISOString();
             end = end.toISOString();
             this.range = "start: " + start + ", stop: " + end;
-            this.period = "1m";
             this.render();
         });
     }
@@ -109,20 +167,35 @@ class TimeGraph {
 class LatencyGraph extends TimeGraph {
     constructor(div, site, period) {
         super(div, site, period);
+        this.showRebuilds = true;
+        // Sites and access points only have their clients' percentiles,
+        // averaged; clients set this to false.
+        this.averagesClients = true;
+        this.source = "pping";
     }
 
     render() {
         let range = encodeURIComponent(this.range);
         let period = encodeURIComponent(this.period);
-        $.get("/query/latency_site/" + this.site + "/" + range + "/" + period, (data) => {
+        let suffix = this.averagesClients ? " (average of clients)" : "";
+        let source = encodeURIComponent(this.source);
+        $.get("/query/latency_site/" + this.site + "/" + range + "/" + period + "?source=" + source, (data) => {
             console.log(data.length);
             let latency = {
                 x: [],
                 y: [],
                 type: 'scatter',
-                name: "Average",
+                name: "Median" + suffix,
                 mode: "lines",
             };
+            let latencyP95 = {
+                x: [],
+                y: [],
+                type: 'scatter',
+                name: "95th Percentile" + suffix,
+                mode: "lines",
+                line: {dash: "dot"}
+            };
             let latencyMin = {
                 x: [],
                 y: [],
@@ -145,7 +218,9 @@ class LatencyGraph extends TimeGraph {
             };
             for (let i = 0; i < data.length; i++) {
                 latency.x.push(toLocalTime(data[i].date));
-                latency.y.push(data[i].avg);
+                latency.y.push(data[i].median);
+                latencyP95.x.push(toLocalTime(data[i].date));
+                latencyP95.y.push(data[i].p95);
                 latencyMin.x.push(toLocalTime(data[i].date));
                 latencyMin.y.push(data[i].min);
                 latencyMax.x.push(toLocalTime(data[i].date));
@@ -171,10 +246,11 @@ class LatencyGraph extends TimeGraph {
                     title: "Date/Time",
                     automargin: true,
                     titlefont: { size: 10 },
-                }
+                },
+                shapes: rebuildMarkers(),
             }
             let buttonsToRemove = ["zoom2d", "pan2d", "select2d", "lasso2d", "zoomIn2d", "zoomOut2d", "autoScale2d", "resetScale2d"];
-            Plotly.newPlot(bw, [latency, latencyMax, latencyMin], layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
+            Plotly.newPlot(bw, [latency, latencyMax, latencyMin, latencyP95], layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
             super.render();
         });
     }
@@ -327,6 +403,7 @@ class SignalGraph extends TimeGraph {
 class BandwidthGraph extends TimeGraph {
     constructor(div, site, period) {
         super(div, site, period);
+        this.showRebuilds = true;
     }
 
     render() {
@@ -376,7 +453,8 @@ class BandwidthGraph extends TimeGraph {
                     title: "Date/Time",
                     automargin: true,
                     titlefont: { size: 10 },
-                }
+                },
+                shapes: rebuildMarkers(),
             }
             let buttonsToRemove = ["zoom2d", "pan2d", "select2d", "lasso2d", "zoomIn2d", "zoomOut2d", "autoScale2d", "resetScale2d"];
             Plotly.newPlot(bw, [up, down], layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
@@ -446,9 +524,181 @@ class DropsGraph extends TimeGraph {
     }
 }
 
+class CakeGraph extends TimeGraph {
+    constructor(div, site, period) {
+        super(div, site, period);
+    }
+
+    render() {
+        let range = encodeURIComponent(this.range);
+        let period = encodeURIComponent(this.period);
+        $.get("/query/site_cake/" + this.site + "/" + range + "/" + period, (data) => {
+            // Delay on the left axis; drops and ECN marks as bars on the right.
+            // Rising delay with marks/drops means bufferbloat is being managed;
+            // drops without delay means the queue is simply full.
+            let series = [
+                { name: 'Down Delay', dir: 'down', key: 'avg_delay_ms', dash: 'solid' },
+                { name: 'Up Delay', dir: 'up', key: 'avg_delay_ms', dash: 'solid' },
+                { name: 'Down Peak Delay', dir: 'down', key: 'peak_delay_ms', dash: 'dot' },
+                { name: 'Up Peak Delay', dir: 'up', key: 'peak_delay_ms', dash: 'dot' },
+            ].map((s) => {
+                return { x: [], y: [], type: 'scatter', name: s.name, line: { dash: s.dash }, dir: s.dir, key: s.key };
+            });
+            let bars = [
+                { name: 'Down Drops', dir: 'down', key: 'drops' },
+                { name: 'Up Drops', dir: 'up', key: 'drops' },
+                { name: 'Down ECN Marks', dir: 'down', key: 'ecn_marks' },
+                { name: 'Up ECN Marks', dir: 'up', key: 'ecn_marks' },
+            ].map((s) => {
+                return { x: [], y: [], type: 'bar', yaxis: 'y2', opacity: 0.5, name: s.name, dir: s.dir, key: s.key };
+            });
+            let traces = series.concat(bars);
+            for (let i = 0; i < data.length; i++) {
+                traces.forEach((t) => {
+                    t.x.push(toLocalTime(data[i].time));
+                    t.y.push(data[i][t.dir][t.key]);
+                });
+            }
+            let graph = document.getElementById(this.graph_div);
+            let layout = {
+                showlegend: false,
+                autosize: true,
+                barmode: 'stack',
+                margin: {
+                    l: 0,
+                    r: 0,
+                    b: 0,
+                    t: 0,
+                    pad: 4
+                },
+                yaxis: {
+                    title: 'Delay (ms)',
+                    automargin: true,
+                    titlefont: { size: 10 },
+                },
+                yaxis2: {
+                    title: 'Drops / Marks',
+                    overlaying: 'y',
+                    side: 'right',
+                    automargin: true,
+                    titlefont: { size: 10 },
+                },
+                xaxis: {
+                    title: "Date/Time",
+                    automargin: true,
+                    titlefont: { size: 10 },
+                }
+            }
+            let buttonsToRemove = ["zoom2d", "pan2d", "select2d", "lasso2d", "zoomIn2d", "zoomOut2d", "autoScale2d", "resetScale2d"];
+            Plotly.newPlot(graph, traces, layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
+            super.render();
+        });
+    }
+}
+
+class ClassStatsGraph extends TimeGraph {
+    constructor(div, site, period) {
+        super(div, site, period);
+    }
+
+    render() {
+        let range = encodeURIComponent(this.range);
+        let period = encodeURIComponent(this.period);
+        $.get("/query/site_class_stats/" + this.site + "/" + range + "/" + period, (data) => {
+            let traces = [
+                { name: 'Down Overlimits', key: 'down_overlimits', dash: 'solid' },
+                { name: 'Up Overlimits', key: 'up_overlimits', dash: 'solid' },
+                { name: 'Down Borrowed', key: 'down_borrowed', dash: 'dot' },
+                { name: 'Up Borrowed', key: 'up_borrowed', dash: 'dot' },
+            ].map((s) => {
+                return { x: [], y: [], type: 'scatter', name: s.name, line: { dash: s.dash }, key: s.key };
+            });
+            for (let i = 0; i < data.length; i++) {
+                traces.forEach((t) => {
+                    t.x.push(toLocalTime(data[i].time));
+                    t.y.push(data[i][t.key]);
+                });
+            }
+            let graph = document.getElementById(this.graph_div);
+            let layout = {
+                showlegend: false,
+                autosize: true,
+                margin: {
+                    l: 0,
+                    r: 0,
+                    b: 0,
+                    t: 0,
+                    pad: 4
+                },
+                yaxis: {
+                    title: 'Events',
+                    automargin: true,
+                    titlefont: { size: 10 },
+                },
+                xaxis: {
+                    title: "Date/Time",
+                    automargin: true,
+                    titlefont: { size: 10 },
+                }
+            }
+            let buttonsToRemove = ["zoom2d", "pan2d", "select2d", "lasso2d", "zoomIn2d", "zoomOut2d", "autoScale2d", "resetScale2d"];
+            Plotly.newPlot(graph, traces, layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
+            super.render();
+        });
+    }
+}
+
+class AvailabilityGraph extends TimeGraph {
+    constructor(div, site, period) {
+        super(div, site, period);
+    }
+
+    render() {
+        let range = encodeURIComponent(this.range);
+        let period = encodeURIComponent(this.period);
+        $.get("/query/reachability/" + this.site + "/" + range + "/" + period, (data) => {
+            let availability = { x: [], y: [], type: 'scatter', name: 'Available', fill: 'tozeroy' };
+            let loss = { x: [], y: [], type: 'scatter', name: 'Packet Loss', line: { dash: 'dot' } };
+            for (let i = 0; i < data.length; i++) {
+                availability.x.push(toLocalTime(data[i].time));
+                availability.y.push(data[i].availability);
+                loss.x.push(toLocalTime(data[i].time));
+                loss.y.push(data[i].loss);
+            }
+            let graph = document.getElementById(this.graph_div);
+            let layout = {
+                showlegend: false,
+                autosize: true,
+                margin: {
+                    l: 0,
+                    r: 0,
+                    b: 0,
+                    t: 0,
+                    pad: 4
+                },
+                yaxis: {
+                    title: '%',
+                    range: [0, 100],
+                    automargin: true,
+                    titlefont: { size: 10 },
+                },
+                xaxis: {
+                    title: "Date/Time",
+                    automargin: true,
+                    titlefont: { size: 10 },
+                }
+            }
+            let buttonsToRemove = ["zoom2d", "pan2d", "select2d", "lasso2d", "zoomIn2d", "zoomOut2d", "autoScale2d", "resetScale2d"];
+            Plotly.newPlot(graph, [availability, loss], layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
+            super.render();
+        });
+    }
+}
+
 class BandwidthGraphWithLimits extends TimeGraph {
     constructor(div, site, period, maxDown, maxUp) {
         super(div, site, period);
+        this.showRebuilds = true;
         this.maxDown = maxDown;
         this.maxUp = maxUp;
     }
@@ -521,7 +771,8 @@ class BandwidthGraphWithLimits extends TimeGraph {
                     title: "Date/Time",
                     automargin: true,
                     titlefont: { size: 10 },
-                }
+                },
+                shapes: rebuildMarkers(),
             }
             let buttonsToRemove = ["zoom2d", "pan2d", "select2d", "lasso2d", "zoomIn2d", "zoomOut2d", "autoScale2d", "resetScale2d"];
             Plotly.newPlot(bw, [up, down, upMax, downMax], layout, { responsive: true, modeBarButtonsToRemove: buttonsToRemove });
@@ -788,8 +1039,8 @@ class FunnelGraphs {
     }
 }
 
-function updateCpu(div) {
-    $.get("/query/cpu_load", (usage) => {
+function updateCpu(div, shaper) {
+    $.get("/query/cpu_load/" + encodeURIComponent(shaper), (usage) => {
         let data = [
             {
                 x: [],
@@ -823,8 +1074,8 @@ function updateCpu(div) {
     });
 }
 
-function updateMemory(div) {
-    $.get("/query/ram_use", (usage) => {
+function updateMemory(div, shaper) {
+    $.get("/query/ram_use/" + encodeURIComponent(shaper), (usage) => {
         let usageN = Number(usage[0].usage);
         let data = [
             {
@@ -855,8 +1106,8 @@ function updateMemory(div) {
     });
 }
 
-function updateSwap(div) {
-    $.get("/query/swap_use", (usage) => {
+function updateSwap(div, shaper) {
+    $.get("/query/swap_use/" + encodeURIComponent(shaper), (usage) => {
         let usageN = Number(usage[0].usage);
         let data = [
             {
@@ -887,16 +1138,28 @@ function updateSwap(div) {
     });
 }
 
+// Node kinds (level_type) shaped as access points, and as clients.
+function isApKind(kind) {
+    return kind == "ap" || kind == "no_ap" || kind == "unmapped";
+}
+
+function isClientKind(kind) {
+    return kind == "client" || kind == "infrastructure";
+}
+
+// The page showing a queue tree node of the given kind.
+function nodePage(kind) {
+    if (isApKind(kind)) return "/access_point/";
+    if (isClientKind(kind)) return "/client/";
+    return "/site/";
+}
+
 function doSearch() {
     let term = $("#SearchBox").val();
     $.post("/query/search", term, (result) => {
         let html = "";
         for (let i = 0; i < result.length; i++) {
-            switch (result[i].rtype) {
-                case "ap" : html += "<a style='margin-right: 12px' href='/access_point/" + result[i].id + "'>" + result[i].name + " (" + result[i].rtype + ")</a>"; break;
-                case "client" : html += "<a style='margin-right: 12px' href='/client/" + result[i].id + "'>" + result[i].name + " (" + result[i].rtype + ")</a>"; break;
-                default: html += "<a style='margin-right: 12px' href='/site/" + result[i].id + "'>" + result[i].name + " (" + result[i].rtype + ")</a>"; break;
-            }
+            html += "<a style='margin-right: 12px' href='" + nodePage(result[i].rtype) + result[i].id + "'>" + result[i].name + " (" + result[i].rtype + ")</a>";
         }
         $("#SearchResult").html(html);
     });
@@ -919,11 +1182,7 @@ function setupBreadcrumbs(siteId) {
         let crumbsR = crumbs.reverse();
         let html = "<nav aria-label='breadcrumb'><ol class='breadcrumb'>";
         for (let i=0; i<crumbsR.length-1; i++) {
-            switch (crumbsR[i].level_type) {
-                case "ap" : html += "<li class='breadcrumb-item'><a href='/access_point/" + crumbsR[i].id + "'>" + crumbsR[i].name + "</a></li>"; break;
-                case "client" : html += "<li class='breadcrumb-item'><a href='/client/" + crumbsR[i].id + "'>" + crumbsR[i].name + "</a></li>"; break;
-                default : html += "<li class='breadcrumb-item'><a href='/site/" + crumbsR[i].id + "'>" + crumbsR[i].name + "</a></li>"; break;
-            }
+            html += "<li class='breadcrumb-item'><a href='" + nodePage(crumbsR[i].level_type) + crumbsR[i].id + "'>" + crumbsR[i].name + "</a></li>";
         }
         let lastCrumb = crumbsR[crumbsR.length-1];
         html += "<li class='breadcrumb-item active' style='font-weight: bold'>" + lastCrumb.name + "</li>";
@@ -933,11 +1192,7 @@ function setupBreadcrumbs(siteId) {
             if (children.length > 0) {
                 html += "<li class='breadcrumb-item'><select id='childList' onchange='selectBreadcrumbChild()'><option value='' style='font-style: italic'>Children...</option>";
                 for (let i=0; i<children.length; i++) {
-                    switch (children[i].level_type) {
-                        case "ap": html += "<option value='/access_point/" + children[i].id + "'>" + children[i].name + "</option>"; break;
-                        case "client": html += "<option value='/client/" + children[i].id + "'>" + children[i].name + "</option>"; break;
-                        default: html += "<option value='/site/" + children[i].id + "'>" + children[i].name + "</option>"; break;
-                    }
+                    html += "<option value='" + nodePage(children[i].level_type) + children[i].id + "'>" + children[i].name + "</option>";
                 }
                 html += "</select></li>";
             }
@@ -979,4 +1234,30 @@ function peakBandwidth(siteId, div) {
             $("#" + div).html(html);
         });
     });
-}
\ No newline at end of file
+}
+
+// Describes a topology change (from /query/topology) in a few words.
+function describeChange(change) {
+    let kind = Object.keys(change)[0];
+    let c = change[kind];
+    let name = (id, name) => id == null ? "(none)" : name;
+    switch (kind) {
+        case "Added": return "added under " + name(c.parent_id, c.parent_name);
+        case "Removed": return "removed from " + name(c.parent_id, c.parent_name);
+        case "Reparented": return "moved from " + name(c.from_id, c.from_name) + " to " + name(c.to_id, c.to_name);
+        case "SpeedChanged": {
+            let text = "speed " + c.old_down_mbps + "/" + c.old_up_mbps + " → " + c.new_down_mbps + "/" + c.new_up_mbps + " Mbps";
+            if (c.old_plan != c.new_plan) text += ", plan " + (c.old_plan || "(none)") + " → " + (c.new_plan || "(none)");
+            if (c.changed_by != null) text += ", set by " + c.changed_by;
+            return text;
+        }
+        case "IpChanged": {
+            let text = "IP addresses";
+            if (c.added.length > 0) text += " added " + c.added.join(", ");
+            if (c.removed.length > 0) text += " removed " + c.removed.join(", ");
+            return text;
+        }
+        case "Renamed": return "renamed from " + c.old_name;
+    }
+    return kind;
+}
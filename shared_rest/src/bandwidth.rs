@@ -5,7 +5,8 @@ use serde::{Deserialize, Serialize};
 /// and displaying graphs,
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BandwidthReport {
-    /// Timestamp of the bandwidth report
+    /// Timestamp of the bandwidth report (RFC 3339). Reports may arrive late,
+    /// if they were spooled while the manager was unreachable.
     pub timestamp: String,
 
     /// List of upload usage
@@ -26,6 +27,25 @@ pub struct BandwidthLine {
 
     /// Number of queue drops in the time period.
     pub drops: u64,
+
+    /// HTB class counters for the time period. Only present for tower and
+    /// access point queues whose class statistics were read; other parent
+    /// lines are the sum of their children.
+    #[serde(default)]
+    pub class_stats: Option<ClassCounters>,
+}
+
+/// HTB class counters for a parent (tower or access point) queue.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ClassCounters {
+    /// Times the class exceeded its rate (and had to borrow or wait).
+    pub overlimits: u64,
+
+    /// Times the class borrowed spare bandwidth from its parent.
+    pub borrowed: u64,
+
+    /// Times the class lent spare bandwidth to its children.
+    pub lended: u64,
 }
 
 impl BandwidthReport {
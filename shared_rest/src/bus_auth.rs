@@ -0,0 +1,65 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HTTP header carrying the time (Unix seconds) at which a bus message was signed.
+pub const BUS_TIMESTAMP_HEADER: &str = "X-Bqos-Timestamp";
+
+/// HTTP header carrying the hex-encoded HMAC-SHA256 signature of a bus message.
+pub const BUS_SIGNATURE_HEADER: &str = "X-Bqos-Signature";
+
+/// HTTP header carrying the `shaper_id` of the daemon that sent a bus message.
+pub const BUS_SHAPER_HEADER: &str = "X-Bqos-Shaper";
+
+/// Signed messages older (or newer) than this many seconds are rejected, to
+/// limit replay of captured traffic.
+pub const BUS_MAX_CLOCK_SKEW: i64 = 300;
+
+fn bus_mac(key: &str, shaper_id: &str, timestamp: i64, path: &str, body: &[u8]) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{timestamp}:{shaper_id}:{path}:").as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// Signs a bus message. The signature covers the timestamp, the sending shaper,
+/// the request path (e.g. `/bus/tree`) and the body, so a captured message can't
+/// be re-sent to a different endpoint or attributed to a different shaper.
+pub fn sign_bus_message(
+    key: &str,
+    shaper_id: &str,
+    timestamp: i64,
+    path: &str,
+    body: &[u8],
+) -> String {
+    hex::encode(
+        bus_mac(key, shaper_id, timestamp, path, body)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Checks a bus message signature against each of the accepted keys. Accepting
+/// more than one key allows a key to be rotated without updating every shaper
+/// at the same moment.
+pub fn verify_bus_message(
+    keys: &[String],
+    shaper_id: &str,
+    timestamp: i64,
+    path: &str,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    let signature = if let Ok(signature) = hex::decode(signature) {
+        signature
+    } else {
+        return false;
+    };
+    keys.iter().filter(|key| !key.is_empty()).any(|key| {
+        bus_mac(key, shaper_id, timestamp, path, body)
+            .verify_slice(&signature)
+            .is_ok()
+    })
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// CAKE statistics for each mapped client queue, sent by `qos_daemon` to
+/// `/bus/cake` alongside each `BandwidthReport`. Needs bus protocol v2.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CakeReport {
+    /// When the statistics were read (RFC 3339).
+    pub timestamp: String,
+
+    /// Queues on the ISP-facing interface.
+    pub download: Vec<CakeLine>,
+
+    /// Queues on the Internet-facing interface.
+    pub upload: Vec<CakeLine>,
+}
+
+/// The CAKE statistics for one queue.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CakeLine {
+    /// Site ID (currently UISP site ID) of the queue.
+    pub site_id: String,
+
+    /// Bytes waiting in the queue when it was read.
+    pub backlog_bytes: u64,
+
+    /// Memory used by the queue's buffers, in bytes.
+    pub memory_used: u64,
+
+    /// Per-tin statistics, in `tc` order (for `diffserv4`: bulk, best effort,
+    /// video, voice).
+    pub tins: Vec<CakeTin>,
+}
+
+/// Statistics for one CAKE tin. Packet, drop and mark counts cover the report
+/// period; delays, backlog and flow counts are as read.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CakeTin {
+    /// Packets sent during the period.
+    pub sent_packets: u64,
+
+    /// Bytes sent during the period.
+    pub sent_bytes: u64,
+
+    /// Packets dropped during the period.
+    pub drops: u64,
+
+    /// Packets ECN-marked (instead of dropped) during the period.
+    pub ecn_marks: u64,
+
+    /// TCP ACKs dropped by the ACK filter during the period.
+    pub ack_drops: u64,
+
+    /// Bytes waiting in this tin.
+    pub backlog_bytes: u64,
+
+    /// Peak queueing delay, in microseconds.
+    pub peak_delay_us: u64,
+
+    /// Average queueing delay, in microseconds.
+    pub avg_delay_us: u64,
+
+    /// Baseline (minimum) queueing delay, in microseconds.
+    pub base_delay_us: u64,
+
+    /// Flows sending little enough to be prioritized.
+    pub sparse_flows: u64,
+
+    /// Flows with a standing queue.
+    pub bulk_flows: u64,
+
+    /// Flows that don't respond to drops or marks.
+    pub unresponsive_flows: u64,
+}
+
+/// Names of the `diffserv4` tins, in `tc` order.
+pub const CAKE_DIFFSERV4_TINS: [&str; 4] = ["bulk", "best_effort", "video", "voice"];
+
+impl CakeReport {
+    /// Create an empty `CakeReport` with a given timestamp.
+    pub fn new(timestamp: String) -> Self {
+        Self {
+            timestamp,
+            download: Vec::new(),
+            upload: Vec::new(),
+        }
+    }
+}
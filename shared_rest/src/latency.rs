@@ -1,12 +1,53 @@
 use serde::{Deserialize, Serialize};
 use std::{collections::HashSet, net::Ipv4Addr};
 
-/// API to send min/max/average latency.
-/// Currently, only average is sent.
-#[derive(Serialize, Deserialize, Debug)]
+/// Latency distribution for a site over one report period, in milliseconds.
+/// Daemons that predate the distribution fields only send `average`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct MinMaxAvg {
     /// The average latency for the report period for this item.
     pub average: f32,
+
+    /// The lowest sample seen.
+    #[serde(default)]
+    pub min: f32,
+
+    /// The median sample.
+    #[serde(default)]
+    pub median: f32,
+
+    /// The 95th percentile sample.
+    #[serde(default)]
+    pub p95: f32,
+
+    /// The highest sample seen.
+    #[serde(default)]
+    pub max: f32,
+
+    /// Number of samples the distribution was built from. Zero means only
+    /// `average` is known.
+    #[serde(default)]
+    pub samples: u32,
+}
+
+impl MinMaxAvg {
+    /// Build a distribution from raw samples. Returns `None` if there are none.
+    pub fn from_samples(samples: &[f32]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let rank = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+        Some(Self {
+            average: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            min: sorted[0],
+            median: rank(0.5),
+            p95: rank(0.95),
+            max: sorted[sorted.len() - 1],
+            samples: sorted.len() as u32,
+        })
+    }
 }
 
 /// A line item in a latency report.
@@ -15,7 +56,7 @@ pub struct LatencyItem {
     /// The site ID (UISP uuid) for which we are reporting
     pub site: String,
 
-    /// A latency average (this used to include min/max as well)
+    /// Latency distribution for the site
     pub latency: MinMaxAvg,
 }
 
@@ -28,6 +69,16 @@ pub struct LatencyReport {
     /// Detected IP addresses that didn't map to the detected
     /// network topology.
     pub unmapped_ip: Vec<String>,
+
+    /// When the report was gathered (RFC 3339). Empty if not known, in which
+    /// case the manager uses the time it was received.
+    #[serde(default)]
+    pub timestamp: String,
+
+    /// The latency source that produced the report (`pping`, `icmp` or `file`).
+    /// Empty from daemons that only supported `pping`.
+    #[serde(default)]
+    pub source: String,
 }
 
 impl LatencyReport {
@@ -36,6 +87,8 @@ impl LatencyReport {
         Self {
             items: Vec::new(),
             unmapped_ip: Vec::new(),
+            timestamp: String::new(),
+            source: String::new(),
         }
     }
 
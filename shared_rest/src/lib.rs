@@ -2,6 +2,10 @@
 
 //! Provides definitions for a shared REST API between the web-manager (`qos_manager`)
 //! and the QoS control daemon (`qos_daemon`) projects.
+//!
+//! Every bus message carries the protocol version it was written with (see `protocol`).
+//! Optional fields must be marked `#[serde(default)]`, so that a daemon and manager on
+//! different releases can still read each other's messages.
 
 mod latency;
 pub use latency::*;
@@ -9,6 +13,12 @@ mod system;
 pub use system::*;
 mod bandwidth;
 pub use bandwidth::*;
+mod cake;
+pub use cake::*;
+mod reachability;
+pub use reachability::*;
+mod rebuild;
+pub use rebuild::*;
 mod duplicate_ips;
 pub use duplicate_ips::*;
 mod site;
@@ -17,3 +27,9 @@ mod tree;
 pub use tree::*;
 mod unmapped;
 pub use unmapped::*;
+mod bus_auth;
+pub use bus_auth::*;
+mod protocol;
+pub use protocol::*;
+mod prometheus;
+pub use prometheus::*;
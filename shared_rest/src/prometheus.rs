@@ -0,0 +1,69 @@
+use std::fmt::Write;
+
+/// The kind of a Prometheus metric.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricType {
+    /// A value that only goes up (until the process restarts).
+    Counter,
+    /// A value that can go up or down.
+    Gauge,
+}
+
+/// Builds a page in the Prometheus text exposition format, as served by the
+/// `/metrics` endpoints of `qos_daemon` and `qos_manager`.
+#[derive(Default)]
+pub struct PrometheusText {
+    text: String,
+}
+
+impl PrometheusText {
+    /// An empty page.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a metric family. Call before adding its samples.
+    pub fn family(&mut self, name: &str, help: &str, kind: MetricType) -> &mut Self {
+        let kind = match kind {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        };
+        let _ = writeln!(self.text, "# HELP {name} {}", help.replace('\n', " "));
+        let _ = writeln!(self.text, "# TYPE {name} {kind}");
+        self
+    }
+
+    /// Adds a sample, with optional labels.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) -> &mut Self {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            self.text.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.text.push(',');
+                }
+                let _ = write!(self.text, "{label}=\"{}\"", escape_label(value));
+            }
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {value}");
+        self
+    }
+
+    /// Adds a metric family holding a single, unlabelled sample.
+    pub fn single(&mut self, name: &str, help: &str, kind: MetricType, value: f64) -> &mut Self {
+        self.family(name, help, kind).sample(name, &[], value)
+    }
+
+    /// The finished page.
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+/// The bus protocol version spoken by this build. Increase it when a change to
+/// the shared types would break an older peer (removing or renaming a field,
+/// changing a field's type or meaning). Adding a field with `#[serde(default)]`
+/// is *not* a breaking change: older peers ignore fields they don't know, and
+/// newer peers fill in the default when a field is missing. Also increase it
+/// when adding a bus route, so that a daemon can tell whether the manager
+/// accepts it.
+///
+/// * v1: the original protocol.
+/// * v2: adds `/bus/cake` (`CakeReport`).
+/// * v3: adds `/bus/reachability` (`ReachabilityReport`).
+/// * v4: adds `/bus/rebuild` (`RebuildEvent`).
+/// * v5: `QueueTreeEntry::level_type` may be `infrastructure`, `unmapped` or
+///   `no_ap`. Older peers use `NodeKind::legacy_name`.
+pub const BUS_PROTOCOL_VERSION: u32 = 5;
+
+/// The oldest bus protocol version this build can still understand.
+pub const BUS_MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// HTTP header carrying the bus protocol version of a message.
+pub const BUS_PROTOCOL_HEADER: &str = "X-Bqos-Protocol";
+
+/// Sent by `qos_daemon` to `/bus/hello` when it starts, to learn which
+/// protocol versions the manager supports.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Hello {
+    /// The daemon's program version.
+    pub daemon_version: String,
+
+    /// The newest protocol version the daemon speaks.
+    pub protocol_version: u32,
+
+    /// The oldest protocol version the daemon speaks.
+    pub min_protocol_version: u32,
+}
+
+/// The manager's reply to a `Hello`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HelloResponse {
+    /// The manager's program version.
+    pub manager_version: String,
+
+    /// The newest protocol version the manager speaks.
+    pub protocol_version: u32,
+
+    /// The oldest protocol version the manager speaks.
+    pub min_protocol_version: u32,
+}
+
+/// Picks the protocol version to use between two peers: the newest version
+/// both of them speak. Returns `None` if they have no version in common.
+pub fn negotiate_protocol(ours: (u32, u32), theirs: (u32, u32)) -> Option<u32> {
+    let (our_min, our_max) = ours;
+    let (their_min, their_max) = theirs;
+    let version = u32::min(our_max, their_max);
+    if version >= our_min && version >= their_min {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{negotiate_protocol, BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION};
+
+    #[test]
+    fn same_versions_use_the_newest() {
+        let ours = (BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION);
+        assert_eq!(negotiate_protocol(ours, ours), Some(BUS_PROTOCOL_VERSION));
+    }
+
+    #[test]
+    fn newest_common_version_is_chosen() {
+        // An older peer.
+        assert_eq!(negotiate_protocol((1, 5), (1, 3)), Some(3));
+        // A newer peer that still speaks our newest version.
+        assert_eq!(negotiate_protocol((1, 5), (2, 7)), Some(5));
+        // Overlapping ranges, from either side.
+        assert_eq!(negotiate_protocol((2, 5), (4, 9)), Some(5));
+        assert_eq!(negotiate_protocol((4, 9), (2, 5)), Some(5));
+    }
+
+    #[test]
+    fn peer_older_than_our_minimum_is_refused() {
+        assert_eq!(negotiate_protocol((3, 5), (1, 2)), None);
+        // A peer from before versions were sent reads as v0.
+        assert_eq!(
+            negotiate_protocol((BUS_MIN_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION), (0, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn peer_newer_than_our_maximum_is_refused() {
+        assert_eq!(negotiate_protocol((1, 5), (6, 8)), None);
+    }
+}
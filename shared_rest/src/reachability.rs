@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// The results of one round of ICMP probes of every client queue's addresses,
+/// sent by `qos_daemon` to `/bus/reachability`. Needs bus protocol v3.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReachabilityReport {
+    /// When the probes finished (RFC 3339).
+    pub timestamp: String,
+
+    /// One line per probed client queue.
+    pub items: Vec<ReachabilityItem>,
+}
+
+/// Probe results for one client (or infrastructure) queue, over all of its
+/// addresses.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReachabilityItem {
+    /// Site ID (currently UISP site ID) of the queue.
+    pub site: String,
+
+    /// Pings sent.
+    pub sent: u32,
+
+    /// Replies received.
+    pub received: u32,
+
+    /// Average round-trip time of the replies (ms), if there were any.
+    pub rtt_ms: Option<f32>,
+}
+
+impl ReachabilityItem {
+    /// A queue is up if any of its addresses answered.
+    pub fn is_up(&self) -> bool {
+        self.received > 0
+    }
+
+    /// The fraction (0 to 1) of pings that went unanswered.
+    pub fn loss(&self) -> f32 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            1.0 - (self.received.min(self.sent) as f32 / self.sent as f32)
+        }
+    }
+}
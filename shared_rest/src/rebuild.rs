@@ -0,0 +1,85 @@
+use crate::QueueTreeEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Why `qos_daemon` rebuilt its queues.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RebuildTrigger {
+    /// The first build after the daemon started.
+    Startup,
+    /// The queue plan built from UISP changed.
+    TopologyChanged,
+    /// The speed limits fetched from the manager changed.
+    LimitsChanged,
+    /// An operator asked for a rebuild (`SIGHUP`).
+    Forced,
+}
+
+/// A queue rebuild, sent by `qos_daemon` to `/bus/rebuild` once it has
+/// finished (or failed). Needs bus protocol v4.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RebuildEvent {
+    /// When the rebuild started (RFC 3339).
+    pub started: String,
+
+    /// When the rebuild finished or failed (RFC 3339).
+    pub ended: String,
+
+    /// How long the rebuild took (ms).
+    pub duration_ms: u64,
+
+    /// Everything that called for the rebuild.
+    pub triggers: Vec<RebuildTrigger>,
+
+    /// Queue tree nodes that weren't in the previous tree. Every node, at startup.
+    pub nodes_added: usize,
+
+    /// Queue tree nodes that were in the previous tree, and aren't now.
+    pub nodes_removed: usize,
+
+    /// Nodes whose name, kind, parent, limits or addresses changed.
+    pub nodes_changed: usize,
+
+    /// Why the rebuild failed, or `None` if it succeeded.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Counts the nodes added to, removed from and changed between two queue
+/// trees, matching nodes by ID. Returns `(added, removed, changed)`.
+pub fn count_tree_changes(
+    previous: &[QueueTreeEntry],
+    current: &[QueueTreeEntry],
+) -> (usize, usize, usize) {
+    let previous_nodes: HashMap<&str, &QueueTreeEntry> =
+        previous.iter().map(|n| (n.id.as_str(), n)).collect();
+    let parent_id = |tree: &[QueueTreeEntry], node: &QueueTreeEntry| {
+        node.parent.and_then(|p| tree.get(p)).map(|p| p.id.clone())
+    };
+
+    let mut added = 0;
+    let mut changed = 0;
+    for node in current.iter() {
+        match previous_nodes.get(node.id.as_str()) {
+            None => added += 1,
+            Some(old) => {
+                if old.name != node.name
+                    || old.level_type != node.level_type
+                    || old.down_mbps != node.down_mbps
+                    || old.up_mbps != node.up_mbps
+                    || old.ip_addresses != node.ip_addresses
+                    || parent_id(previous, old) != parent_id(current, node)
+                {
+                    changed += 1;
+                }
+            }
+        }
+    }
+    let current_ids: HashSet<&str> = current.iter().map(|n| n.id.as_str()).collect();
+    let removed = previous
+        .iter()
+        .filter(|n| !current_ids.contains(n.id.as_str()))
+        .count();
+    (added, removed, changed)
+}
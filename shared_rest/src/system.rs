@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 /// `qos_daemon` periodically gathers system status, and uses this to send it
 /// to the manager.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SystemStatus {
     /// Total memory installed
     pub total_memory: u64,
@@ -18,11 +18,25 @@ pub struct SystemStatus {
 
     /// Vector of CPU usage, by CPU
     pub cpu_usage: Vec<f32>,
+
+    /// When the status was gathered (RFC 3339). Empty if not known, in which
+    /// case the manager uses the time it was received.
+    #[serde(default)]
+    pub timestamp: String,
+
+    /// The `qos_daemon` version. Empty from daemons that predate it.
+    #[serde(default)]
+    pub daemon_version: String,
+
+    /// Seconds since `qos_daemon` started.
+    #[serde(default)]
+    pub uptime_secs: u64,
 }
 
 impl SystemStatus {
     /// Constructor for system status
     pub fn new(
+        timestamp: String,
         total_memory: u64,
         used_memory: u64,
         total_swap: u64,
@@ -35,6 +49,9 @@ impl SystemStatus {
             total_swap,
             used_swap,
             cpu_usage,
+            timestamp,
+            daemon_version: String::new(),
+            uptime_secs: 0,
         }
     }
 }
@@ -14,14 +14,8 @@ pub struct QueueTreeEntry {
     /// The UISP queue ID (site, client site, AP, etc.)
     pub id: String,
 
-    /// A string defining level type. Levels are:
-    /// * `root` (top of the tree)
-    /// * `tower`
-    /// * `ap`
-    /// * `client`
-    /// 
-    /// TODO: Make this into an enum!
-    pub level_type: String,
+    /// The kind of node (tower, access point, client, etc.).
+    pub level_type: NodeKind,
 
     /// Parent in the tree, or `None` if there isn't one. This is an index within the overall vector
     /// of tree entries.
@@ -35,4 +29,102 @@ pub struct QueueTreeEntry {
 
     /// A set of IP Addresses associated with this site.
     pub ip_addresses: HashSet<String>,
+
+    /// Optional details about the node, where they are known.
+    #[serde(default)]
+    pub metadata: NodeMetadata,
+}
+
+/// The kind of a node in the queue tree. These serialize to the strings that
+/// `level_type` held before it was an enum (`root`, `tower`, `ap`, `client`),
+/// so the web interface can keep switching on them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    /// The top of the tree.
+    Root,
+    /// A site with other sites, access points or clients beneath it.
+    Tower,
+    /// An access point, with clients beneath it.
+    #[serde(rename = "ap")]
+    AccessPoint,
+    /// A client site.
+    Client,
+    /// A site's own equipment (radios, switches, etc.), shaped like a client.
+    Infrastructure,
+    /// The placeholder site holding clients that couldn't be placed in the topology.
+    Unmapped,
+    /// The placeholder access point holding a site's clients with no known access point.
+    NoAp,
+}
+
+impl NodeKind {
+    /// Is this node shaped as a client (a CAKE queue with IP addresses attached)?
+    pub fn is_client_queue(&self) -> bool {
+        matches!(self, NodeKind::Client | NodeKind::Infrastructure)
+    }
+
+    /// Is this node shaped as an access point (a queue with clients beneath it)?
+    pub fn is_ap_queue(&self) -> bool {
+        matches!(self, NodeKind::AccessPoint | NodeKind::NoAp | NodeKind::Unmapped)
+    }
+
+    /// The `level_type` that peers speaking bus protocol v4 or older expect.
+    /// They only know `root`, `tower`, `ap` and `client`.
+    pub fn legacy_name(&self) -> &'static str {
+        match self {
+            NodeKind::Root => "root",
+            NodeKind::Tower => "tower",
+            NodeKind::AccessPoint | NodeKind::NoAp | NodeKind::Unmapped => "ap",
+            NodeKind::Client | NodeKind::Infrastructure => "client",
+        }
+    }
+
+    /// The kind of a node from a peer speaking bus protocol v4 or older,
+    /// which marked infrastructure and placeholder nodes only by their names
+    /// and IDs.
+    pub fn from_legacy(level_type: &str, id: &str, name: &str) -> Self {
+        match level_type {
+            "root" => NodeKind::Root,
+            "tower" => NodeKind::Tower,
+            "ap" if id == "666.2" => NodeKind::Unmapped,
+            "ap" if id.ends_with(".2") => NodeKind::NoAp,
+            "ap" => NodeKind::AccessPoint,
+            _ if name.ends_with(" Infrastructure") => NodeKind::Infrastructure,
+            _ => NodeKind::Client,
+        }
+    }
+}
+
+/// Optional details about a queue tree node. Every field may be missing: it
+/// depends upon the node kind, and upon what UISP knows about it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NodeMetadata {
+    /// UISP device IDs at this node (an access point, or a site's devices).
+    #[serde(default)]
+    pub uisp_device_ids: Vec<String>,
+
+    /// The UISP CRM client ID, for client sites.
+    #[serde(default)]
+    pub crm_client_id: Option<String>,
+
+    /// The UISP CRM service ID, for client sites.
+    #[serde(default)]
+    pub crm_service_id: Option<String>,
+
+    /// The shaping profile (CRM service plan name) applied to a client site.
+    #[serde(default)]
+    pub shaping_profile: Option<String>,
+
+    /// The CPU on which the node's queue is shaped.
+    #[serde(default)]
+    pub cpu: Option<u32>,
+
+    /// The `tc` class handle (`major:minor`) of the node's queue.
+    #[serde(default)]
+    pub class_handle: Option<String>,
+
+    /// GPS location, as (latitude, longitude).
+    #[serde(default)]
+    pub gps: Option<(f64, f64)>,
 }